@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkinterface::Message;
+use zkinterface::consumers::workspace::iterate_stream;
+
+fuzz_target!(|data: &[u8]| {
+    for buffer in iterate_stream(data) {
+        let _ = Message::from(&buffer[..]);
+    }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkinterface::Message;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Message::from(data);
+});
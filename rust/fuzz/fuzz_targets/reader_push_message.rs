@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use zkinterface::Reader;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Reader::new();
+    if reader.push_message(data.to_vec()).is_ok() {
+        // Accepted messages must be safe to read.
+        let _ = format!("{:?}", reader);
+        for _ in reader.iter_constraints() {}
+    }
+});
@@ -1,8 +1,12 @@
 extern crate zkinterface;
 
-use zkinterface::{Result, cli::{cli, Options}};
+use zkinterface::cli::{cli, Options};
 use structopt::StructOpt;
+use std::process::exit;
 
-fn main() -> Result<()> {
-    cli(&Options::from_args())
+fn main() {
+    if let Err(err) = cli(&Options::from_args()) {
+        eprintln!("Error: {}", err);
+        exit(1);
+    }
 }
@@ -1,17 +1,32 @@
 extern crate serde;
 extern crate serde_json;
 
-use std::fs::{File, create_dir_all, remove_file};
+use std::fs::{File, create_dir_all, remove_file, read, read_to_string, write};
 use std::io::{stdin, stdout, Read, Write, copy};
 use std::path::{Path, PathBuf};
+use std::fmt;
+use serde::Serialize;
 use structopt::StructOpt;
 use num_bigint::BigUint;
 use num_integer::Integer;
 
-use crate::{Reader, Workspace, Messages, consumers::stats::Stats, Result};
-use crate::consumers::workspace::{list_workspace_files, has_zkif_extension};
+use crate::{Reader, Workspace, MappedWorkspace, WorkspaceSink, Sink, Message, Messages, CircuitHeader, Command, Field, Error, consumers::stats::{Stats, DetailedStats, Breakdown}, Result};
+use crate::consumers::workspace::{list_workspace_files, has_zkif_extension, iterate_stream, MessageOrigin};
+use crate::zkinterface_generated::zkinterface as fb;
 use crate::consumers::validator::Validator;
 use crate::consumers::simulator::Simulator;
+use crate::consumers::solver::Solver;
+use crate::consumers::diff::diff_messages;
+use crate::consumers::fingerprint::Fingerprint;
+use crate::consumers::verifier::verify_message;
+use crate::consumers::lint::{Linter, Severity};
+use crate::consumers::determinism::DeterminismChecker;
+use crate::formats::iden3;
+use crate::formats::text::{read_text, write_text};
+use crate::transformations::optimizer::Optimizer;
+use crate::transformations::linker::{Linker, PartVar};
+use crate::producers::gadget_process::GadgetProcess;
+use crate::producers::workspace::SplitLimits;
 use crate::producers::circuit_generator::{generate_all_metrics_data, generate_some_metrics_data};
 
 const ABOUT: &str = "
@@ -29,13 +44,65 @@ Print a statement in different forms:
     zkif to-yaml workspace
     zkif explain workspace
 
+Create a statement from JSON or YAML, in the format of to-json and to-yaml:
+    zkif from-json statement.json --out workspace
+    zkif from-yaml statement.yaml > statement.zkif
+
+Write a statement in a human-readable text format, and read it back:
+    zkif to-text workspace > statement.txt
+    zkif from-text statement.txt --out workspace
+
 Simulate a proving system:
     zkif stats       workspace
+    zkif stats       --detailed [--format json] workspace
+    zkif stats       --breakdown [--format json] workspace
     zkif validate    workspace
     zkif simulate    workspace
+    zkif simulate    --diagnose --format json workspace
+    zkif simulate    --parallel workspace
+    zkif lint        [--format json] workspace
     zkif fake_prove  workspace
     zkif fake_verify workspace
 
+Prove and verify with Groth16 over BLS12-381 (requires the cargo feature `groth16`):
+    zkif example --field-order 52435875175126190479447740508185965837690552500527637822603658699938581184513 workspace
+    zkif prove  workspace
+    zkif verify workspace
+
+Search for a second witness of the same instance, to find under-constrained private variables:
+    zkif check-determinism workspace
+    zkif check-determinism --trials 1000 --format json workspace
+
+Complete a partial witness:
+    zkif solve workspace
+    zkif solve workspace --out other_workspace
+
+Remove linear constraints by substitution, and write the smaller statement to another workspace:
+    zkif optimize workspace --out optimized_workspace
+
+Write constraints in canonical form (sorted terms, merged duplicates, no zero coefficients):
+    zkif normalize workspace --out normalized_workspace
+
+Compare two statements, after normalization of the constraints:
+    zkif diff workspace other_workspace
+
+Compute a fingerprint of the constraints, independent of how they are split into files:
+    zkif hash workspace
+
+Convert from and to the circom formats .r1cs and .wtns:
+    zkif from-r1cs circuit.r1cs witness.wtns --out workspace
+    zkif to-r1cs workspace --out circom_dir
+
+Call a gadget program with the header of a request as input, and collect its constraints, witness, and response:
+    zkif call-gadget ./my_gadget request_workspace --out gadget_workspace
+
+Split constraints and witnesses into messages of at most 100000 constraints or 64MB, in files of at most 1GB:
+    zkif split workspace --max-items 100000 --max-message-bytes 67108864 --max-file-bytes 1073741824 --out split_workspace
+
+Link statements into one, connecting variable 3 of the first (0) to variable 1 of the second (1):
+    zkif link first_workspace second_workspace --connect 0:3=1:1 --out linked_workspace
+    zkif link first_workspace second_workspace --connect 0:3=1:1 --substitute --out linked_workspace
+
 Write all the statement files to stdout (to pipe to another program):
     zkif cat workspace
 
@@ -66,11 +133,58 @@ pub struct Options {
     ///
     /// explain     Print the content in a human-readable form.
     ///
+    /// from-json   Convert from JSON into the --out workspace, or to stdout.
+    ///
+    /// from-yaml   Convert from YAML into the --out workspace, or to stdout.
+    ///
+    /// to-text     Print the statement in the human-readable text format of `formats::text`.
+    ///
+    /// from-text   Convert from the text format into the --out workspace, or to stdout.
+    ///
     /// validate    Validate the format and semantics of a statement, as seen by a verifier.
     ///
     /// simulate    Simulate a proving system as prover by verifying that the statement is true.
+    ///             With --diagnose, report all unsatisfied constraints.
+    ///             With --parallel, check constraints on all cores once the witness is loaded.
     ///
     /// stats       Calculate statistics about the circuit.
+    ///             With --detailed, add histograms, the most used variables, and memory estimates,
+    ///             as a table or with --format json.
+    ///             With --breakdown, report the constraints and assignments of each file and message.
+    ///
+    /// lint        Report suspicious constraints and variables, such as unconstrained private variables,
+    ///             duplicate constraints, or constraints that always hold, as text or with --format json.
+    ///
+    /// check-determinism
+    ///             Search for alternative witnesses of the same instance during --trials trials,
+    ///             and report the private variables found to be non-unique, as text or with --format json.
+    ///
+    /// solve       Complete a partial witness using the constraints, and write it to witness.zkif.
+    ///
+    /// optimize    Remove linear constraints by substitution, and write the result into the --out workspace.
+    ///
+    /// normalize   Write constraints in canonical form into the --out workspace.
+    ///
+    /// diff        Compare two statements given as two workspaces or files.
+    ///
+    /// hash        Print a stable fingerprint of the constraints.
+    ///
+    /// from-r1cs   Convert circom .r1cs and .wtns files into the --out workspace.
+    ///
+    /// to-r1cs     Convert to circuit.r1cs and witness.wtns, in the --out directory.
+    ///
+    /// call-gadget Run a gadget program given first, on the request header found in the next paths.
+    ///             Write the constraints, witness, and response header into the --out workspace, or to stdout.
+    ///
+    /// split       Write the statement into the --out workspace, with messages and files within the limits
+    ///             given by --max-items, --max-message-bytes, and --max-file-bytes.
+    ///
+    /// link        Link the statements found in each path into one, with fresh variable IDs, into the --out workspace.
+    ///             Connect instance variables with --connect, by equality constraints or with --substitute.
+    ///
+    /// prove       Prove the statement with Groth16 (requires the feature `groth16`).
+    ///
+    /// verify      Verify a Groth16 proof of the statement (requires the feature `groth16`).
     ///
     /// clean       Clean workspace by deleting all *.zkif files in it.
     ///
@@ -91,6 +205,55 @@ pub struct Options {
 
     #[structopt(short, long, default_value = "101")]
     pub field_order: BigUint,
+
+    /// `simulate --diagnose`: check all constraints and report every unsatisfied one.
+    #[structopt(long)]
+    pub diagnose: bool,
+
+    /// `simulate --parallel`: check constraints on all cores. Not available with stdin.
+    #[structopt(long)]
+    pub parallel: bool,
+
+    /// `stats --detailed`: report extended statistics about the constraints.
+    #[structopt(long)]
+    pub detailed: bool,
+
+    /// `stats --breakdown`: report the constraints and assignments of each file and message.
+    #[structopt(long)]
+    pub breakdown: bool,
+
+    /// The format of reports and findings: text or json.
+    #[structopt(long, default_value = "text")]
+    pub format: String,
+
+    /// The workspace where to write the output of tools that produce messages.
+    #[structopt(short, long)]
+    pub out: Option<PathBuf>,
+
+    /// `link --connect A:X=B:Y`: connect the instance variable X of the statement A to the variable Y of the statement B.
+    /// Statements are numbered from 0 in the order of paths.
+    #[structopt(long)]
+    pub connect: Vec<String>,
+
+    /// `link --substitute`: merge connected variables instead of adding equality constraints.
+    #[structopt(long)]
+    pub substitute: bool,
+
+    /// `split --max-items`: the maximum number of constraints or assigned variables per message, or 0.
+    #[structopt(long, default_value = "0")]
+    pub max_items: usize,
+
+    /// `split --max-message-bytes`: the maximum size of a message, or 0.
+    #[structopt(long, default_value = "0")]
+    pub max_message_bytes: usize,
+
+    /// `split --max-file-bytes`: the size of files at which to continue in a new file, or 0 to keep the files of the input.
+    #[structopt(long, default_value = "0")]
+    pub max_file_bytes: u64,
+
+    /// `check-determinism --trials`: the maximum number of alternative witnesses to try.
+    #[structopt(long, default_value = "100")]
+    pub trials: usize,
 }
 
 pub fn cli(options: &Options) -> Result<()> {
@@ -100,12 +263,30 @@ pub fn cli(options: &Options) -> Result<()> {
         "to-json" => main_json(&load_messages(options)?),
         "to-yaml" => main_yaml(&load_messages(options)?),
         "explain" => main_explain(&load_messages(options)?),
-        "validate" => main_validate(&stream_messages(options)?),
-        "simulate" => main_simulate(&stream_messages(options)?),
-        "stats" => main_stats(&stream_messages(options)?),
+        "from-json" => main_import(options, |text| Ok(serde_json::from_str(text)?)),
+        "from-yaml" => main_import(options, |text| Ok(serde_yaml::from_str(text)?)),
+        "to-text" => main_text(&load_messages(options)?),
+        "from-text" => main_import(options, read_text),
+        "call-gadget" => main_call_gadget(options),
+        "validate" => main_validate(options),
+        "simulate" => main_simulate(options),
+        "stats" => main_stats(options),
+        "lint" => main_lint(options, &stream_messages(options)?),
+        "check-determinism" => main_check_determinism(options, &stream_messages(options)?),
+        "solve" => main_solve(options, &stream_messages(options)?),
+        "optimize" => main_optimize(options, &stream_messages(options)?),
+        "normalize" => main_normalize(options, &stream_messages(options)?),
+        "diff" => main_diff(options),
+        "hash" => main_hash(options),
+        "link" => main_link(options),
+        "split" => main_split(options, &stream_messages(options)?),
+        "from-r1cs" => main_from_r1cs(options),
+        "to-r1cs" => main_to_r1cs(options, &load_messages(options)?),
         "clean" => main_clean(options),
         "fake_prove" => main_fake_prove(&load_messages(options)?),
         "fake_verify" => main_fake_verify(&load_messages(options)?),
+        "prove" => main_prove(options),
+        "verify" => main_verify(options),
         "metrics" => main_generate_metrics(options, false),
         "metrics-all" => main_generate_metrics(options, true),
         "help" => {
@@ -116,7 +297,7 @@ pub fn cli(options: &Options) -> Result<()> {
         _ => {
             Options::clap().print_long_help()?;
             eprintln!("\n");
-            Err(format!("Unknown command {}", &options.tool).into())
+            Err(Error::InvalidArgument(format!("Unknown command {}", &options.tool)))
         }
     }
 }
@@ -143,14 +324,32 @@ fn stream_messages(opts: &Options) -> Result<Workspace> {
     Workspace::from_dirs_and_files(&opts.paths)
 }
 
+/// Visit the messages of the workspace without copying them.
+/// Files are memory-mapped. Messages from stdin are read one at a time.
+fn visit_roots(opts: &Options, mut visit: impl FnMut(&MessageOrigin, &fb::Root)) -> Result<()> {
+    if opts.paths == vec![PathBuf::from("-")] {
+        for (index, buffer) in iterate_stream(stdin()).enumerate() {
+            let origin = MessageOrigin { path: None, index };
+            verify_message(&buffer)?;
+            visit(&origin, &fb::get_size_prefixed_root_as_root(&buffer));
+        }
+    } else {
+        let ws = MappedWorkspace::from_dirs_and_files(&opts.paths)?;
+        for res in ws.iter_roots_with_origin() {
+            let (origin, root) = res?;
+            visit(&origin, &root);
+        }
+    }
+    Ok(())
+}
+
 fn field_order_to_maximum(order: &BigUint) -> Result<Vec<u8>> {
     let two = &BigUint::from(2 as u32);
     if order < two
         || two < order && order.is_even() {
-        return Err(format!("Invalid field order {}. Expected a prime modulus (not the field maximum)", order).into());
+        return Err(Error::Field(format!("Invalid field order {}. Expected a prime modulus (not the field maximum)", order)));
     }
-    let field_max = order - 1 as u32;
-    Ok(field_max.to_bytes_le())
+    Ok(Field::from_modulus(order)?.encoded_maximum())
 }
 
 fn main_example(opts: &Options) -> Result<()> {
@@ -159,7 +358,7 @@ fn main_example(opts: &Options) -> Result<()> {
     let field_max = field_order_to_maximum(&opts.field_order)?;
 
     if opts.paths.len() != 1 {
-        return Err("Specify a single directory where to write examples.".into());
+        return Err(Error::InvalidArgument("Specify a single directory where to write examples.".into()));
     }
     let out_dir = &opts.paths[0];
 
@@ -213,30 +412,116 @@ fn main_yaml(reader: &Reader) -> Result<()> {
     Ok(())
 }
 
+fn main_text(reader: &Reader) -> Result<()> {
+    let messages = Messages::from(reader);
+    write_text(&messages, &mut stdout())
+}
+
+fn main_import(opts: &Options, parse: impl Fn(&str) -> Result<Messages>) -> Result<()> {
+    let mut messages = Messages::default();
+    for path in &opts.paths {
+        let mut text = String::new();
+        if path == Path::new("-") {
+            stdin().read_to_string(&mut text)?;
+        } else {
+            File::open(path)?.read_to_string(&mut text)?;
+        }
+        let parsed = parse(&text).map_err(|err| Error::Format(format!("Error parsing {}: {}", path.display(), err)))?;
+        messages.circuit_headers.extend(parsed.circuit_headers);
+        messages.constraint_systems.extend(parsed.constraint_systems);
+        messages.witnesses.extend(parsed.witnesses);
+    }
+
+    write_messages(opts, &messages)
+}
+
+/// Write messages into the --out workspace, or to stdout if --out is not given or is "-".
+fn write_messages(opts: &Options, messages: &Messages) -> Result<()> {
+    match &opts.out {
+        Some(out) if out != Path::new("-") => {
+            let mut sink = WorkspaceSink::new(out)?;
+            messages.push_into(&mut sink)?;
+            eprintln!("Written the statement into {}", out.display());
+        }
+        _ => messages.write_into(&mut stdout())?,
+    }
+    Ok(())
+}
+
+fn main_call_gadget(opts: &Options) -> Result<()> {
+    if opts.paths.len() < 2 {
+        return Err(Error::InvalidArgument("Specify a gadget program, then the workspace or files of the request.".into()));
+    }
+    let request = Messages::from(&Workspace::from_dirs_and_files(&opts.paths[1..])?);
+    let header = request.circuit_headers.first().ok_or(Error::MissingHeader)?;
+    let has_values = header.instance_variables.values.as_ref().is_some_and(|values| !values.is_empty());
+    let command = Command { constraints_generation: true, witness_generation: has_values };
+
+    let gadget = GadgetProcess::new(&opts.paths[0]);
+    let report = |response: &CircuitHeader| eprintln!("The gadget allocated variables up to {}, with outputs {:?}",
+                                                      response.free_variable_id, response.instance_variables.variable_ids);
+
+    match &opts.out {
+        Some(out) if out != Path::new("-") => {
+            // Capture the outputs of the gadget straight into the workspace.
+            let mut sink = WorkspaceSink::new(out)?;
+            let response = gadget.call(&command, header, &mut sink)?;
+            report(&response);
+            sink.push_header(response)?;
+            eprintln!("Written the statement into {}", out.display());
+            Ok(())
+        }
+        _ => {
+            let mut outputs = Messages::default();
+            let response = gadget.call(&command, header, &mut outputs)?;
+            report(&response);
+            outputs.circuit_headers.push(response);
+            outputs.write_into(&mut stdout())
+        }
+    }
+}
+
 fn main_explain(reader: &Reader) -> Result<()> {
     eprintln!("{:?}", reader);
     Ok(())
 }
 
-fn main_validate(ws: &Workspace) -> Result<()> {
+fn main_validate(opts: &Options) -> Result<()> {
     // Validate semantics as verifier.
     let mut validator = Validator::new_as_verifier();
-    for msg in ws.iter_messages() {
-        validator.ingest_message(&msg);
-    }
+    visit_roots(opts, |_, root| validator.ingest_root(root))?;
     print_violations(&validator.get_violations(), "COMPLIANT with the specification")
 }
 
-fn main_simulate(ws: &Workspace) -> Result<()> {
+fn main_simulate(opts: &Options) -> Result<()> {
     // Validate semantics as prover.
     let mut validator = Validator::new_as_prover();
     // Check whether the statement is true.
-    let mut simulator = Simulator::default();
+    let mut simulator = if opts.diagnose { Simulator::new_diagnostic() } else { Simulator::default() };
 
-    // Must validate and simulate in parallel to support stdin.
-    for msg in ws.iter_messages() {
-        validator.ingest_message(&msg);
-        simulator.ingest_message(&msg);
+    if opts.parallel {
+        let ws = MappedWorkspace::from_dirs_and_files(&opts.paths)?;
+        for root in ws.iter_roots() {
+            validator.ingest_root(&root?);
+        }
+        simulator.ingest_workspace_parallel(&ws);
+    } else {
+        // Must validate and simulate in parallel to support stdin.
+        visit_roots(opts, |origin, root| {
+            validator.ingest_root(root);
+            simulator.ingest_root_from(root, origin);
+        })?;
+    }
+
+    if opts.diagnose {
+        match &opts.format[..] {
+            "json" => {
+                serde_json::to_writer_pretty(stdout(), simulator.get_unsatisfied())?;
+                println!();
+            }
+            "text" => {}
+            _ => return Err(Error::InvalidArgument(format!("Unknown format {}", opts.format))),
+        }
     }
 
     let result_val = print_violations(&validator.get_violations(), "COMPLIANT with the specification");
@@ -248,21 +533,274 @@ fn print_violations(errors: &[String], what_it_is_supposed_to_be: &str) -> Resul
     if errors.len() > 0 {
         eprintln!("The statement is NOT {}!", what_it_is_supposed_to_be);
         eprintln!("Violations:\n- {}\n", errors.join("\n- "));
-        Err(format!("Found {} violations.", errors.len()).into())
+        Err(Error::Unsatisfied(format!("Found {} violations.", errors.len())))
     } else {
         eprintln!("The statement is {}!", what_it_is_supposed_to_be);
         Ok(())
     }
 }
 
-fn main_stats(ws: &Workspace) -> Result<()> {
+fn main_stats(opts: &Options) -> Result<()> {
+    if opts.breakdown {
+        let mut breakdown = Breakdown::default();
+        visit_roots(opts, |origin, root| breakdown.ingest_root_from(root, origin))?;
+        return print_report(opts, &breakdown);
+    }
+    if opts.detailed {
+        let mut stats = DetailedStats::default();
+        visit_roots(opts, |_, root| stats.ingest_root(root))?;
+        return print_report(opts, &stats.finish());
+    }
+
     let mut stats = Stats::default();
-    stats.ingest_workspace(ws);
+    visit_roots(opts, |_, root| stats.ingest_root(root))?;
     serde_json::to_writer_pretty(stdout(), &stats)?;
     println!();
     Ok(())
 }
 
+/// Print a report as a table, or as JSON with --format json.
+fn print_report(opts: &Options, report: &(impl Serialize + fmt::Display)) -> Result<()> {
+    match &opts.format[..] {
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), report)?;
+            println!();
+        }
+        "text" => print!("{}", report),
+        _ => return Err(Error::InvalidArgument(format!("Unknown format {}", opts.format))),
+    }
+    Ok(())
+}
+
+fn main_lint(opts: &Options, ws: &Workspace) -> Result<()> {
+    let mut linter = Linter::default();
+    for msg in ws.iter_messages() {
+        linter.ingest_message(&msg)?;
+    }
+    let findings = linter.get_findings();
+
+    match &opts.format[..] {
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &findings)?;
+            println!();
+        }
+        "text" => findings.iter().for_each(|finding| println!("{}", finding)),
+        _ => return Err(Error::InvalidArgument(format!("Unknown format {}", opts.format))),
+    }
+
+    let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    if errors > 0 {
+        Err(Error::Check(format!("Found {} errors in {} findings.", errors, findings.len())))
+    } else {
+        eprintln!("Found {} findings, and no errors.", findings.len());
+        Ok(())
+    }
+}
+
+fn main_check_determinism(opts: &Options, ws: &Workspace) -> Result<()> {
+    let mut checker = DeterminismChecker::default();
+    for msg in ws.iter_messages() {
+        checker.ingest_message(&msg)?;
+    }
+
+    let report = checker.check(opts.trials, &mut rand::thread_rng())?;
+    print_report(opts, &report)?;
+
+    if report.non_unique.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Check(format!("Found {} non-unique private variables.", report.non_unique.len())))
+    }
+}
+
+fn main_solve(opts: &Options, ws: &Workspace) -> Result<()> {
+    let mut solver = Solver::default();
+    for msg in ws.iter_messages() {
+        solver.ingest_message(&msg)?;
+    }
+
+    let solved = solver.solve()?;
+    eprintln!("Solved {} variables.", solved.len());
+
+    let mut sink = WorkspaceSink::new(output_dir(opts)?)?;
+    sink.push_witness(solver.get_witness()?)?;
+    eprintln!("Written {}", sink.workspace.join("witness.zkif").display());
+
+    let unsolved = solver.unsolved();
+    if !unsolved.is_empty() {
+        return Err(Error::Check(format!("Could not solve {} variables: {:?}", unsolved.len(), unsolved)));
+    }
+    Ok(())
+}
+
+/// The directory given by --out, or the single workspace directory being read.
+fn output_dir(opts: &Options) -> Result<PathBuf> {
+    match (&opts.out, &opts.paths[..]) {
+        (Some(out), _) => Ok(out.clone()),
+        (None, [dir]) if dir.is_dir() => Ok(dir.clone()),
+        _ => Err(Error::InvalidArgument("Specify an output directory with --out".into())),
+    }
+}
+
+fn main_optimize(opts: &Options, ws: &Workspace) -> Result<()> {
+    // The output cannot be mixed with the input files.
+    let out_dir = opts.out.as_ref().ok_or_else(|| Error::InvalidArgument("Specify an output directory with --out".into()))?;
+
+    let mut optimizer = Optimizer::default();
+    for msg in ws.iter_messages() {
+        optimizer.ingest_message(&msg)?;
+    }
+
+    let mut sink = WorkspaceSink::new(out_dir)?;
+    optimizer.emit(&mut sink)?;
+    eprintln!("Written the optimized statement into {}", out_dir.display());
+    Ok(())
+}
+
+fn main_normalize(opts: &Options, ws: &Workspace) -> Result<()> {
+    // The output cannot be mixed with the input files.
+    let out_dir = opts.out.as_ref().ok_or_else(|| Error::InvalidArgument("Specify an output directory with --out".into()))?;
+    let mut sink = WorkspaceSink::new(out_dir)?;
+    let mut field = None;
+
+    for msg in ws.iter_messages() {
+        match msg {
+            Message::Header(header) => {
+                field = Some(Field::from_header(&header)?);
+                sink.push_header(header)?;
+            }
+            Message::ConstraintSystem(cs) => {
+                let field = field.as_ref().ok_or(Error::MissingHeader)?;
+                sink.push_constraints(cs.normalize(field))?;
+            }
+            Message::Witness(witness) => sink.push_witness(witness)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err),
+        }
+    }
+    eprintln!("Written the normalized statement into {}", out_dir.display());
+    Ok(())
+}
+
+fn main_diff(opts: &Options) -> Result<()> {
+    if opts.paths.len() != 2 {
+        return Err(Error::InvalidArgument("Specify two workspaces or files to compare.".into()));
+    }
+    let a = Messages::from(&Workspace::from_dirs_and_files(&opts.paths[..1])?);
+    let b = Messages::from(&Workspace::from_dirs_and_files(&opts.paths[1..])?);
+
+    let differences = diff_messages(&a, &b);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        return Err(Error::Check(format!("Found {} differences.", differences.len())));
+    }
+    eprintln!("The statements are equivalent.");
+    Ok(())
+}
+
+fn main_split(opts: &Options, ws: &Workspace) -> Result<()> {
+    // The output cannot be mixed with the input files.
+    let out_dir = opts.out.as_ref().ok_or_else(|| Error::InvalidArgument("Specify an output directory with --out".into()))?;
+    let limits = SplitLimits {
+        max_items: opts.max_items,
+        max_message_bytes: opts.max_message_bytes,
+        max_file_bytes: opts.max_file_bytes,
+    };
+
+    let mut sink = WorkspaceSink::with_limits(out_dir, limits)?;
+    for msg in ws.iter_messages() {
+        match msg {
+            Message::Header(h) => sink.push_header(h)?,
+            Message::ConstraintSystem(cs) => sink.push_constraints(cs)?,
+            Message::Witness(w) => sink.push_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err),
+        }
+    }
+    eprintln!("Written the split statement into {}", out_dir.display());
+    Ok(())
+}
+
+fn main_link(opts: &Options) -> Result<()> {
+    let out_dir = opts.out.as_ref().ok_or_else(|| Error::InvalidArgument("Specify an output directory with --out".into()))?;
+
+    let mut linker = if opts.substitute { Linker::new_substitution() } else { Linker::new_equality() };
+    for path in &opts.paths {
+        let statement = Messages::from(&Workspace::from_dirs_and_files(std::slice::from_ref(path))?);
+        linker.add_statement(&statement)
+            .map_err(|err| Error::InvalidArgument(format!("Cannot link {}: {}", path.display(), err)))?;
+    }
+    for connection in &opts.connect {
+        let (a, b) = parse_connection(connection)
+            .ok_or_else(|| Error::InvalidArgument(format!("Invalid connection '{}', expected the form A:X=B:Y", connection)))?;
+        linker.connect(a, b)?;
+    }
+
+    let mut sink = WorkspaceSink::new(out_dir)?;
+    linker.emit(&mut sink)?;
+    eprintln!("Written the linked statement into {}", out_dir.display());
+    Ok(())
+}
+
+/// Parse a connection of the form A:X=B:Y.
+fn parse_connection(text: &str) -> Option<(PartVar, PartVar)> {
+    let parse_var = |part_var: &str| -> Option<PartVar> {
+        let (part, id) = part_var.trim().split_once(':')?;
+        Some((part.parse().ok()?, id.parse().ok()?))
+    };
+    let (a, b) = text.split_once('=')?;
+    Some((parse_var(a)?, parse_var(b)?))
+}
+
+fn main_hash(opts: &Options) -> Result<()> {
+    let mut fingerprint = Fingerprint::default();
+    visit_roots(opts, |_, root| fingerprint.ingest_root(root))?;
+    println!("{}", fingerprint.finish());
+    Ok(())
+}
+
+fn main_from_r1cs(opts: &Options) -> Result<()> {
+    let has_extension = |path: &PathBuf, ext: &str| path.extension() == Some(ext.as_ref());
+    let r1cs_path = opts.paths.iter().find(|p| has_extension(p, "r1cs"))
+        .ok_or_else(|| Error::InvalidArgument("Specify a .r1cs file".into()))?;
+    let wtns_path = opts.paths.iter().find(|p| has_extension(p, "wtns"));
+
+    let (mut header, constraints) = iden3::read_r1cs(&read(r1cs_path)?)?;
+    let witness = match wtns_path {
+        Some(path) => Some(iden3::read_wtns(&read(path)?, &mut header)?),
+        None => None,
+    };
+
+    let mut sink = WorkspaceSink::new(output_dir(opts)?)?;
+    sink.push_header(header)?;
+    sink.push_constraints(constraints)?;
+    if let Some(witness) = witness {
+        sink.push_witness(witness)?;
+    }
+    eprintln!("Written the statement into {}", sink.workspace.display());
+    Ok(())
+}
+
+fn main_to_r1cs(opts: &Options, reader: &Reader) -> Result<()> {
+    let messages = Messages::from(reader);
+    let header = messages.circuit_headers.last().ok_or(Error::MissingHeader)?;
+    let out_dir = output_dir(opts)?;
+    create_dir_all(&out_dir)?;
+
+    let path = out_dir.join("circuit.r1cs");
+    iden3::write_r1cs(header, &messages.constraint_systems, &mut File::create(&path)?)?;
+    eprintln!("Written {}", path.display());
+
+    if !messages.witnesses.is_empty() {
+        let path = out_dir.join("witness.wtns");
+        iden3::write_wtns(header, &messages.witnesses, &mut File::create(&path)?)?;
+        eprintln!("Written {}", path.display());
+    }
+    Ok(())
+}
+
 fn main_clean(opts: &Options) -> Result<()> {
     let all_files = list_workspace_files(&opts.paths)?;
     for file in &all_files {
@@ -295,9 +833,75 @@ fn main_fake_verify(_: &Reader) -> Result<()> {
     Ok(())
 }
 
+/// Where to store the parameters and proof: inside the workspace directory if one is given,
+/// or in the current directory.
+#[cfg(feature = "groth16")]
+fn proof_dir(opts: &Options) -> PathBuf {
+    match &opts.paths[..] {
+        [dir] if dir.is_dir() => dir.clone(),
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(feature = "groth16")]
+fn main_prove(opts: &Options) -> Result<()> {
+    use crate::consumers::groth16::*;
+
+    let statement = Groth16Statement::from_workspace(&stream_messages(opts)?)?;
+
+    // Reuse the parameters only if they were generated for the same circuit.
+    let params_path = proof_dir(opts).join("groth16_parameters");
+    let circuit_path = proof_dir(opts).join("groth16_parameters.circuit");
+    let circuit = statement.circuit_fingerprint()?;
+    let params = if params_path.exists() && read_to_string(&circuit_path).ok().as_deref() == Some(&circuit[..]) {
+        eprintln!("Using existing parameters from {}", params_path.display());
+        read_parameters(&params_path)?
+    } else {
+        let params = statement.setup()?;
+        write_parameters(&params, &params_path)?;
+        write(&circuit_path, &circuit)?;
+        eprintln!("Parameters written to {}", params_path.display());
+        params
+    };
+
+    let proof = statement.prove(&params)?;
+    let proof_path = proof_dir(opts).join("groth16_proof");
+    write_proof(&proof, &proof_path)?;
+    eprintln!("Proof written to {}", proof_path.display());
+    Ok(())
+}
+
+#[cfg(feature = "groth16")]
+fn main_verify(opts: &Options) -> Result<()> {
+    use crate::consumers::groth16::*;
+
+    let statement = Groth16Statement::from_workspace(&stream_messages(opts)?)?;
+    let circuit_path = proof_dir(opts).join("groth16_parameters.circuit");
+    if read_to_string(&circuit_path).ok() != Some(statement.circuit_fingerprint()?) {
+        return Err(Error::InvalidArgument(format!("The parameters were not generated for this circuit, see {}. Run `zkif prove` again.", circuit_path.display())));
+    }
+    let header = statement.header.ok_or(Error::MissingHeader)?;
+
+    let params = read_parameters(proof_dir(opts).join("groth16_parameters"))?;
+    let proof = read_proof(proof_dir(opts).join("groth16_proof"))?;
+    Groth16Statement::verify(&header, &params, &proof)?;
+    eprintln!("The proof is valid!");
+    Ok(())
+}
+
+#[cfg(not(feature = "groth16"))]
+fn main_prove(_: &Options) -> Result<()> {
+    Err(Error::InvalidArgument("zkif was built without the Groth16 backend. Rebuild with `--features groth16`.".into()))
+}
+
+#[cfg(not(feature = "groth16"))]
+fn main_verify(_: &Options) -> Result<()> {
+    Err(Error::InvalidArgument("zkif was built without the Groth16 backend. Rebuild with `--features groth16`.".into()))
+}
+
 fn main_generate_metrics(opts: &Options, generate_all: bool) -> Result<()> {
     if opts.paths.len() != 1 {
-        return Err("Specify a single directory where to write examples.".into());
+        return Err(Error::InvalidArgument("Specify a single directory where to write examples.".into()));
     }
     let out_dir = &opts.paths[0];
 
@@ -320,23 +924,395 @@ fn test_cli() -> Result<()> {
     let workspace = PathBuf::from("local/test_cli");
     let _ = remove_dir_all(&workspace);
 
+    cli(&test_options("example", &workspace))?;
+    cli(&test_options("validate", &workspace))?;
+    cli(&test_options("simulate", &workspace))?;
+
     cli(&Options {
-        tool: "example".to_string(),
-        paths: vec![workspace.clone()],
-        field_order: BigUint::from(101 as u32),
+        diagnose: true,
+        format: "json".to_string(),
+        ..test_options("simulate", &workspace)
     })?;
 
     cli(&Options {
-        tool: "validate".to_string(),
-        paths: vec![workspace.clone()],
+        diagnose: true,
+        parallel: true,
+        ..test_options("simulate", &workspace)
+    })?;
+
+    for format in &["text", "json"] {
+        cli(&Options {
+            detailed: true,
+            format: format.to_string(),
+            ..test_options("stats", &workspace)
+        })?;
+        cli(&Options {
+            breakdown: true,
+            format: format.to_string(),
+            ..test_options("stats", &workspace)
+        })?;
+        cli(&Options {
+            format: format.to_string(),
+            ..test_options("lint", &workspace)
+        })?;
+        cli(&Options {
+            format: format.to_string(),
+            ..test_options("check-determinism", &workspace)
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "groth16")]
+#[test]
+fn test_cli_groth16() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::str::FromStr;
+
+    let workspace = PathBuf::from("local/test_cli_groth16");
+    let _ = remove_dir_all(&workspace);
+    let bls12_381_order = BigUint::from_str("52435875175126190479447740508185965837690552500527637822603658699938581184513")?;
+
+    for tool in &["example", "prove", "verify"] {
+        cli(&Options {
+            field_order: bls12_381_order.clone(),
+            ..test_options(tool, &workspace)
+        })?;
+    }
+
+    // After the circuit changes, the parameters do not match anymore, and prove generates new ones.
+    use crate::producers::examples::example_constraints;
+    example_constraints().write_into(&mut File::create(workspace.join("more_constraints.zkif"))?)?;
+    assert!(cli(&test_options("verify", &workspace)).is_err());
+    cli(&test_options("prove", &workspace))?;
+    cli(&test_options("verify", &workspace))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_options(tool: &str, workspace: &Path) -> Options {
+    Options {
+        tool: tool.to_string(),
+        paths: vec![workspace.to_path_buf()],
         field_order: BigUint::from(101 as u32),
+        diagnose: false,
+        parallel: false,
+        detailed: false,
+        breakdown: false,
+        format: "text".to_string(),
+        out: None,
+        connect: vec![],
+        substitute: false,
+        max_items: 0,
+        max_message_bytes: 0,
+        max_file_bytes: 0,
+        trials: 100,
+    }
+}
+
+#[test]
+fn test_cli_split() -> Result<()> {
+    use std::fs::{remove_dir_all, read_dir};
+    use crate::producers::examples::example_constraints;
+
+    let workspace = PathBuf::from("local/test_cli_split");
+    let split = PathBuf::from("local/test_cli_split_out");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&split);
+    cli(&test_options("example", &workspace))?;
+
+    cli(&Options {
+        out: Some(split.clone()),
+        max_items: 1,
+        ..test_options("split", &workspace)
     })?;
+    cli(&test_options("simulate", &split))?;
+
+    // A message for each of the 3 constraints and each of the 2 witness variables, in the same files.
+    assert_eq!(read_dir(&split)?.count(), 3);
+    let messages = Messages::from(&Workspace::from_dir(&split)?);
+    assert_eq!(messages.constraint_systems.len(), 3);
+    assert_eq!(messages.witnesses.len(), 2);
 
+    // A file for each message.
+    let _ = remove_dir_all(&split);
     cli(&Options {
-        tool: "simulate".to_string(),
-        paths: vec![workspace.clone()],
-        field_order: BigUint::from(101 as u32),
+        out: Some(split.clone()),
+        max_items: 1,
+        max_file_bytes: 1,
+        ..test_options("split", &workspace)
+    })?;
+    cli(&test_options("simulate", &split))?;
+    assert_eq!(read_dir(&split)?.count(), 6);
+
+    // With more than 10 files, the constraints are still read in order.
+    for i in 0..3 {
+        example_constraints().write_into(&mut File::create(workspace.join(format!("constraints_more_{}.zkif", i)))?)?;
+    }
+    let _ = remove_dir_all(&split);
+    cli(&Options {
+        out: Some(split.clone()),
+        max_items: 1,
+        max_file_bytes: 1,
+        ..test_options("split", &workspace)
     })?;
+    cli(&test_options("simulate", &split))?;
+    assert_eq!(read_dir(&split)?.count(), 15);
+
+    let hash = |path: &PathBuf| -> Result<String> {
+        let mut fingerprint = Fingerprint::default();
+        visit_roots(&test_options("hash", path), |_, root| fingerprint.ingest_root(root))?;
+        Ok(fingerprint.finish())
+    };
+    assert_eq!(hash(&split)?, hash(&workspace)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_link() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_link");
+    let linked = PathBuf::from("local/test_cli_link_out");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&linked);
+    cli(&test_options("example", &workspace))?;
+
+    // Two copies of the example, with the same x.
+    cli(&Options {
+        paths: vec![workspace.clone(), workspace.clone()],
+        out: Some(linked.clone()),
+        connect: vec!["0:1=1:1".to_string()],
+        ..test_options("link", &workspace)
+    })?;
+    cli(&test_options("validate", &linked))?;
+    cli(&test_options("simulate", &linked))?;
+
+    let messages = Messages::from(&Workspace::from_dir(&linked)?);
+    assert_eq!(messages.circuit_headers[0].free_variable_id, 11);
+    assert_eq!(messages.constraint_systems.iter().map(|cs| cs.constraints.len()).sum::<usize>(), 7);
+
+    assert!(cli(&Options {
+        paths: vec![workspace.clone(), workspace.clone()],
+        out: Some(linked.clone()),
+        connect: vec!["0:1-1:1".to_string()],
+        ..test_options("link", &workspace)
+    }).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_solve() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_solve");
+    let _ = remove_dir_all(&workspace);
+
+    cli(&test_options("example", &workspace))?;
+    remove_file(workspace.join("witness.zkif"))?;
+    assert!(cli(&test_options("simulate", &workspace)).is_err());
+
+    cli(&test_options("solve", &workspace))?;
+    cli(&test_options("simulate", &workspace))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_optimize() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_optimize");
+    let optimized = PathBuf::from("local/test_cli_optimize_out");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&optimized);
+
+    cli(&test_options("example", &workspace))?;
+    assert!(cli(&test_options("optimize", &workspace)).is_err());
+
+    cli(&Options { out: Some(optimized.clone()), ..test_options("optimize", &workspace) })?;
+    cli(&test_options("simulate", &optimized))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_normalize() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use crate::ConstraintSystem;
+
+    let workspace = PathBuf::from("local/test_cli_normalize");
+    let normalized = PathBuf::from("local/test_cli_normalize_out");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&normalized);
+
+    cli(&test_options("example", &workspace))?;
+    cli(&Options { out: Some(normalized.clone()), ..test_options("normalize", &workspace) })?;
+    cli(&test_options("simulate", &normalized))?;
+
+    let original = Messages::from(&Workspace::from_dir(&workspace)?);
+    let normalized_messages = Messages::from(&Workspace::from_dir(&normalized)?);
+    assert_eq!(normalized_messages.constraint_systems, original.constraint_systems);
+
+    // Repeated IDs and zero coefficients: (2*xx + 100*xx + 0*x) * 1 = xx + 0*y becomes xx * 1 = xx.
+    let _ = remove_dir_all(&normalized);
+    let messy: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![4, 4, 1], vec![2, 100, 0]), (vec![0], vec![1]), (vec![4, 2], vec![1, 0])),
+    ];
+    ConstraintSystem::from(messy).write_into(&mut File::create(workspace.join("messy.zkif"))?)?;
+    cli(&Options { out: Some(normalized.clone()), ..test_options("normalize", &workspace) })?;
+    cli(&test_options("simulate", &normalized))?;
+
+    let normalized_messages = Messages::from(&Workspace::from_dir(&normalized)?);
+    let expected: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![4], vec![1]), (vec![0], vec![1]), (vec![4], vec![1])),
+    ];
+    assert!(normalized_messages.constraint_systems.contains(&ConstraintSystem::from(expected)));
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_diff() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_diff");
+    let other = PathBuf::from("local/test_cli_diff_other");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&other);
+
+    cli(&test_options("example", &workspace))?;
+    cli(&Options { field_order: BigUint::from(103 as u32), ..test_options("example", &other) })?;
+
+    cli(&Options { paths: vec![workspace.clone(), workspace.clone()], ..test_options("diff", &workspace) })?;
+    assert!(cli(&Options { paths: vec![workspace.clone(), other], ..test_options("diff", &workspace) }).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_iden3() -> Result<()> {
+    use std::fs::remove_dir_all;
+
+    let workspace = PathBuf::from("local/test_cli_iden3");
+    let circom_dir = PathBuf::from("local/test_cli_iden3_circom");
+    let imported = PathBuf::from("local/test_cli_iden3_imported");
+    let _ = remove_dir_all(&workspace);
+    let _ = remove_dir_all(&circom_dir);
+    let _ = remove_dir_all(&imported);
+
+    cli(&test_options("example", &workspace))?;
+    cli(&Options { out: Some(circom_dir.clone()), ..test_options("to-r1cs", &workspace) })?;
+
+    cli(&Options {
+        paths: vec![circom_dir.join("circuit.r1cs"), circom_dir.join("witness.wtns")],
+        out: Some(imported.clone()),
+        ..test_options("from-r1cs", &workspace)
+    })?;
+    cli(&test_options("simulate", &imported))?;
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_from_serde() -> Result<()> {
+    use std::fs::{remove_dir_all, write};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_cli_from_serde");
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir)?;
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    write(dir.join("statement.json"), serde_json::to_string(&messages)?)?;
+    write(dir.join("statement.yaml"), serde_yaml::to_string(&messages)?)?;
+
+    for (tool, file) in &[("from-json", "statement.json"), ("from-yaml", "statement.yaml")] {
+        let workspace = dir.join(tool);
+        cli(&Options {
+            paths: vec![dir.join(file)],
+            out: Some(workspace.clone()),
+            ..test_options(tool, &dir)
+        })?;
+        cli(&test_options("simulate", &workspace))?;
+        assert_eq!(Messages::from(&Workspace::from_dir(&workspace)?), messages);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_cli_text() -> Result<()> {
+    use std::fs::{remove_dir_all, write};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_cli_text");
+    let _ = remove_dir_all(&dir);
+    create_dir_all(&dir)?;
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    let mut text = vec![];
+    write_text(&messages, &mut text)?;
+    write(dir.join("statement.txt"), text)?;
+
+    let workspace = dir.join("workspace");
+    cli(&Options {
+        paths: vec![dir.join("statement.txt")],
+        out: Some(workspace.clone()),
+        ..test_options("from-text", &dir)
+    })?;
+    cli(&test_options("simulate", &workspace))?;
+    let parsed = Messages::from(&Workspace::from_dir(&workspace)?);
+    assert_eq!(diff_messages(&messages, &parsed), Vec::<String>::new());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_call_gadget() -> Result<()> {
+    use std::fs::{remove_dir_all, write, set_permissions, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_cli_call_gadget");
+    let _ = remove_dir_all(&dir);
+    let request_dir = dir.join("request");
+    WorkspaceSink::new(&request_dir)?.push_header(example_circuit_header())?;
+
+    // A gadget that answers with prepared messages.
+    let mut output = vec![];
+    example_constraints().write_into(&mut output)?;
+    example_witness().write_into(&mut output)?;
+    CircuitHeader::simple_inputs(5).write_into(&mut output)?;
+    write(dir.join("output.zkif"), output)?;
+    let gadget = dir.join("gadget.sh");
+    write(&gadget, format!("#!/bin/sh\ncat > /dev/null\ncat {}\n", dir.join("output.zkif").canonicalize()?.display()))?;
+    set_permissions(&gadget, Permissions::from_mode(0o755))?;
+
+    let out = dir.join("out");
+    cli(&Options {
+        paths: vec![gadget, request_dir],
+        out: Some(out.clone()),
+        ..test_options("call-gadget", &dir)
+    })?;
+
+    let outputs = Messages::from(&Workspace::from_dir(&out)?);
+    assert_eq!(outputs.circuit_headers, vec![CircuitHeader::simple_inputs(5)]);
+    assert_eq!(outputs.constraint_systems, vec![example_constraints()]);
+    assert_eq!(outputs.witnesses, vec![example_witness()]);
 
     Ok(())
 }
@@ -0,0 +1,328 @@
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, Message, Field, FieldElement};
+use crate::consumers::solver::Solver;
+use crate::consumers::simulator::Simulator;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use rand::Rng;
+use serde::Serialize;
+
+type Var = u64;
+
+/// A private variable that takes different values in two witnesses of the same instance.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct NonUniqueVariable {
+    pub id: Var,
+    /// The name of the variable, if given in `Variables.info`.
+    pub name: Option<String>,
+    /// The value in the given witness, in decimal.
+    pub value: String,
+    /// The value in the alternative witness, in decimal.
+    pub alternative: String,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct DeterminismReport {
+    /// Private variables determined uniquely by the instance, through linear propagation.
+    pub determined: Vec<Var>,
+    /// Private variables shown to be non-unique by an alternative witness.
+    pub non_unique: Vec<NonUniqueVariable>,
+    /// Private variables neither determined nor shown to be non-unique.
+    pub undecided: Vec<Var>,
+    /// The number of alternative witnesses that were tried.
+    pub trials: usize,
+    /// The first alternative witness found, which satisfies all constraints.
+    pub alternative_witness: Option<Witness>,
+}
+
+impl fmt::Display for DeterminismReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Determined by the instance: {} private variables.", self.determined.len())?;
+        writeln!(f, "Non-unique: {} private variables.", self.non_unique.len())?;
+        for var in &self.non_unique {
+            write!(f, "    variable_{}", var.id)?;
+            if let Some(name) = &var.name {
+                write!(f, " ({})", name)?;
+            }
+            writeln!(f, " = {}, or {}", var.value, var.alternative)?;
+        }
+        writeln!(f, "Undecided after {} trials: {} private variables.", self.trials, self.undecided.len())
+    }
+}
+
+/// DeterminismChecker looks for under-constrained private variables. Given a valid witness,
+/// it searches for a second witness of the same instance that still satisfies all constraints.
+///
+/// First, the values that the instance determines are propagated through the constraints with a `Solver`,
+/// including linear systems solved by Gaussian elimination (see `Solver::solve_linear`).
+/// Then each trial targets one of the remaining private variables: it gets a random value different from
+/// the given witness, and the other variables are propagated, or guessed when propagation is stuck.
+/// The first trial of each variable guesses the values of the given witness, the next ones guess randomly.
+/// An alternative witness is accepted when the `Simulator` finds it true, and the variables that differ are non-unique.
+///
+/// The search only finds alternatives, so the variables that are not found to be non-unique
+/// are not proven unique, except those determined by propagation.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::determinism::DeterminismChecker;
+/// use zkinterface::producers::examples::*;
+///
+/// let mut checker = DeterminismChecker::default();
+/// checker.ingest_header(&example_circuit_header()).unwrap();
+/// checker.ingest_witness(&example_witness()).unwrap();
+/// checker.ingest_constraint_system(&example_constraints()).unwrap();
+///
+/// let report = checker.check(10, &mut rand::thread_rng()).unwrap();
+/// assert_eq!(report.determined, vec![4, 5]);
+/// assert!(report.non_unique.is_empty());
+/// ```
+#[derive(Clone, Default)]
+pub struct DeterminismChecker {
+    header: Option<CircuitHeader>,
+    field: Option<Field>,
+    witness: BTreeMap<Var, FieldElement>,
+    names: HashMap<Var, String>,
+    constraint_systems: Vec<ConstraintSystem>,
+}
+
+impl DeterminismChecker {
+    pub fn ingest_message(&mut self, msg: &Message) -> Result<()> {
+        match msg {
+            Message::Header(h) => self.ingest_header(h)?,
+            Message::ConstraintSystem(cs) => self.ingest_constraint_system(cs)?,
+            Message::Witness(w) => self.ingest_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.clone()),
+        }
+        Ok(())
+    }
+
+    pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
+        if self.header.is_some() {
+            return Err(Error::MultipleHeaders);
+        }
+        self.field = Some(Field::from_header(header)?);
+        self.names.extend(header.instance_variables.get_names());
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        let field = self.field()?.clone();
+        for var in witness.assigned_variables.get_variables() {
+            self.witness.insert(var.id, field.decode(var.value));
+        }
+        self.names.extend(witness.assigned_variables.get_names());
+        Ok(())
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
+        self.field()?;
+        self.constraint_systems.push(system.clone());
+        Ok(())
+    }
+
+    /// Check that the given witness is valid, then search for alternative witnesses during at most `trials` trials.
+    pub fn check(&self, trials: usize, rng: &mut impl Rng) -> Result<DeterminismReport> {
+        let field = self.field()?;
+        let header = self.header.as_ref().ok_or(Error::MissingHeader)?;
+
+        let original = self.encode_witness(field, self.witness.iter());
+        let violations = self.simulate(header, &original);
+        if !violations.is_empty() {
+            return Err(Error::Unsatisfied(format!("The witness is not valid, so it cannot be compared to alternatives: {}", violations.join(" "))));
+        }
+
+        let instance_ids: HashSet<Var> = header.instance_variables.variable_ids.iter().cloned().collect();
+        let private_ids: Vec<Var> = self.witness.keys()
+            .filter(|id| **id != 0 && !instance_ids.contains(id))
+            .cloned().collect();
+
+        // Propagate what the instance alone determines.
+        let mut base = Solver::default();
+        base.ingest_header(header)?;
+        for system in &self.constraint_systems {
+            base.ingest_constraint_system(system)?;
+        }
+        base.solve_linear()?;
+
+        let mut report = DeterminismReport::default();
+        let mut candidates = vec![];
+        for &id in &private_ids {
+            if base.get_value(id).is_some() {
+                report.determined.push(id);
+            } else {
+                candidates.push(id);
+            }
+        }
+
+        let mut found = HashMap::<Var, FieldElement>::new();
+        for trial in 0..trials {
+            let undecided: Vec<Var> = candidates.iter().filter(|id| !found.contains_key(id)).cloned().collect();
+            if undecided.is_empty() { break; }
+            report.trials += 1;
+
+            let target = undecided[trial % undecided.len()];
+            let guess_original = trial < candidates.len();
+
+            // The constraints are shared with the base solver.
+            let mut solver = base.clone();
+            solver.assign(target, self.random_other_than(field, &self.witness[&target], rng));
+            let complete = loop {
+                // The guesses contradict the constraints.
+                if solver.solve_linear().is_err() { break false; }
+                match private_ids.iter().find(|id| solver.get_value(**id).is_none()) {
+                    None => break true,
+                    Some(&id) => {
+                        let value = if guess_original || rng.gen::<bool>() {
+                            self.witness[&id].clone()
+                        } else {
+                            field.random(rng)
+                        };
+                        solver.assign(id, value);
+                    }
+                }
+            };
+            if !complete { continue; }
+
+            let alternative = self.encode_witness(field, private_ids.iter().map(|id| (id, solver.get_value(*id).unwrap())));
+            if !self.simulate(header, &alternative).is_empty() { continue; }
+
+            for &id in &private_ids {
+                let value = solver.get_value(id).unwrap();
+                if *value != self.witness[&id] && !found.contains_key(&id) {
+                    found.insert(id, value.clone());
+                }
+            }
+            if report.alternative_witness.is_none() {
+                report.alternative_witness = Some(alternative);
+            }
+        }
+
+        for &id in &candidates {
+            match found.get(&id) {
+                Some(alternative) => report.non_unique.push(NonUniqueVariable {
+                    id,
+                    name: self.names.get(&id).cloned(),
+                    value: field.to_biguint(&self.witness[&id]).to_string(),
+                    alternative: field.to_biguint(alternative).to_string(),
+                }),
+                None => report.undecided.push(id),
+            }
+        }
+        Ok(report)
+    }
+
+    /// The violations found by a simulation with the given witness. Messages are not copied.
+    fn simulate(&self, header: &CircuitHeader, witness: &Witness) -> Vec<String> {
+        let mut simulator = Simulator::default();
+        let mut ingest = || -> Result<()> {
+            simulator.ingest_header(header)?;
+            simulator.ingest_witness(witness)?;
+            for system in &self.constraint_systems {
+                simulator.ingest_constraint_system(system)?;
+            }
+            Ok(())
+        };
+        let result = ingest();
+        let mut violations = simulator.get_violations();
+        if let Err(err) = result {
+            violations.push(err.to_string());
+        }
+        violations
+    }
+
+    fn random_other_than(&self, field: &Field, value: &FieldElement, rng: &mut impl Rng) -> FieldElement {
+        loop {
+            let other = field.random(rng);
+            if other != *value {
+                return other;
+            }
+        }
+    }
+
+    fn encode_witness<'a>(&self, field: &Field, values: impl Iterator<Item=(&'a Var, &'a FieldElement)>) -> Witness {
+        let (ids, values): (Vec<Var>, Vec<FieldElement>) = values.map(|(id, value)| (*id, value.clone())).unzip();
+        let mut witness = Witness::default();
+        witness.assigned_variables.values = Some(field.encode_all(&values));
+        witness.assigned_variables.variable_ids = ids;
+        witness
+    }
+
+    fn field(&self) -> Result<&Field> {
+        self.field.as_ref()
+            .ok_or(Error::MissingHeader)
+    }
+}
+
+
+#[test]
+fn test_determinism_checker() -> Result<()> {
+    use crate::producers::examples::*;
+    use crate::{Variables, BilinearConstraint};
+    use rand::{SeedableRng, rngs::StdRng};
+
+    // Add a private variable 6 that no constraint ties down, and 7 = 6 * 6.
+    let header = CircuitHeader { free_variable_id: 8, ..example_circuit_header() };
+    let extra_witness = Witness {
+        assigned_variables: Variables { variable_ids: vec![6, 7], values: Some(vec![2, 4]), info: None },
+    };
+    let var = |id| Variables { variable_ids: vec![id], values: Some(vec![1]), info: None };
+    let extra_constraints = ConstraintSystem {
+        constraints: vec![BilinearConstraint {
+            linear_combination_a: var(6),
+            linear_combination_b: var(6),
+            linear_combination_c: var(7),
+        }],
+    };
+
+    let mut checker = DeterminismChecker::default();
+    checker.ingest_header(&header)?;
+    checker.ingest_witness(&example_witness())?;
+    checker.ingest_witness(&extra_witness)?;
+    checker.ingest_constraint_system(&example_constraints())?;
+    checker.ingest_constraint_system(&extra_constraints)?;
+
+    let report = checker.check(10, &mut StdRng::seed_from_u64(0))?;
+    assert_eq!(report.determined, vec![4, 5]);
+    let non_unique: Vec<Var> = report.non_unique.iter().map(|v| v.id).collect();
+    assert_eq!(non_unique, vec![6, 7]);
+    assert!(report.undecided.is_empty());
+
+    // The alternative is a valid witness.
+    let alternative = report.alternative_witness.unwrap();
+    assert!(checker.simulate(&header, &alternative).is_empty());
+
+    // A linear system determines 6 and 7 together (6 + 7 = 11, 6 - 7 = 5), but 8 + 9 = 10 leaves 8 and 9 free.
+    let header = CircuitHeader { free_variable_id: 10, ..example_circuit_header() };
+    let extra_witness = Witness {
+        assigned_variables: Variables { variable_ids: vec![6, 7, 8, 9], values: Some(vec![8, 3, 4, 6]), info: None },
+    };
+    let one = |id| (vec![id], vec![1]);
+    let linear: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![6, 7], vec![1, 1]), one(0), (vec![0], vec![11])),
+        ((vec![6, 7], serialize_small(&[1, NEG_ONE])), one(0), (vec![0], vec![5])),
+        ((vec![8, 9], vec![1, 1]), one(0), (vec![0], vec![10])),
+    ];
+    let mut checker = DeterminismChecker::default();
+    checker.ingest_header(&header)?;
+    checker.ingest_witness(&example_witness())?;
+    checker.ingest_witness(&extra_witness)?;
+    checker.ingest_constraint_system(&example_constraints())?;
+    checker.ingest_constraint_system(&ConstraintSystem::from(linear))?;
+
+    let report = checker.check(10, &mut StdRng::seed_from_u64(0))?;
+    assert_eq!(report.determined, vec![4, 5, 6, 7]);
+    let non_unique: Vec<Var> = report.non_unique.iter().map(|v| v.id).collect();
+    assert_eq!(non_unique, vec![8, 9]);
+
+    // An invalid witness is rejected.
+    let mut checker = DeterminismChecker::default();
+    checker.ingest_header(&example_circuit_header())?;
+    checker.ingest_witness(&example_witness_inputs(3, 5))?;
+    checker.ingest_constraint_system(&example_constraints())?;
+    assert!(checker.check(10, &mut StdRng::seed_from_u64(0)).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use crate::{CircuitHeader, BilinearConstraint, Variables, KeyValue, Messages, Field};
+
+use std::collections::BTreeMap;
+use num_bigint::BigUint;
+
+type Var = u64;
+
+/// Compare two statements semantically, and describe the differences from `a` to `b`.
+///
+/// Headers are compared field by field. Constraints are compared after normalization
+/// (see `Variables::normalize`), by position, after skipping the common first and last constraints.
+/// Witness assignments are compared by variable ID.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::diff::diff_messages;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::Messages;
+///
+/// let a = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+/// let b = Messages { witnesses: vec![example_witness_inputs(3, 5)], ..a.clone() };
+///
+/// assert_eq!(diff_messages(&a, &a), Vec::<String>::new());
+/// assert_eq!(diff_messages(&a, &b), vec!["Witness variable_5 changed from 16 to 25"]);
+/// ```
+pub fn diff_messages(a: &Messages, b: &Messages) -> Vec<String> {
+    let mut diff = vec![];
+
+    // Headers.
+    let (headers_a, headers_b) = (&a.circuit_headers, &b.circuit_headers);
+    if headers_a.len() != headers_b.len() {
+        diff.push(format!("Header count changed from {} to {}", headers_a.len(), headers_b.len()));
+    }
+    for (i, (header_a, header_b)) in headers_a.iter().zip(headers_b).enumerate() {
+        diff_headers(&mut diff, i, header_a, header_b);
+    }
+
+    let field_a = headers_a.first().and_then(|h| Field::from_header(h).ok());
+    let field_b = headers_b.first().and_then(|h| Field::from_header(h).ok());
+
+    // Constraints.
+    let constraints_a = normalized_constraints(a, field_a.as_ref());
+    let constraints_b = normalized_constraints(b, field_b.as_ref());
+    diff_constraints(&mut diff, &constraints_a, &constraints_b, field_a.as_ref(), field_b.as_ref());
+
+    // Witness.
+    let witness_a = assignments(a.witnesses.iter().map(|w| &w.assigned_variables), field_a.as_ref());
+    let witness_b = assignments(b.witnesses.iter().map(|w| &w.assigned_variables), field_b.as_ref());
+    diff_assignments(&mut diff, "Witness", &witness_a, &witness_b);
+
+    diff
+}
+
+fn diff_headers(diff: &mut Vec<String>, index: usize, a: &CircuitHeader, b: &CircuitHeader) {
+    let field_a = Field::from_header(a).ok();
+    let field_b = Field::from_header(b).ok();
+    let prefix = if index == 0 { "Header".to_string() } else { format!("Header {}", index) };
+
+    // Compare the values, not their encoding.
+    let describe = |max: &Option<Vec<u8>>| match max {
+        Some(max) => BigUint::from_bytes_le(max).to_string(),
+        None => "none".to_string(),
+    };
+    let (max_a, max_b) = (describe(&a.field_maximum), describe(&b.field_maximum));
+    if max_a != max_b {
+        diff.push(format!("{} field_maximum changed from {} to {}", prefix, max_a, max_b));
+    }
+
+    if a.free_variable_id != b.free_variable_id {
+        diff.push(format!("{} free_variable_id changed from {} to {}",
+                          prefix, a.free_variable_id, b.free_variable_id));
+    }
+
+    let instance_a = assignments(Some(&a.instance_variables), field_a.as_ref());
+    let instance_b = assignments(Some(&b.instance_variables), field_b.as_ref());
+    diff_assignments(diff, &format!("{} instance", prefix), &instance_a, &instance_b);
+
+    let config_a: Vec<String> = a.configuration.iter().flatten().map(describe_key_value).collect();
+    let config_b: Vec<String> = b.configuration.iter().flatten().map(describe_key_value).collect();
+    for entry in &config_a {
+        if !config_b.contains(entry) {
+            diff.push(format!("{} configuration removed: {}", prefix, entry));
+        }
+    }
+    for entry in &config_b {
+        if !config_a.contains(entry) {
+            diff.push(format!("{} configuration added: {}", prefix, entry));
+        }
+    }
+}
+
+fn diff_constraints(
+    diff: &mut Vec<String>,
+    a: &[BilinearConstraint],
+    b: &[BilinearConstraint],
+    field_a: Option<&Field>,
+    field_b: Option<&Field>,
+) {
+    if a.len() != b.len() {
+        diff.push(format!("Constraint count changed from {} to {}", a.len(), b.len()));
+    }
+
+    // Skip the common prefix and suffix, so that an insertion or a removal is reported once.
+    let prefix = a.iter().zip(b).take_while(|(ca, cb)| ca == cb).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(ca, cb)| ca == cb).count();
+    let middle_a = &a[prefix..a.len() - suffix];
+    let middle_b = &b[prefix..b.len() - suffix];
+
+    for i in 0..middle_a.len().max(middle_b.len()) {
+        match (middle_a.get(i), middle_b.get(i)) {
+            (Some(ca), Some(cb)) => diff.push(format!("Constraint {} changed from {} to {}",
+                                                      prefix + i, describe_constraint(ca, field_a), describe_constraint(cb, field_b))),
+            (Some(ca), None) => diff.push(format!("Constraint {} removed: {}",
+                                                  prefix + i, describe_constraint(ca, field_a))),
+            (None, Some(cb)) => diff.push(format!("Constraint {} added: {}",
+                                                  prefix + i, describe_constraint(cb, field_b))),
+            (None, None) => {}
+        }
+    }
+}
+
+fn diff_assignments(diff: &mut Vec<String>, what: &str, a: &BTreeMap<Var, String>, b: &BTreeMap<Var, String>) {
+    for (id, value_a) in a {
+        match b.get(id) {
+            Some(value_b) if value_a != value_b =>
+                diff.push(format!("{} variable_{} changed from {} to {}", what, id, value_a, value_b)),
+            Some(_) => {}
+            None => diff.push(format!("{} variable_{} removed", what, id)),
+        }
+    }
+    for (id, value_b) in b {
+        if !a.contains_key(id) {
+            diff.push(format!("{} variable_{} added with value {}", what, id, value_b));
+        }
+    }
+}
+
+fn normalized_constraints(messages: &Messages, field: Option<&Field>) -> Vec<BilinearConstraint> {
+    let constraints = messages.constraint_systems.iter().flat_map(|cs| &cs.constraints);
+    match field {
+        Some(field) => constraints.map(|c| c.normalize(field)).collect(),
+        None => constraints.cloned().collect(),
+    }
+}
+
+/// The decimal values of variables, by ID.
+fn assignments<'a>(all_vars: impl IntoIterator<Item=&'a Variables>, field: Option<&Field>) -> BTreeMap<Var, String> {
+    let mut values = BTreeMap::new();
+    for vars in all_vars {
+        for var in vars.get_variables() {
+            let value = if var.has_value() { describe_value(var.value, field) } else { "none".to_string() };
+            values.insert(var.id, value);
+        }
+    }
+    values
+}
+
+fn describe_value(encoded: &[u8], field: Option<&Field>) -> String {
+    match field {
+        Some(field) => field.to_biguint(&field.decode(encoded)).to_string(),
+        None => BigUint::from_bytes_le(encoded).to_string(),
+    }
+}
+
+fn describe_lc(lc: &Variables, field: Option<&Field>) -> String {
+    let terms: Vec<String> = lc.get_variables().iter()
+        .map(|term| format!("{}*var_{}", describe_value(term.value, field), term.id))
+        .collect();
+    if terms.is_empty() { "0".to_string() } else { terms.join(" + ") }
+}
+
+fn describe_constraint(constraint: &BilinearConstraint, field: Option<&Field>) -> String {
+    format!("({}) * ({}) = ({})",
+            describe_lc(&constraint.linear_combination_a, field),
+            describe_lc(&constraint.linear_combination_b, field),
+            describe_lc(&constraint.linear_combination_c, field))
+}
+
+fn describe_key_value(kv: &KeyValue) -> String {
+    match (&kv.text, &kv.data) {
+        (Some(text), _) => format!("{}={:?}", kv.key, text),
+        (None, Some(data)) => format!("{}={:?}", kv.key, data),
+        (None, None) => format!("{}={}", kv.key, kv.number),
+    }
+}
+
+
+#[test]
+fn test_diff() {
+    use crate::producers::examples::*;
+
+    let a = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+
+    // Insert a constraint in the middle, and change the header.
+    let mut b = a.clone();
+    let constraints = &mut b.constraint_systems[0].constraints;
+    constraints.insert(1, constraints[0].clone());
+    b.circuit_headers[0].free_variable_id = 7;
+    b.circuit_headers[0].configuration.get_or_insert_with(Vec::new).push(KeyValue::from(("mode", "test")));
+
+    assert_eq!(diff_messages(&a, &b), vec![
+        "Header free_variable_id changed from 6 to 7",
+        "Header configuration added: mode=\"test\"",
+        "Constraint count changed from 3 to 4",
+        "Constraint 1 added: (1*var_1) * (1*var_1) = (1*var_4)",
+    ]);
+
+    // Constraints that differ only in their encoding are the same.
+    let mut c = a.clone();
+    let lc = &mut c.constraint_systems[0].constraints[2].linear_combination_b;
+    lc.variable_ids = vec![5, 4, 6];
+    lc.values = Some(vec![1, 0, 1, 0, 0, 0]);
+    assert_eq!(diff_messages(&a, &c), Vec::<String>::new());
+}
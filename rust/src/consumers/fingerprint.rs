@@ -0,0 +1,117 @@
+use sha2::{Digest, Sha256};
+
+use crate::{ConstraintSystem, Message};
+use crate::field::trim_zeros;
+use crate::consumers::reader::{Variable, fb_variables};
+use crate::zkinterface_generated::zkinterface as fb;
+
+/// Fingerprint computes a stable hash of the constraints of a statement.
+///
+/// The hash depends only on the sequence of constraints, not on how they are split into
+/// messages or files. Coefficients are hashed without their trailing zero bytes, so that
+/// truncated and padded encodings give the same fingerprint.
+/// Headers and witnesses are not included.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::fingerprint::Fingerprint;
+/// use zkinterface::producers::examples::example_constraints;
+/// use zkinterface::ConstraintSystem;
+///
+/// let cs = example_constraints();
+/// let first = ConstraintSystem { constraints: cs.constraints[..1].to_vec() };
+/// let rest = ConstraintSystem { constraints: cs.constraints[1..].to_vec() };
+///
+/// let mut fingerprint = Fingerprint::default();
+/// fingerprint.ingest_constraint_system(&first);
+/// fingerprint.ingest_constraint_system(&rest);
+/// assert_eq!(fingerprint.finish(), cs.fingerprint());
+/// ```
+#[derive(Clone)]
+pub struct Fingerprint {
+    hasher: Sha256,
+}
+
+/// A version tag, to change if the hashed representation ever changes.
+const DOMAIN: &[u8] = b"zkinterface constraints fingerprint v1";
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(DOMAIN);
+        Fingerprint { hasher }
+    }
+}
+
+impl Fingerprint {
+    pub fn ingest_message(&mut self, msg: &Message) {
+        if let Message::ConstraintSystem(cs) = msg {
+            self.ingest_constraint_system(cs);
+        }
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) {
+        for constraint in &system.constraints {
+            self.hash_terms(&constraint.linear_combination_a.get_variables());
+            self.hash_terms(&constraint.linear_combination_b.get_variables());
+            self.hash_terms(&constraint.linear_combination_c.get_variables());
+        }
+    }
+
+    /// Like `ingest_message`, for a message borrowed from a FlatBuffers buffer.
+    pub fn ingest_root(&mut self, root: &fb::Root) {
+        let constraints = match root.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+            Some(constraints) => constraints,
+            None => return,
+        };
+        for i in 0..constraints.len() {
+            let constraint = constraints.get(i);
+            self.hash_terms(&fb_variables(constraint.linear_combination_a()));
+            self.hash_terms(&fb_variables(constraint.linear_combination_b()));
+            self.hash_terms(&fb_variables(constraint.linear_combination_c()));
+        }
+    }
+
+    /// The fingerprint in hexadecimal.
+    pub fn finish(self) -> String {
+        self.hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn hash_terms(&mut self, terms: &[Variable]) {
+        self.hasher.update((terms.len() as u64).to_le_bytes());
+        for term in terms {
+            let coeff = trim_zeros(term.value);
+            self.hasher.update(term.id.to_le_bytes());
+            self.hasher.update((coeff.len() as u64).to_le_bytes());
+            self.hasher.update(coeff);
+        }
+    }
+}
+
+
+#[test]
+fn test_fingerprint() {
+    use crate::producers::examples::example_constraints;
+
+    let cs = example_constraints();
+    let fingerprint = cs.fingerprint();
+    assert_eq!(fingerprint.len(), 64);
+
+    // Padded coefficients give the same fingerprint.
+    let mut padded = cs.clone();
+    let lc = &mut padded.constraints[2].linear_combination_b;
+    lc.values = Some(vec![1, 0, 0, 0, 1, 0, 0, 0]);
+    assert_eq!(padded.fingerprint(), fingerprint);
+
+    // Other coefficients give another fingerprint.
+    let mut other = cs.clone();
+    other.constraints[2].linear_combination_b.values = Some(vec![1, 2]);
+    assert_ne!(other.fingerprint(), fingerprint);
+
+    // Moving a term to another constraint gives another fingerprint.
+    let mut moved = cs.clone();
+    moved.constraints[0].linear_combination_c.variable_ids.push(5);
+    moved.constraints[0].linear_combination_c.values.as_mut().unwrap().push(1);
+    moved.constraints[1].linear_combination_c = Default::default();
+    assert_ne!(moved.fingerprint(), fingerprint);
+}
@@ -0,0 +1,277 @@
+//! A built-in Groth16 proving system over BLS12-381, based on bellman.
+//!
+//! Available with the cargo feature `groth16`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use bellman::{Circuit, ConstraintSystem as BellmanCS, LinearCombination, SynthesisError, Variable as BellmanVar};
+use bellman::groth16::{
+    Parameters, Proof,
+    create_random_proof, generate_random_parameters, prepare_verifying_key, verify_proof,
+};
+use bls12_381::{Bls12, Scalar};
+use ff::PrimeField;
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, Variables, Workspace, Message};
+use crate::consumers::fingerprint::Fingerprint;
+use crate::field::trim_zeros;
+
+/// A statement loaded in memory, ready to be given to the Groth16 setup, prover, or verifier.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::groth16::Groth16Statement;
+/// use zkinterface::producers::examples::*;
+///
+/// let field_max = Groth16Statement::field_maximum();
+/// let header = example_circuit_header_in_field(field_max);
+///
+/// let statement = Groth16Statement {
+///     header: Some(header.clone()),
+///     constraints: vec![example_constraints()],
+///     witness: example_witness().assigned_variables.get_variables().iter()
+///         .map(|v| (v.id, v.value.to_vec())).collect(),
+/// };
+///
+/// let params = statement.setup().unwrap();
+/// let proof = statement.prove(&params).unwrap();
+/// Groth16Statement::verify(&header, &params, &proof).unwrap();
+/// ```
+#[derive(Clone, Default, Debug)]
+pub struct Groth16Statement {
+    pub header: Option<CircuitHeader>,
+    pub constraints: Vec<ConstraintSystem>,
+    /// Encoded values of witness variables, by variable ID.
+    pub witness: HashMap<u64, Vec<u8>>,
+}
+
+impl Groth16Statement {
+    /// Collect the header, constraints, and witness of a workspace.
+    pub fn from_workspace(ws: &Workspace) -> Result<Self> {
+        let mut statement = Self::default();
+
+        for msg in ws.iter_messages() {
+            match msg {
+                Message::Header(h) => {
+                    if statement.header.is_some() {
+                        return Err(Error::MultipleHeaders);
+                    }
+                    statement.header = Some(h);
+                }
+                Message::ConstraintSystem(cs) => statement.constraints.push(cs),
+                Message::Witness(w) => {
+                    for var in w.assigned_variables.get_variables() {
+                        statement.witness.insert(var.id, var.value.to_vec());
+                    }
+                }
+                Message::Command(_) => {}
+                Message::Err(err) => return Err(err),
+            }
+        }
+
+        Ok(statement)
+    }
+
+    /// The field_maximum that a header must declare to be used with this backend.
+    pub fn field_maximum() -> Vec<u8> {
+        (-Scalar::one()).to_repr().to_vec()
+    }
+
+    fn header(&self) -> Result<&CircuitHeader> {
+        let header = self.header.as_ref().ok_or(Error::MissingHeader)?;
+        ensure_field(header)?;
+        Ok(header)
+    }
+
+    /// A fingerprint of what the parameters depend on: the constraints, the instance variables,
+    /// and the number of variables. Parameters can be reused only for the same fingerprint.
+    pub fn circuit_fingerprint(&self) -> Result<String> {
+        let header = self.header()?;
+        let mut constraints = Fingerprint::default();
+        for system in &self.constraints {
+            constraints.ingest_constraint_system(system);
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(constraints.finish());
+        hasher.update((header.instance_variables.variable_ids.len() as u64).to_le_bytes());
+        for id in &header.instance_variables.variable_ids {
+            hasher.update(id.to_le_bytes());
+        }
+        hasher.update(header.free_variable_id.to_le_bytes());
+        Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Generate fresh random parameters (proving and verifying keys) for this constraint system.
+    pub fn setup(&self) -> Result<Parameters<Bls12>> {
+        let circuit = SynthesizedCircuit { header: self.header()?, constraints: &self.constraints, witness: None };
+        Ok(generate_random_parameters::<Bls12, _, _>(circuit, &mut OsRng)?)
+    }
+
+    /// Prove that the witness satisfies the constraint system.
+    pub fn prove(&self, params: &Parameters<Bls12>) -> Result<Proof<Bls12>> {
+        let circuit = SynthesizedCircuit { header: self.header()?, constraints: &self.constraints, witness: Some(&self.witness) };
+        Ok(create_random_proof(circuit, params, &mut OsRng)?)
+    }
+
+    /// Verify a proof against the values of the instance variables of a header.
+    pub fn verify(header: &CircuitHeader, params: &Parameters<Bls12>, proof: &Proof<Bls12>) -> Result<()> {
+        ensure_field(header)?;
+
+        let mut public_inputs = vec![];
+        for var in header.instance_variables.get_variables() {
+            if !var.has_value() {
+                return Err(Error::Unsatisfied(format!("No value given for the instance variable_{}", var.id)));
+            }
+            public_inputs.push(decode_scalar(var.value)?);
+        }
+
+        let pvk = prepare_verifying_key(&params.vk);
+        verify_proof(&pvk, proof, &public_inputs)
+            .map_err(|err| Error::Unsatisfied(format!("The proof is not valid: {}", err)))
+    }
+}
+
+pub fn write_parameters(params: &Parameters<Bls12>, path: impl AsRef<Path>) -> Result<()> {
+    params.write(BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+pub fn read_parameters(path: impl AsRef<Path>) -> Result<Parameters<Bls12>> {
+    Ok(Parameters::read(BufReader::new(File::open(path)?), true)?)
+}
+
+pub fn write_proof(proof: &Proof<Bls12>, path: impl AsRef<Path>) -> Result<()> {
+    proof.write(BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+pub fn read_proof(path: impl AsRef<Path>) -> Result<Proof<Bls12>> {
+    Ok(Proof::read(BufReader::new(File::open(path)?))?)
+}
+
+fn ensure_field(header: &CircuitHeader) -> Result<()> {
+    let max = header.field_maximum.as_ref().ok_or_else(|| Error::Field("No field_maximum specified".into()))?;
+    if trim_zeros(max) != trim_zeros(&Groth16Statement::field_maximum()) {
+        return Err(Error::Field("The Groth16 backend requires the scalar field of BLS12-381 (see Groth16Statement::field_maximum).".into()));
+    }
+    Ok(())
+}
+
+/// Decode a little-endian, possibly truncated, field element.
+fn decode_scalar(encoded: &[u8]) -> Result<Scalar> {
+    let encoded = trim_zeros(encoded);
+    let mut repr = <Scalar as PrimeField>::Repr::default();
+    if encoded.len() > repr.len() {
+        return Err(Error::Field("Value too large for the scalar field of BLS12-381.".into()));
+    }
+    repr[..encoded.len()].copy_from_slice(encoded);
+    Option::from(Scalar::from_repr(repr))
+        .ok_or_else(|| Error::Field("Value too large for the scalar field of BLS12-381.".into()))
+}
+
+struct SynthesizedCircuit<'a> {
+    header: &'a CircuitHeader,
+    constraints: &'a [ConstraintSystem],
+    witness: Option<&'a HashMap<u64, Vec<u8>>>,
+}
+
+impl<'a> Circuit<Scalar> for SynthesizedCircuit<'a> {
+    fn synthesize<CS: BellmanCS<Scalar>>(self, cs: &mut CS) -> std::result::Result<(), SynthesisError> {
+        let mut vars = HashMap::<u64, BellmanVar>::new();
+        vars.insert(0, CS::one());
+
+        // Instance variables are allocated as public inputs, in the order of the header.
+        for var in self.header.instance_variables.get_variables() {
+            let value = var.value;
+            let allocated = cs.alloc_input(
+                || format!("instance_{}", var.id),
+                || decode_scalar(value).map_err(|_| SynthesisError::AssignmentMissing),
+            )?;
+            vars.insert(var.id, allocated);
+        }
+
+        for (i, constraint) in self.constraints.iter().flat_map(|cs| &cs.constraints).enumerate() {
+            let a = self.linear_combination(cs, &mut vars, &constraint.linear_combination_a)?;
+            let b = self.linear_combination(cs, &mut vars, &constraint.linear_combination_b)?;
+            let c = self.linear_combination(cs, &mut vars, &constraint.linear_combination_c)?;
+            cs.enforce(|| format!("constraint_{}", i), |_| a, |_| b, |_| c);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SynthesizedCircuit<'a> {
+    fn linear_combination<CS: BellmanCS<Scalar>>(
+        &self,
+        cs: &mut CS,
+        vars: &mut HashMap<u64, BellmanVar>,
+        terms: &Variables,
+    ) -> std::result::Result<LinearCombination<Scalar>, SynthesisError> {
+        let mut lc = LinearCombination::zero();
+        for term in terms.get_variables() {
+            let coeff = decode_scalar(term.value).map_err(|_| SynthesisError::Unsatisfiable)?;
+
+            let var = match vars.get(&term.id) {
+                Some(var) => *var,
+                None => {
+                    // Witness variables are allocated on first use.
+                    let witness = self.witness;
+                    let id = term.id;
+                    let var = cs.alloc(
+                        || format!("witness_{}", id),
+                        || {
+                            let value = witness
+                                .and_then(|w| w.get(&id))
+                                .ok_or(SynthesisError::AssignmentMissing)?;
+                            decode_scalar(value).map_err(|_| SynthesisError::AssignmentMissing)
+                        },
+                    )?;
+                    vars.insert(id, var);
+                    var
+                }
+            };
+            lc = lc + (coeff, var);
+        }
+        Ok(lc)
+    }
+}
+
+
+#[test]
+fn test_groth16() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let header = example_circuit_header_in_field(Groth16Statement::field_maximum());
+    let witness = example_witness().assigned_variables.get_variables().iter()
+        .map(|v| (v.id, v.value.to_vec())).collect();
+
+    let statement = Groth16Statement {
+        header: Some(header.clone()),
+        constraints: vec![example_constraints()],
+        witness,
+    };
+
+    let params = statement.setup()?;
+    let proof = statement.prove(&params)?;
+    Groth16Statement::verify(&header, &params, &proof)?;
+
+    // Wrong instance values must be rejected.
+    let wrong_header = header.clone().with_instance_values(Variables {
+        variable_ids: vec![1, 2, 3],
+        values: Some(serialize_small(&[3, 4, 26])),
+        info: None,
+    })?;
+    assert!(Groth16Statement::verify(&wrong_header, &params, &proof).is_err());
+
+    // Other fields are not supported.
+    let other_field = Groth16Statement { header: Some(example_circuit_header()), ..statement };
+    assert!(other_field.setup().is_err());
+
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, BilinearConstraint, Variables, Message, Field};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use serde::Serialize;
+
+type Var = u64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Unusual, but often intended.
+    Info,
+    /// Probably a mistake, or wasted work.
+    Warning,
+    /// A likely soundness problem, or a statement that cannot be true.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A finding of the Linter, about a constraint, a variable, or both.
+/// Constraints are numbered from 0 in the order of all messages.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub constraint: Option<u64>,
+    pub variable: Option<Var>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// A normalized constraint, with A and B in a canonical order, to find duplicates.
+type ConstraintKey = [(Vec<Var>, Vec<u8>); 3];
+
+/// Linter finds suspicious constraints and variables in a statement:
+/// - private variables that no constraint ties down, a soundness red flag (error),
+/// - constraints that never hold (error),
+/// - duplicate constraints, and constraints that always hold such as `0 * x = 0` (warning),
+/// - instance variables that never appear, and private variables that appear in only one constraint (warning),
+/// - instance variables that appear in only one constraint (info).
+///
+/// Constraints are compared after normalization (see `Variables::normalize`), with A and B in any order.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::lint::{Linter, Severity};
+/// use zkinterface::producers::examples::*;
+///
+/// let mut linter = Linter::default();
+/// linter.ingest_header(&example_circuit_header()).unwrap();
+/// linter.ingest_witness(&example_witness()).unwrap();
+/// linter.ingest_constraint_system(&example_constraints()).unwrap();
+/// linter.ingest_constraint_system(&example_constraints()).unwrap();
+///
+/// let findings = linter.get_findings();
+/// assert_eq!(findings[0].constraint, Some(3));
+/// assert_eq!(findings[0].severity, Severity::Warning);
+/// assert_eq!(findings[0].message, "Constraint #3 is a duplicate of constraint #0.");
+/// ```
+#[derive(Clone, Default)]
+pub struct Linter {
+    header: Option<CircuitHeader>,
+    field: Option<Field>,
+    witness_ids: BTreeSet<Var>,
+    constraint_count: u64,
+    /// For each variable, the number of constraints where it appears, and the first of them.
+    occurrences: HashMap<Var, (u64, u64)>,
+    seen: HashMap<ConstraintKey, u64>,
+    findings: Vec<Finding>,
+}
+
+impl Linter {
+    pub fn ingest_message(&mut self, msg: &Message) -> Result<()> {
+        match msg {
+            Message::Header(h) => self.ingest_header(h)?,
+            Message::ConstraintSystem(cs) => self.ingest_constraint_system(cs)?,
+            Message::Witness(w) => self.ingest_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.clone()),
+        }
+        Ok(())
+    }
+
+    pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
+        if self.header.is_some() {
+            return Err(Error::MultipleHeaders);
+        }
+        self.field = Some(Field::from_header(header)?);
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        self.ensure_header()?;
+        self.witness_ids.extend(&witness.assigned_variables.variable_ids);
+        Ok(())
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
+        let field = self.field.clone().ok_or(Error::MissingHeader)?;
+        for constraint in &system.constraints {
+            self.ingest_constraint(&field, constraint);
+        }
+        Ok(())
+    }
+
+    fn ingest_constraint(&mut self, field: &Field, constraint: &BilinearConstraint) {
+        let index = self.constraint_count;
+        self.constraint_count += 1;
+
+        let normalized = constraint.normalize(field);
+        let (a, b, c) = (&normalized.linear_combination_a, &normalized.linear_combination_b, &normalized.linear_combination_c);
+
+        // Occurrences of variables, once per constraint.
+        let ids: HashSet<Var> = a.variable_ids.iter().chain(&b.variable_ids).chain(&c.variable_ids).cloned().collect();
+        for id in ids {
+            let entry = self.occurrences.entry(id).or_insert((0, index));
+            entry.0 += 1;
+        }
+
+        // Constraints that hold or fail regardless of the values.
+        let is_zero = |lc: &Variables| lc.variable_ids.is_empty();
+        let is_constant = |lc: &Variables| lc.variable_ids.iter().all(|&id| id == 0);
+        if (is_zero(a) || is_zero(b)) && is_zero(c) {
+            self.found(Severity::Warning, Some(index), None,
+                       format!("Constraint #{} always holds, because it is 0 = 0.", index));
+        } else if is_constant(a) && is_constant(b) && is_constant(c) {
+            let constant = |lc: &Variables| lc.get_variables().first()
+                .map_or_else(|| field.zero(), |term| field.decode(term.value));
+            if field.mul(&constant(a), &constant(b)) == constant(c) {
+                self.found(Severity::Warning, Some(index), None,
+                           format!("Constraint #{} always holds, because it involves only constants.", index));
+            } else {
+                self.found(Severity::Error, Some(index), None,
+                           format!("Constraint #{} never holds, because it involves only constants that do not match.", index));
+            }
+        }
+
+        // Duplicates.
+        let key_of = |lc: &Variables| (lc.variable_ids.clone(), lc.values.clone().unwrap_or_default());
+        let (key_a, key_b) = (key_of(a), key_of(b));
+        let key = if key_a <= key_b { [key_a, key_b, key_of(c)] } else { [key_b, key_a, key_of(c)] };
+        match self.seen.get(&key) {
+            Some(&first) => self.found(Severity::Warning, Some(index), None,
+                                       format!("Constraint #{} is a duplicate of constraint #{}.", index, first)),
+            None => { self.seen.insert(key, index); }
+        }
+    }
+
+    /// Finish the analysis of variables, and return all findings.
+    pub fn get_findings(mut self) -> Vec<Finding> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => return vec![Finding { severity: Severity::Error, constraint: None, variable: None, message: Error::MissingHeader.to_string() }],
+        };
+        let instance_ids: BTreeSet<Var> = header.instance_variables.variable_ids.iter().cloned().collect();
+        let mut private_ids: BTreeSet<Var> = header.list_witness_ids().into_iter().collect();
+        private_ids.extend(self.witness_ids.iter().filter(|id| !instance_ids.contains(id)));
+
+        for &id in &instance_ids {
+            match self.occurrences.get(&id) {
+                None => self.found(Severity::Warning, None, Some(id),
+                                   format!("The instance variable_{} does not appear in any constraint.", id)),
+                Some(&(1, first)) => self.found(Severity::Info, Some(first), Some(id),
+                                                format!("The instance variable_{} appears only in constraint #{}.", id, first)),
+                Some(_) => {}
+            }
+        }
+        for &id in &private_ids {
+            match self.occurrences.get(&id) {
+                None => self.found(Severity::Error, None, Some(id),
+                                   format!("The private variable_{} is not constrained by any constraint.", id)),
+                Some(&(1, first)) => self.found(Severity::Warning, Some(first), Some(id),
+                                                format!("The private variable_{} appears only in constraint #{}.", id, first)),
+                Some(_) => {}
+            }
+        }
+        self.findings
+    }
+
+    fn found(&mut self, severity: Severity, constraint: Option<u64>, variable: Option<Var>, message: String) {
+        self.findings.push(Finding { severity, constraint, variable, message });
+    }
+
+    fn ensure_header(&self) -> Result<()> {
+        match self.header {
+            None => Err(Error::MissingHeader),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+
+#[test]
+fn test_linter() -> Result<()> {
+    use crate::producers::examples::*;
+
+    // Constraints before the header are an error.
+    let mut linter = Linter::default();
+    assert_eq!(linter.ingest_constraint_system(&example_constraints()), Err(Error::MissingHeader));
+
+    let header = CircuitHeader { free_variable_id: 8, ..example_circuit_header() };
+    linter.ingest_header(&header)?;
+    linter.ingest_witness(&example_witness())?;
+    linter.ingest_constraint_system(&example_constraints())?;
+
+    let constraints: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        // y * y = yy, again with A and B swapped and an explicit zero term.
+        ((vec![2, 6], vec![1, 0]), (vec![2], vec![1]), (vec![5], vec![1])),
+        // 0 * y = 0
+        ((vec![], vec![]), (vec![2], vec![1]), (vec![], vec![])),
+        // 1 * 1 = 2
+        ((vec![0], vec![1]), (vec![0], vec![1]), (vec![0], vec![2])),
+    ];
+    linter.ingest_constraint_system(&ConstraintSystem::from(constraints))?;
+
+    let findings: Vec<String> = linter.get_findings().iter().map(|f| f.to_string()).collect();
+    assert_eq!(findings, vec![
+        "warning: Constraint #3 is a duplicate of constraint #1.",
+        "warning: Constraint #4 always holds, because it is 0 = 0.",
+        "error: Constraint #5 never holds, because it involves only constants that do not match.",
+        "info: The instance variable_1 appears only in constraint #0.",
+        "info: The instance variable_3 appears only in constraint #2.",
+        "error: The private variable_6 is not constrained by any constraint.",
+        "error: The private variable_7 is not constrained by any constraint.",
+    ]);
+
+    Ok(())
+}
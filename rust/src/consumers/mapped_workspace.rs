@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use flatbuffers::SIZE_UOFFSET;
+use memmap2::Mmap;
+
+use crate::consumers::reader::read_size_prefix;
+use crate::consumers::verifier::verify_message;
+use crate::consumers::workspace::{list_workspace_files, sort_workspace_files, MessageOrigin};
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::{Result, Error};
+
+
+/// MappedWorkspace reads zkInterface messages from memory-mapped files, without copying them.
+/// Messages are given as FlatBuffers views borrowed from the mapped files,
+/// which `Validator`, `Simulator`, and `Stats` consume with their `ingest_root` methods.
+///
+/// Unlike `Workspace`, it does not support stdin.
+/// An invalid or truncated message is returned as an error, which ends the iteration of its file.
+///
+/// # Example
+/// ```
+/// use zkinterface::{WorkspaceSink, Sink};
+/// use zkinterface::consumers::mapped_workspace::MappedWorkspace;
+/// use zkinterface::consumers::simulator::Simulator;
+/// use zkinterface::producers::examples::*;
+/// use std::path::PathBuf;
+///
+/// let dir = PathBuf::from("local/test_mapped_workspace");
+/// let mut sink = WorkspaceSink::new(&dir).unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_witness(example_witness()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let ws = MappedWorkspace::from_dir(&dir).unwrap();
+/// let mut simulator = Simulator::default();
+/// for root in ws.iter_roots() {
+///     simulator.ingest_root(&root.unwrap());
+/// }
+/// assert_eq!(simulator.get_violations(), Vec::<String>::new());
+/// ```
+pub struct MappedWorkspace {
+    files: Vec<(PathBuf, Mmap)>,
+}
+
+impl MappedWorkspace {
+    pub fn from_dir(path: &Path) -> Result<Self> {
+        Self::from_dirs_and_files(&[path.to_path_buf()])
+    }
+
+    pub fn from_dirs_and_files(paths: &[PathBuf]) -> Result<Self> {
+        let mut all_files = list_workspace_files(paths)?;
+        if all_files.iter().any(|path| path == Path::new("-")) {
+            return Err(Error::InvalidArgument("Cannot memory-map stdin".into()));
+        }
+        sort_workspace_files(&mut all_files);
+
+        let mut files = Vec::with_capacity(all_files.len());
+        for path in all_files {
+            let file = File::open(&path)
+                .map_err(|err| Error::Io(io::Error::new(err.kind(), format!("Error opening workspace file {}: {}", path.display(), err))))?;
+            // Safety: the files must not be modified while they are mapped.
+            let mmap = unsafe { Mmap::map(&file)? };
+            files.push((path, mmap));
+        }
+        Ok(MappedWorkspace { files })
+    }
+
+    pub fn iter_roots<'w>(&'w self) -> impl Iterator<Item=Result<fb::Root<'w>>> + 'w {
+        self.iter_roots_with_origin().map(|res| res.map(|(_, root)| root))
+    }
+
+    /// Like `iter_roots`, but also tell where each message comes from.
+    pub fn iter_roots_with_origin<'w>(&'w self) -> impl Iterator<Item=Result<(MessageOrigin, fb::Root<'w>)>> + 'w {
+        self.files.iter().flat_map(|(path, mmap)|
+            iterate_mapped_file(path, mmap).enumerate().map(move |(index, buffer)|
+                buffer.map(|buffer| (MessageOrigin { path: Some(path.clone()), index },
+                                     fb::get_size_prefixed_root_as_root(buffer)))))
+    }
+}
+
+/// Split a mapped file into size-prefixed messages. Stop at an end marker, or after an error for an invalid message.
+fn iterate_mapped_file<'a>(path: &'a Path, mut buf: &'a [u8]) -> impl Iterator<Item=Result<&'a [u8]>> + 'a {
+    iter::from_fn(move || {
+        let size = read_size_prefix(buf);
+        if size <= SIZE_UOFFSET { return None; }
+        if let Err(err) = verify_message(buf) {
+            buf = &[];
+            let context = |msg| format!("{}: {}", path.display(), msg);
+            return Some(Err(match err {
+                Error::Framing(msg) => Error::Framing(context(msg)),
+                Error::InvalidMessage(msg) => Error::InvalidMessage(context(msg)),
+                err => err,
+            }));
+        }
+        let (msg, rest) = buf.split_at(size);
+        buf = rest;
+        Some(Ok(msg))
+    })
+}
+
+
+#[test]
+fn test_mapped_workspace() -> Result<()> {
+    use std::fs::{create_dir_all, write};
+    use crate::Workspace;
+    use crate::consumers::stats::Stats;
+    use crate::consumers::validator::Validator;
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_mapped_workspace_truncated");
+    create_dir_all(&dir)?;
+    let mut buf = vec![];
+    example_circuit_header().write_into(&mut buf)?;
+    example_witness().write_into(&mut buf)?;
+    example_constraints().write_into(&mut buf)?;
+    write(dir.join("main.zkif"), &buf)?;
+
+    let ws = MappedWorkspace::from_dir(&dir)?;
+    let origins = ws.iter_roots_with_origin().map(|res| res.map(|(origin, _)| origin.index)).collect::<Result<Vec<usize>>>()?;
+    assert_eq!(origins, vec![0, 1, 2]);
+
+    let mut validator = Validator::new_as_prover();
+    let mut mapped_stats = Stats::default();
+    for root in ws.iter_roots() {
+        let root = root?;
+        validator.ingest_root(&root);
+        mapped_stats.ingest_root(&root);
+    }
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    let mut stats = Stats::default();
+    stats.ingest_workspace(&Workspace::from_dir(&dir)?);
+    assert_eq!(mapped_stats, stats);
+
+    // A truncated message at the end is an error.
+    let mut truncated = vec![];
+    example_constraints().write_into(&mut truncated)?;
+    buf.extend_from_slice(&truncated[..truncated.len() / 2]);
+    write(dir.join("main.zkif"), &buf)?;
+
+    let ws = MappedWorkspace::from_dir(&dir)?;
+    let results: Vec<Result<usize>> = ws.iter_roots_with_origin().map(|res| res.map(|(origin, _)| origin.index)).collect();
+    assert_eq!(results.len(), 4);
+    assert!(results[..3].iter().all(|res| res.is_ok()));
+    assert!(matches!(&results[3], Err(Error::Framing(msg)) if msg.contains("main.zkif")));
+
+    let mut simulator = Simulator::default();
+    simulator.ingest_workspace_parallel(&ws);
+    assert!(simulator.get_violations().iter().any(|violation| violation.contains("main.zkif")));
+
+    assert!(MappedWorkspace::from_dirs_and_files(&[PathBuf::from("-")]).is_err());
+    Ok(())
+}
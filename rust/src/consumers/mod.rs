@@ -3,3 +3,13 @@ pub mod simulator;
 pub mod stats;
 pub mod reader;
 pub mod workspace;
+pub mod mapped_workspace;
+pub mod solver;
+pub mod diff;
+pub mod fingerprint;
+pub mod lint;
+pub mod determinism;
+
+#[cfg(feature = "groth16")]
+pub mod groth16;
+pub mod verifier;
@@ -4,18 +4,21 @@ use flatbuffers::{read_scalar_at, SIZE_UOFFSET, UOffsetT};
 use std::collections::HashMap;
 use std::fmt;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, ErrorKind};
 use std::path::Path;
 
 use crate::zkinterface_generated::zkinterface as fb;
-use crate::Result;
+use crate::{Result, Error};
+use crate::consumers::verifier::{Limits, verify_message};
 
 pub fn read_circuit_header(msg: &[u8]) -> Result<fb::CircuitHeader> {
+    verify_message(msg)?;
     fb::get_size_prefixed_root_as_root(msg)
-        .message_as_circuit_header().ok_or("Not a CircuitHeader message".into())
+        .message_as_circuit_header().ok_or_else(|| Error::InvalidMessage("Not a CircuitHeader message".into()))
 }
 
 pub fn parse_header(msg: &[u8]) -> Option<(fb::CircuitHeader, Vec<Variable>)> {
+    verify_message(msg).ok()?;
     let header = fb::get_size_prefixed_root_as_root(msg).message_as_circuit_header()?;
     let input_var_ids = header.instance_variables()?.variable_ids()?.safe_slice();
 
@@ -55,14 +58,20 @@ pub fn split_messages(mut buf: &[u8]) -> Vec<&[u8]> {
     let mut bufs = vec![];
     loop {
         let size = read_size_prefix(buf);
-        if size <= SIZE_UOFFSET { break; }
+        if size <= SIZE_UOFFSET || size > buf.len() { break; }
         bufs.push(&buf[..size]);
         buf = &buf[size..];
     }
     bufs
 }
 
-pub fn read_buffer(mut stream: impl Read) -> Result<Vec<u8>> {
+pub fn read_buffer(stream: impl Read) -> Result<Vec<u8>> {
+    read_buffer_with_limits(stream, &Limits::default())
+}
+
+/// Read one size-prefixed message, refusing sizes beyond `limits.max_message_size`.
+/// The content of the message is not verified.
+pub fn read_buffer_with_limits(mut stream: impl Read, limits: &Limits) -> Result<Vec<u8>> {
     let mut buffer = vec![0u8; 4];
     if stream.read_exact(&mut buffer).is_err() {
         return Ok(Vec::new()); // End of stream at the correct place.
@@ -72,8 +81,12 @@ pub fn read_buffer(mut stream: impl Read) -> Result<Vec<u8>> {
     if size <= SIZE_UOFFSET {
         return Ok(Vec::new()); // Explicit size 0 as end marker.
     }
+    limits.check_size(size)?;
     buffer.resize(size, 0);
-    stream.read_exact(&mut buffer[4..])?;
+    stream.read_exact(&mut buffer[4..]).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => Error::Framing(format!("message of {} bytes is truncated", size)),
+        _ => Error::Io(err),
+    })?;
     //eprintln!("Read buffer: {:?}", buffer);
     Ok(buffer)
 }
@@ -159,7 +172,13 @@ impl Reader {
         }
     }
 
+    /// Add a buffer of one or more messages, after verifying that they are well-formed.
     pub fn push_message(&mut self, buf: Vec<u8>) -> Result<()> {
+        let mut rest = &buf[..];
+        while read_size_prefix(rest) > SIZE_UOFFSET {
+            verify_message(rest)?;
+            rest = &rest[read_size_prefix(rest)..];
+        }
         self.messages.push(buf);
         Ok(())
     }
@@ -265,6 +284,13 @@ pub fn collect_instance_variables<'a>(conn: &fb::Variables<'a>, first_id: u64) -
     Some(vars)
 }
 
+/// The variables of a `Variables` table, borrowed from the message. Missing tables are empty.
+pub fn fb_variables<'a>(variables: Option<fb::Variables<'a>>) -> Vec<Variable<'a>> {
+    variables
+        .and_then(|variables| collect_instance_variables(&variables, 0))
+        .unwrap_or_default()
+}
+
 pub fn collect_unassigned_private_variables<'a>(instance_variables: &fb::Variables<'a>, first_id: u64, free_id: u64) -> Option<Vec<Variable<'a>>> {
     let var_ids = instance_variables.variable_ids()?.safe_slice();
 
@@ -375,8 +401,8 @@ impl<'a> Iterator for R1CSIterator<'a> {
             let message = self.messages_iter.next()?;
 
             // Parse the message, skip irrelevant message types, or fail if invalid.
-            let constraints = match message.message_as_constraint_system() {
-                Some(message) => message.constraints().unwrap(),
+            let constraints = match message.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+                Some(constraints) => constraints,
                 None => continue,
             };
 
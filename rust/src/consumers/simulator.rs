@@ -1,23 +1,94 @@
-use crate::{Result, CircuitHeader, Witness, ConstraintSystem, Variables, Message};
-use crate::structs::constraints::BilinearConstraint;
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, Message, Field, FieldElement, KeyValue};
+use crate::structs::variables::names_in_info;
+use crate::consumers::reader::{Variable, fb_variables};
+use crate::consumers::workspace::MessageOrigin;
+use crate::consumers::mapped_workspace::MappedWorkspace;
+use crate::zkinterface_generated::zkinterface as fb;
 
 use std::collections::HashMap;
-use num_bigint::BigUint;
-use num_traits::identities::{Zero, One};
+use rayon::prelude::*;
+use std::fmt;
+use serde::Serialize;
 
 type Var = u64;
-type Field = BigUint;
+
+/// The number of constraints checked by one task in parallel mode.
+const PARALLEL_CHUNK_SIZE: usize = 1 << 14;
 
 #[derive(Clone, Default)]
 pub struct Simulator {
-    values: HashMap<Var, Field>,
-    modulus: Field,
+    values: HashMap<Var, FieldElement>,
+    field: Option<Field>,
 
     verified_at_least_one_constraint: bool,
     found_error: Option<String>,
+
+    // Diagnostic mode.
+    diagnostic: bool,
+    constraint_count: u64,
+    origin: Option<MessageOrigin>,
+    names: HashMap<Var, String>,
+    unsatisfied: Vec<UnsatisfiedConstraint>,
+}
+
+/// A report of a constraint that is not satisfied, found in diagnostic mode.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct UnsatisfiedConstraint {
+    /// The position of the constraint in the whole statement, starting from 0.
+    pub index: u64,
+    /// The message containing the constraint, if known.
+    pub origin: Option<MessageOrigin>,
+    /// The position of the constraint within its message.
+    pub index_in_message: usize,
+    /// The values of the linear combinations A, B, C in decimal, if all variables have values.
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub c: Option<String>,
+    /// The variables used by the constraint.
+    pub variables: Vec<VariableReport>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct VariableReport {
+    pub id: u64,
+    /// The name of the variable, if given in `Variables.info`.
+    pub name: Option<String>,
+    /// The value in decimal, if any.
+    pub value: Option<String>,
+}
+
+impl fmt::Display for UnsatisfiedConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Constraint #{} ", self.index)?;
+        if let Some(origin) = &self.origin {
+            write!(f, "({}, constraint {}) ", origin, self.index_in_message)?;
+        }
+        match (&self.a, &self.b, &self.c) {
+            (Some(a), Some(b), Some(c)) =>
+                write!(f, "is not satisfied: A * B = {} * {} but C = {}", a, b, c)?,
+            _ => write!(f, "cannot be evaluated because some variables have no value")?,
+        }
+        for var in &self.variables {
+            write!(f, "\n    variable_{}", var.id)?;
+            if let Some(name) = &var.name {
+                write!(f, " ({})", name)?;
+            }
+            match &var.value {
+                Some(value) => write!(f, " = {}", value)?,
+                None => write!(f, " has no value")?,
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Simulator {
+    /// A simulator that checks all constraints and reports every unsatisfied one,
+    /// instead of stopping at the first failure.
+    pub fn new_diagnostic() -> Simulator {
+        Simulator { diagnostic: true, ..Self::default() }
+    }
+
     pub fn get_violations(self) -> Vec<String> {
         let mut violations = vec![];
         if !self.verified_at_least_one_constraint {
@@ -26,9 +97,24 @@ impl Simulator {
         if let Some(err) = self.found_error {
             violations.push(err);
         }
+        for unsatisfied in &self.unsatisfied {
+            violations.push(unsatisfied.to_string());
+        }
         violations
     }
 
+    /// The constraints found to be unsatisfied in diagnostic mode.
+    pub fn get_unsatisfied(&self) -> &[UnsatisfiedConstraint] {
+        &self.unsatisfied
+    }
+
+    /// Like `ingest_message`, and remember the origin of the message for diagnostics.
+    pub fn ingest_message_from(&mut self, msg: &Message, origin: &MessageOrigin) {
+        self.origin = Some(origin.clone());
+        self.ingest_message(msg);
+        self.origin = None;
+    }
+
     pub fn ingest_message(&mut self, msg: &Message) {
         if self.found_error.is_some() { return; }
 
@@ -50,14 +136,19 @@ impl Simulator {
     }
 
     pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
-        // Set the field.
-        let max = header.field_maximum.as_ref().ok_or("No field_maximum specified")?;
-        self.modulus = BigUint::from_bytes_le(max) + 1 as u8;
+        let max = header.field_maximum.as_ref().ok_or_else(|| Error::Field("No field_maximum specified".into()))?;
+        self.names.extend(header.instance_variables.get_names());
+        self.ingest_header_parts(max, &header.instance_variables.get_variables())
+    }
 
-        self.set(0, Field::one());
+    fn ingest_header_parts(&mut self, field_maximum: &[u8], instance_variables: &[Variable]) -> Result<()> {
+        // Set the field.
+        let field = Field::from_maximum(field_maximum)?;
+        self.set(0, field.one());
+        self.field = Some(field);
 
         // Set instance variable values.
-        for var in header.instance_variables.get_variables() {
+        for var in instance_variables {
             self.set_encoded(var.id, var.value);
         }
 
@@ -65,9 +156,14 @@ impl Simulator {
     }
 
     pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        self.names.extend(witness.assigned_variables.get_names());
+        self.ingest_assignment(&witness.assigned_variables.get_variables())
+    }
+
+    fn ingest_assignment(&mut self, assigned_variables: &[Variable]) -> Result<()> {
         self.ensure_header()?;
 
-        for var in witness.assigned_variables.get_variables() {
+        for var in assigned_variables {
             self.set_encoded(var.id, var.value);
         }
         Ok(())
@@ -76,61 +172,256 @@ impl Simulator {
     pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
         self.ensure_header()?;
 
-        if system.constraints.len() > 0 {
+        for (index_in_message, constraint) in system.constraints.iter().enumerate() {
+            self.check_constraint(
+                &constraint.linear_combination_a.get_variables(),
+                &constraint.linear_combination_b.get_variables(),
+                &constraint.linear_combination_c.get_variables(),
+                index_in_message,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Like `ingest_message`, for a message borrowed from a FlatBuffers buffer, without copying it.
+    pub fn ingest_root(&mut self, root: &fb::Root) {
+        if self.found_error.is_some() { return; }
+
+        if let Err(err) = self.ingest_root_(root) {
+            self.found_error = Some(err.to_string());
+        }
+    }
+
+    /// Like `ingest_root`, and remember the origin of the message for diagnostics.
+    pub fn ingest_root_from(&mut self, root: &fb::Root, origin: &MessageOrigin) {
+        self.origin = Some(origin.clone());
+        self.ingest_root(root);
+        self.origin = None;
+    }
+
+    fn ingest_root_(&mut self, root: &fb::Root) -> Result<()> {
+        if let Some(header) = root.message_as_circuit_header() {
+            let max = header.field_maximum().ok_or_else(|| Error::Field("No field_maximum specified".into()))?;
+            self.names.extend(fb_names(header.instance_variables()));
+            self.ingest_header_parts(max, &fb_variables(header.instance_variables()))?;
+        } else if let Some(witness) = root.message_as_witness() {
+            self.names.extend(fb_names(witness.assigned_variables()));
+            self.ingest_assignment(&fb_variables(witness.assigned_variables()))?;
+        } else if let Some(system) = root.message_as_constraint_system() {
+            self.ensure_header()?;
+            // A missing vector of constraints is empty, as in `ConstraintSystem::from`.
+            let constraints = match system.constraints() {
+                Some(constraints) => constraints,
+                None => return Ok(()),
+            };
+            for i in 0..constraints.len() {
+                let constraint = constraints.get(i);
+                self.check_constraint(
+                    &fb_variables(constraint.linear_combination_a()),
+                    &fb_variables(constraint.linear_combination_b()),
+                    &fb_variables(constraint.linear_combination_c()),
+                    i,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Simulate a whole workspace using all cores.
+    ///
+    /// The headers and witnesses are ingested first, then the constraints of all messages
+    /// are checked in parallel. Errors and diagnostics are reported in the same order
+    /// as a sequential simulation would. An invalid message is reported as an error.
+    pub fn ingest_workspace_parallel(&mut self, ws: &MappedWorkspace) {
+        let mut systems = vec![];
+        for res in ws.iter_roots_with_origin() {
+            let (origin, root) = match res {
+                Ok(res) => res,
+                Err(err) => {
+                    self.found_error = Some(err.to_string());
+                    return;
+                }
+            };
+            if root.message_type() == fb::Message::ConstraintSystem {
+                systems.push((origin, root));
+            } else {
+                self.ingest_root(&root);
+            }
+        }
+        if self.found_error.is_some() { return; }
+        if let Err(err) = self.ensure_header() {
+            self.found_error = Some(err.to_string());
+            return;
+        }
+
+        // Split the constraints into chunks of similar size, in order.
+        // Each chunk knows the index of the first constraint of its message.
+        let mut chunks = vec![];
+        let mut index = self.constraint_count;
+        for (origin, root) in &systems {
+            let constraints = match root.message_as_constraint_system().unwrap().constraints() {
+                Some(constraints) => constraints,
+                None => continue,
+            };
+            for start in (0..constraints.len()).step_by(PARALLEL_CHUNK_SIZE) {
+                let end = (start + PARALLEL_CHUNK_SIZE).min(constraints.len());
+                chunks.push((origin, root, start..end, index));
+            }
+            index += constraints.len() as u64;
+        }
+        if index > self.constraint_count {
             self.verified_at_least_one_constraint = true;
         }
 
-        for constraint in &system.constraints {
-            self.verify_constraint(constraint)?;
+        let results: Vec<std::result::Result<Vec<UnsatisfiedConstraint>, (u64, String)>> = chunks
+            .into_par_iter()
+            .map(|chunk| {
+                let (origin, root, range, first_index) = chunk;
+                let constraints = root.message_as_constraint_system().unwrap().constraints().unwrap();
+                let mut reports = vec![];
+                for i in range {
+                    let constraint = constraints.get(i);
+                    let place = ConstraintPlace { index: first_index + i as u64, origin: Some(origin), index_in_message: i };
+                    let report = self.evaluate_constraint(
+                        &fb_variables(constraint.linear_combination_a()),
+                        &fb_variables(constraint.linear_combination_b()),
+                        &fb_variables(constraint.linear_combination_c()),
+                        &place,
+                    ).map_err(|err| (place.index, err.to_string()))?;
+                    reports.extend(report);
+                }
+                Ok(reports)
+            })
+            .collect();
+
+        // Report the first error, or all diagnostics, in the order of the workspace.
+        for result in results {
+            match result {
+                Ok(reports) => self.unsatisfied.extend(reports),
+                Err((index, err)) => {
+                    self.constraint_count = index;
+                    self.found_error = Some(err);
+                    return;
+                }
+            }
         }
+        self.constraint_count = index;
+    }
+
+    fn check_constraint(&mut self, a: &[Variable], b: &[Variable], c: &[Variable], index_in_message: usize) -> Result<()> {
+        self.verified_at_least_one_constraint = true;
+        let place = ConstraintPlace { index: self.constraint_count, origin: self.origin.as_ref(), index_in_message };
+        if let Some(report) = self.evaluate_constraint(a, b, c, &place)? {
+            self.unsatisfied.push(report);
+        }
+        self.constraint_count += 1;
         Ok(())
     }
 
-    fn verify_constraint(&mut self, constraint: &BilinearConstraint) -> Result<()> {
-        let a = self.sum_terms(&constraint.linear_combination_a)?;
-        let b = self.sum_terms(&constraint.linear_combination_b)?;
-        let c = self.sum_terms(&constraint.linear_combination_c)?;
-        let ab = (a * b) % &self.modulus;
-        let c = c % &self.modulus;
-        if ab.eq(&c) {
+    /// Check a constraint without changing the state of the simulator.
+    /// In diagnostic mode, return a report if it is not satisfied; otherwise, return an error.
+    fn evaluate_constraint(&self, a: &[Variable], b: &[Variable], c: &[Variable], place: &ConstraintPlace) -> Result<Option<UnsatisfiedConstraint>> {
+        if self.diagnostic {
+            self.diagnose_constraint(a, b, c, place)
+        } else {
+            self.verify_constraint(a, b, c).map(|()| None)
+        }
+    }
+
+    fn diagnose_constraint(&self, a: &[Variable], b: &[Variable], c: &[Variable], place: &ConstraintPlace) -> Result<Option<UnsatisfiedConstraint>> {
+        let lcs = [a, b, c];
+        let sums: Vec<_> = lcs.iter().map(|lc| self.sum_terms(lc).ok()).collect();
+
+        let field = self.field()?;
+        if let [Some(a), Some(b), Some(c)] = &sums[..] {
+            if field.mul(a, b) == *c {
+                return Ok(None);
+            }
+        }
+
+        let mut variables: Vec<VariableReport> = vec![];
+        for term in lcs.iter().flat_map(|lc| lc.iter()) {
+            if variables.iter().any(|v| v.id == term.id) { continue; }
+            variables.push(VariableReport {
+                id: term.id,
+                name: self.names.get(&term.id).cloned(),
+                value: self.values.get(&term.id).map(|v| field.to_biguint(v).to_string()),
+            });
+        }
+
+        let to_decimal = |sum: &Option<FieldElement>| sum.as_ref().map(|v| field.to_biguint(v).to_string());
+        Ok(Some(UnsatisfiedConstraint {
+            index: place.index,
+            origin: place.origin.cloned(),
+            index_in_message: place.index_in_message,
+            a: to_decimal(&sums[0]),
+            b: to_decimal(&sums[1]),
+            c: to_decimal(&sums[2]),
+            variables,
+        }))
+    }
+
+    fn verify_constraint(&self, a: &[Variable], b: &[Variable], c: &[Variable]) -> Result<()> {
+        let a_value = self.sum_terms(a)?;
+        let b_value = self.sum_terms(b)?;
+        let c_value = self.sum_terms(c)?;
+        let ab = self.field()?.mul(&a_value, &b_value);
+        if ab == c_value {
             Ok(())
         } else {
-            Err(format!("Constraint is not satisfied ({:?})", constraint).into())
+            Err(Error::Unsatisfied(format!("Constraint is not satisfied ({:?} * {:?} = {:?})", a, b, c)))
         }
     }
 
-    fn sum_terms(&self, terms: &Variables) -> Result<Field> {
-        let mut sum = Field::zero();
-        for term in terms.get_variables() {
+    fn sum_terms(&self, terms: &[Variable]) -> Result<FieldElement> {
+        let field = self.field()?;
+        let mut sum = field.zero();
+        for term in terms {
             let value = self.get(term.id)?;
-            let coeff = Field::from_bytes_le(term.value);
-            sum += coeff * value;
+            let coeff = field.decode(term.value);
+            sum = field.add(&sum, &field.mul(&coeff, value));
         }
         Ok(sum)
     }
 
     fn set_encoded(&mut self, id: Var, encoded: &[u8]) {
-        self.set(id, Field::from_bytes_le(encoded));
+        if let Some(field) = &self.field {
+            let value = field.decode(encoded);
+            self.set(id, value);
+        }
     }
 
-    fn set(&mut self, id: Var, mut value: Field) {
-        value %= &self.modulus;
+    fn set(&mut self, id: Var, value: FieldElement) {
         self.values.insert(id, value);
     }
 
-    fn get(&self, id: Var) -> Result<&Field> {
+    fn get(&self, id: Var) -> Result<&FieldElement> {
         self.values.get(&id)
-            .ok_or(format!("No value given for variable {}", id).into())
+            .ok_or_else(|| Error::Unsatisfied(format!("No value given for variable {}", id)))
+    }
+
+    fn field(&self) -> Result<&Field> {
+        self.field.as_ref()
+            .ok_or(Error::MissingHeader)
     }
 
     fn ensure_header(&self) -> Result<()> {
-        match self.modulus.is_zero() {
-            true => Err("A header must be provided before other messages.".into()),
-            _ => Ok(()),
-        }
+        self.field().map(|_| ())
     }
 }
 
+/// The position of a constraint, for diagnostics.
+struct ConstraintPlace<'a> {
+    index: u64,
+    origin: Option<&'a MessageOrigin>,
+    index_in_message: usize,
+}
+
+fn fb_names(variables: Option<fb::Variables>) -> HashMap<u64, String> {
+    let info = variables.and_then(|vars| KeyValue::from_vector(vars.info()));
+    names_in_info(info.as_deref().unwrap_or_default())
+}
+
 #[test]
 fn test_simulator() -> Result<()> {
     use crate::producers::examples::*;
@@ -146,3 +437,97 @@ fn test_simulator() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_simulator_diagnostic() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let header = example_circuit_header();
+    let mut witness = example_witness_inputs(3, 5);
+    witness.assigned_variables.set_name(5, "yy");
+    let cs = example_constraints();
+
+    let mut simulator = Simulator::new_diagnostic();
+    simulator.ingest_header(&header)?;
+    simulator.ingest_witness(&witness)?;
+    simulator.ingest_constraint_system(&cs)?;
+
+    // y * y = yy and xx + yy = zz are both violated.
+    let unsatisfied = simulator.get_unsatisfied();
+    assert_eq!(unsatisfied.len(), 2);
+    assert_eq!(unsatisfied[0].index, 1);
+    assert_eq!(unsatisfied[0].a, Some("4".to_string()));
+    assert_eq!(unsatisfied[0].c, Some("25".to_string()));
+    assert_eq!(unsatisfied[0].variables[1].name, Some("yy".to_string()));
+    assert_eq!(unsatisfied[1].index, 2);
+    assert_eq!(simulator.get_violations().len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_simulator_parallel() -> Result<()> {
+    use crate::{Workspace, WorkspaceSink, Sink};
+    use crate::producers::examples::*;
+    use std::path::PathBuf;
+
+    let dir = PathBuf::from("local/test_simulator_parallel");
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_witness(example_witness_inputs(3, 5))?;
+    sink.push_constraints(example_constraints())?;
+    sink.push_constraints(example_constraints())?;
+
+    let mut sequential = Simulator::new_diagnostic();
+    for (origin, msg) in Workspace::from_dir(&dir)?.iter_messages_with_origin() {
+        sequential.ingest_message_from(&msg, &origin);
+    }
+
+    let mut parallel = Simulator::new_diagnostic();
+    parallel.ingest_workspace_parallel(&MappedWorkspace::from_dir(&dir)?);
+
+    assert_eq!(parallel.get_unsatisfied().len(), 4);
+    assert_eq!(parallel.get_unsatisfied(), sequential.get_unsatisfied());
+    assert_eq!(parallel.get_violations(), sequential.get_violations());
+
+    // Without diagnostics, the first failure is reported.
+    let mut parallel = Simulator::default();
+    parallel.ingest_workspace_parallel(&MappedWorkspace::from_dir(&dir)?);
+    assert_eq!(parallel.get_violations().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_simulator_missing_constraints() -> Result<()> {
+    use crate::producers::examples::*;
+    use std::convert::TryFrom;
+    use flatbuffers::FlatBufferBuilder;
+
+    // A ConstraintSystem message without a vector of constraints.
+    let mut builder = FlatBufferBuilder::new();
+    let system = fb::ConstraintSystem::create(&mut builder, &fb::ConstraintSystemArgs { constraints: None, info: None });
+    let root = fb::Root::create(&mut builder, &fb::RootArgs {
+        message_type: fb::Message::ConstraintSystem,
+        message: Some(system.as_union_value()),
+    });
+    builder.finish_size_prefixed(root, None);
+    let buffer = builder.finished_data();
+
+    // Both the owned and the zero-copy paths treat it as empty.
+    let mut owned = Simulator::default();
+    owned.ingest_header(&example_circuit_header())?;
+    owned.ingest_witness(&example_witness())?;
+    owned.ingest_constraint_system(&ConstraintSystem::try_from(buffer)?)?;
+    owned.ingest_constraint_system(&example_constraints())?;
+
+    let mut zero_copy = Simulator::default();
+    zero_copy.ingest_header(&example_circuit_header())?;
+    zero_copy.ingest_witness(&example_witness())?;
+    zero_copy.ingest_root(&fb::get_size_prefixed_root_as_root(buffer));
+    zero_copy.ingest_constraint_system(&example_constraints())?;
+
+    assert_eq!(owned.get_violations(), Vec::<String>::new());
+    assert_eq!(zero_copy.get_violations(), Vec::<String>::new());
+    Ok(())
+}
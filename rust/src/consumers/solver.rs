@@ -0,0 +1,449 @@
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, Variables, Message, Field, FieldElement};
+use crate::structs::constraints::BilinearConstraint;
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::rc::Rc;
+
+type Var = u64;
+
+/// Solver completes a partial witness by propagating values through the constraints.
+///
+/// A variable is solved when a constraint determines it uniquely: the constraint must have
+/// a single unknown variable that appears linearly, either in C when A * B is known,
+/// or in A or B when the other factor is known and non-zero and C is known.
+/// With `solve_linear`, the constraints that are linear in the unknowns are also solved together.
+///
+/// Cloning a Solver is cheap, because the constraints are shared.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::solver::Solver;
+/// use zkinterface::producers::examples::*;
+///
+/// let mut solver = Solver::default();
+/// solver.ingest_header(&example_circuit_header()).unwrap();
+/// solver.ingest_constraint_system(&example_constraints()).unwrap();
+///
+/// // No witness at all: xx and yy are found from x * x = xx and y * y = yy.
+/// assert_eq!(solver.solve().unwrap(), vec![4, 5]);
+/// let witness = solver.get_witness().unwrap();
+/// assert_eq!(witness.assigned_variables.variable_ids, vec![4, 5]);
+/// assert_eq!(witness.assigned_variables.values, Some(vec![9, 16]));
+/// ```
+#[derive(Clone, Default)]
+pub struct Solver {
+    header: Option<CircuitHeader>,
+    field: Option<Field>,
+    values: HashMap<Var, FieldElement>,
+    constraints: Rc<Vec<BilinearConstraint>>,
+}
+
+impl Solver {
+    pub fn ingest_message(&mut self, msg: &Message) -> Result<()> {
+        match msg {
+            Message::Header(h) => self.ingest_header(h)?,
+            Message::ConstraintSystem(cs) => self.ingest_constraint_system(cs)?,
+            Message::Witness(w) => self.ingest_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.clone()),
+        }
+        Ok(())
+    }
+
+    pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
+        if self.header.is_some() {
+            return Err(Error::MultipleHeaders);
+        }
+        let field = Field::from_header(header)?;
+
+        self.values.insert(0, field.one());
+        for var in header.instance_variables.get_variables() {
+            if var.has_value() {
+                self.values.insert(var.id, field.decode(var.value));
+            }
+        }
+
+        self.field = Some(field);
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        let field = self.field()?.clone();
+        for var in witness.assigned_variables.get_variables() {
+            self.values.insert(var.id, field.decode(var.value));
+        }
+        Ok(())
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
+        self.ensure_header()?;
+        Rc::make_mut(&mut self.constraints).extend(system.constraints.iter().cloned());
+        Ok(())
+    }
+
+    /// Solve as many variables as possible. Return the IDs of newly solved variables,
+    /// in the order in which they were found.
+    ///
+    /// Return `Error::Unsatisfied` if a constraint whose variables all have values does not hold.
+    pub fn solve(&mut self) -> Result<Vec<Var>> {
+        let field = self.field()?.clone();
+
+        // Index the constraints in which each variable appears.
+        let mut constraints_of_var = HashMap::<Var, Vec<usize>>::new();
+        for (i, constraint) in self.constraints.iter().enumerate() {
+            for id in constraint_var_ids(constraint) {
+                constraints_of_var.entry(id).or_default().push(i);
+            }
+        }
+
+        let mut solved = vec![];
+        let mut queue: VecDeque<usize> = (0..self.constraints.len()).collect();
+        let mut queued: HashSet<usize> = queue.iter().cloned().collect();
+
+        while let Some(i) = queue.pop_front() {
+            queued.remove(&i);
+
+            if let Some((id, value)) = self.solve_constraint(&field, &self.constraints[i], i)? {
+                self.values.insert(id, value);
+                solved.push(id);
+
+                // Revisit the constraints that may now be solvable.
+                for &j in constraints_of_var.get(&id).into_iter().flatten() {
+                    if queued.insert(j) {
+                        queue.push_back(j);
+                    }
+                }
+            }
+        }
+
+        Ok(solved)
+    }
+
+    /// Like `solve`, and also solve the constraints that are linear in the unknowns together,
+    /// by Gaussian elimination. This finds the variables of systems such as x + y = 3 and x - y = 1,
+    /// where no single constraint determines a variable.
+    pub fn solve_linear(&mut self) -> Result<Vec<Var>> {
+        let field = self.field()?.clone();
+        let mut solved = self.solve()?;
+        loop {
+            let found = self.eliminate(&field);
+            if found.is_empty() { break; }
+            for (id, value) in found {
+                self.values.insert(id, value);
+                solved.push(id);
+            }
+            solved.extend(self.solve()?);
+        }
+        Ok(solved)
+    }
+
+    /// Find the variables that the linear constraints determine, by reducing them to
+    /// reduced row echelon form. A pivot is determined when no free variable remains in its row.
+    fn eliminate(&self, field: &Field) -> Vec<(Var, FieldElement)> {
+        let mut pivots: Vec<(Var, LinearRow)> = vec![];
+
+        for constraint in self.constraints.iter() {
+            let mut row = match self.linear_row(field, constraint) {
+                Some(row) => row,
+                None => continue,
+            };
+            for (pivot, pivot_row) in &pivots {
+                if let Some(coeff) = row.terms.get(pivot).cloned() {
+                    row.sub_scaled(field, pivot_row, &coeff);
+                }
+            }
+            // A row without unknowns is redundant, or inconsistent.
+            let (pivot, coeff) = match row.terms.iter().next() {
+                Some((&pivot, coeff)) => (pivot, coeff.clone()),
+                None => continue,
+            };
+            let inverse = match field.inverse(&coeff) {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            row.scale(field, &inverse);
+            for (_, pivot_row) in &mut pivots {
+                if let Some(coeff) = pivot_row.terms.get(&pivot).cloned() {
+                    pivot_row.sub_scaled(field, &row, &coeff);
+                }
+            }
+            pivots.push((pivot, row));
+        }
+
+        pivots.into_iter()
+            .filter(|(_, row)| row.terms.len() == 1)
+            .map(|(pivot, row)| (pivot, row.constant))
+            .collect()
+    }
+
+    /// Write a constraint as a linear equation of the unknowns, if A or B is known.
+    fn linear_row(&self, field: &Field, constraint: &BilinearConstraint) -> Option<LinearRow> {
+        let a = self.partial_sum(field, &constraint.linear_combination_a);
+        let b = self.partial_sum(field, &constraint.linear_combination_b);
+        let c = self.partial_sum(field, &constraint.linear_combination_c);
+
+        // known * (partial.known + partial terms) = c.known + c terms
+        let (known, partial) = match (a.unknowns.is_empty(), b.unknowns.is_empty()) {
+            (true, _) => (&a.known, &b),
+            (_, true) => (&b.known, &a),
+            _ => return None,
+        };
+        let mut row = LinearRow {
+            terms: BTreeMap::new(),
+            constant: field.sub(&c.known, &field.mul(known, &partial.known)),
+        };
+        for (id, coeff) in &partial.unknowns {
+            row.add_term(field, *id, &field.mul(known, coeff));
+        }
+        for (id, coeff) in &c.unknowns {
+            row.add_term(field, *id, &field.neg(coeff));
+        }
+        Some(row)
+    }
+
+    /// The IDs of the variables that still have no value, among the witness variables declared
+    /// by the header and the variables used in constraints.
+    pub fn unsolved(&self) -> Vec<Var> {
+        let mut ids: HashSet<Var> = match &self.header {
+            Some(header) => header.list_witness_ids().into_iter().collect(),
+            None => HashSet::new(),
+        };
+        for constraint in self.constraints.iter() {
+            ids.extend(constraint_var_ids(constraint));
+        }
+        let mut unsolved: Vec<Var> = ids.into_iter()
+            .filter(|id| !self.values.contains_key(id))
+            .collect();
+        unsolved.sort_unstable();
+        unsolved
+    }
+
+    /// The value of a variable, if known.
+    pub fn get_value(&self, id: Var) -> Option<&FieldElement> {
+        self.values.get(&id)
+    }
+
+    /// Set the value of a variable, as if given by a witness. Call `solve` again to propagate it.
+    pub fn assign(&mut self, id: Var, value: FieldElement) {
+        self.values.insert(id, value);
+    }
+
+    /// A witness of all known values, excluding the constant one and the instance variables.
+    pub fn get_witness(&self) -> Result<Witness> {
+        let field = self.field()?;
+        let header = self.header.as_ref().ok_or(Error::MissingHeader)?;
+        let instance_ids: HashSet<Var> = header.instance_variables.variable_ids.iter().cloned().collect();
+
+        let mut ids: Vec<Var> = self.values.keys()
+            .filter(|id| **id != 0 && !instance_ids.contains(id))
+            .cloned().collect();
+        ids.sort_unstable();
+
+        let values: Vec<FieldElement> = ids.iter().map(|id| self.values[id].clone()).collect();
+
+        Ok(Witness {
+            assigned_variables: Variables {
+                variable_ids: ids,
+                values: Some(field.encode_all(&values)),
+                info: None,
+            }
+        })
+    }
+
+    fn solve_constraint(&self, field: &Field, constraint: &BilinearConstraint, index: usize) -> Result<Option<(Var, FieldElement)>> {
+        let a = self.partial_sum(field, &constraint.linear_combination_a);
+        let b = self.partial_sum(field, &constraint.linear_combination_b);
+        let c = self.partial_sum(field, &constraint.linear_combination_c);
+
+        if a.unknowns.is_empty() && b.unknowns.is_empty() && c.unknowns.is_empty() {
+            if field.mul(&a.known, &b.known) != c.known {
+                return Err(Error::Unsatisfied(format!("Constraint #{} does not hold with the given values.", index)));
+            }
+            return Ok(None);
+        }
+        Ok(self.solve_unknown(field, &a, &b, &c))
+    }
+
+    /// Solve the single unknown of a constraint, if it is determined.
+    fn solve_unknown(&self, field: &Field, a: &PartialSum, b: &PartialSum, c: &PartialSum) -> Option<(Var, FieldElement)> {
+        if c.unknowns.len() == 1 {
+            // Solve C when the product A * B is known.
+            let product = match (a.unknowns.is_empty(), b.unknowns.is_empty()) {
+                (true, true) => field.mul(&a.known, &b.known),
+                (true, _) if field.is_zero(&a.known) => field.zero(),
+                (_, true) if field.is_zero(&b.known) => field.zero(),
+                _ => return None,
+            };
+            let (id, coeff) = &c.unknowns[0];
+            if a.contains(*id) || b.contains(*id) { return None; }
+            let value = field.mul(&field.sub(&product, &c.known), &field.inverse(coeff)?);
+            return Some((*id, value));
+        }
+
+        if c.unknowns.is_empty() {
+            // Solve A or B when the other factor is known and non-zero.
+            let (known, partial) = match (a.unknowns.len(), b.unknowns.len()) {
+                (0, 1) => (&a.known, b),
+                (1, 0) => (&b.known, a),
+                _ => return None,
+            };
+            let (id, coeff) = &partial.unknowns[0];
+            let target = field.mul(&c.known, &field.inverse(known)?);
+            let value = field.mul(&field.sub(&target, &partial.known), &field.inverse(coeff)?);
+            return Some((*id, value));
+        }
+
+        None
+    }
+
+    /// Evaluate the known terms of a linear combination, and collect the unknown terms.
+    fn partial_sum(&self, field: &Field, terms: &Variables) -> PartialSum {
+        let mut known = field.zero();
+        let mut unknowns: Vec<(Var, FieldElement)> = vec![];
+
+        for term in terms.get_variables() {
+            let coeff = field.decode(term.value);
+            match self.values.get(&term.id) {
+                Some(value) => known = field.add(&known, &field.mul(&coeff, value)),
+                None => match unknowns.iter_mut().find(|(id, _)| *id == term.id) {
+                    Some((_, sum)) => *sum = field.add(sum, &coeff),
+                    None => unknowns.push((term.id, coeff)),
+                },
+            }
+        }
+        unknowns.retain(|(_, coeff)| !field.is_zero(coeff));
+
+        PartialSum { known, unknowns }
+    }
+
+    fn field(&self) -> Result<&Field> {
+        self.field.as_ref()
+            .ok_or(Error::MissingHeader)
+    }
+
+    fn ensure_header(&self) -> Result<()> {
+        self.field().map(|_| ())
+    }
+}
+
+struct PartialSum {
+    known: FieldElement,
+    unknowns: Vec<(Var, FieldElement)>,
+}
+
+impl PartialSum {
+    fn contains(&self, id: Var) -> bool {
+        self.unknowns.iter().any(|(unknown, _)| *unknown == id)
+    }
+}
+
+/// A linear equation: the sum of the terms equals the constant. Terms have non-zero coefficients.
+struct LinearRow {
+    terms: BTreeMap<Var, FieldElement>,
+    constant: FieldElement,
+}
+
+impl LinearRow {
+    fn add_term(&mut self, field: &Field, id: Var, coeff: &FieldElement) {
+        let sum = match self.terms.get(&id) {
+            Some(existing) => field.add(existing, coeff),
+            None => coeff.clone(),
+        };
+        if field.is_zero(&sum) {
+            self.terms.remove(&id);
+        } else {
+            self.terms.insert(id, sum);
+        }
+    }
+
+    /// Subtract `factor` times another row.
+    fn sub_scaled(&mut self, field: &Field, other: &LinearRow, factor: &FieldElement) {
+        for (id, coeff) in &other.terms {
+            self.add_term(field, *id, &field.neg(&field.mul(coeff, factor)));
+        }
+        self.constant = field.sub(&self.constant, &field.mul(&other.constant, factor));
+    }
+
+    fn scale(&mut self, field: &Field, factor: &FieldElement) {
+        for coeff in self.terms.values_mut() {
+            *coeff = field.mul(coeff, factor);
+        }
+        self.constant = field.mul(&self.constant, factor);
+    }
+}
+
+fn constraint_var_ids(constraint: &BilinearConstraint) -> impl Iterator<Item=Var> + '_ {
+    constraint.linear_combination_a.variable_ids.iter()
+        .chain(constraint.linear_combination_b.variable_ids.iter())
+        .chain(constraint.linear_combination_c.variable_ids.iter())
+        .cloned()
+}
+
+
+#[test]
+fn test_solver() -> Result<()> {
+    use crate::producers::examples::*;
+    use crate::consumers::simulator::Simulator;
+
+    let header = example_circuit_header();
+    let cs = example_constraints();
+
+    // Only xx is given: yy is found through the linear constraint xx + yy = zz.
+    let partial = Witness {
+        assigned_variables: Variables {
+            variable_ids: vec![4],
+            values: Some(vec![9]),
+            info: None,
+        }
+    };
+    // Drop y * y = yy so that only the linear constraint can determine yy.
+    let linear_only = ConstraintSystem { constraints: vec![cs.constraints[0].clone(), cs.constraints[2].clone()] };
+
+    let mut solver = Solver::default();
+    solver.ingest_header(&header)?;
+    solver.ingest_witness(&partial)?;
+    solver.ingest_constraint_system(&linear_only)?;
+    assert_eq!(solver.solve()?, vec![5]);
+    assert!(solver.unsolved().is_empty());
+
+    let witness = solver.get_witness()?;
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&header)?;
+    simulator.ingest_witness(&witness)?;
+    simulator.ingest_constraint_system(&cs)?;
+
+    // A variable that no constraint determines remains unsolved.
+    let mut solver = Solver::default();
+    solver.ingest_header(&CircuitHeader { free_variable_id: 7, ..header })?;
+    solver.ingest_constraint_system(&cs)?;
+    solver.solve()?;
+    assert_eq!(solver.unsolved(), vec![6]);
+
+    // A partial witness that contradicts the constraints is rejected.
+    let mut solver = Solver::default();
+    solver.ingest_header(&example_circuit_header())?;
+    solver.ingest_witness(&Witness {
+        assigned_variables: Variables { variable_ids: vec![4, 5], values: Some(vec![9, 15]), info: None },
+    })?;
+    solver.ingest_constraint_system(&example_constraints())?;
+    assert!(matches!(solver.solve(), Err(Error::Unsatisfied(_))));
+
+    // x + y = 11 and x - y = 5 determine x = 8 and y = 3, but not one at a time.
+    let one = |id| (vec![id], vec![1]);
+    let system: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![4, 5], vec![1, 1]), one(0), (vec![0], vec![11])),
+        ((vec![4, 5], serialize_small(&[1, NEG_ONE])), one(0), (vec![0], vec![5])),
+    ];
+    let mut solver = Solver::default();
+    solver.ingest_header(&example_circuit_header())?;
+    solver.ingest_constraint_system(&ConstraintSystem::from(system))?;
+    assert_eq!(solver.solve()?, Vec::<Var>::new());
+    let mut solved = solver.solve_linear()?;
+    solved.sort_unstable();
+    assert_eq!(solved, vec![4, 5]);
+    assert_eq!(solver.get_value(4), Some(&solver.field()?.from_u64(8)));
+    assert_eq!(solver.get_value(5), Some(&solver.field()?.from_u64(3)));
+
+    Ok(())
+}
@@ -2,9 +2,22 @@ extern crate serde;
 extern crate serde_json;
 
 use serde::{Deserialize, Serialize};
+use num_bigint::BigUint;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
 
-use crate::{Workspace, Message};
+use crate::{Workspace, Message, Field};
+use crate::consumers::workspace::MessageOrigin;
+use crate::consumers::reader::{Variable, fb_variables};
+use crate::zkinterface_generated::zkinterface as fb;
 
+/// Statistics about a statement.
+///
+/// The public inputs are the instance variables of the first header, which is the statement,
+/// or the request of a gadget call. Later headers, such as gadget responses, only extend the
+/// variable space. The private variables are counted up to the highest `free_variable_id`,
+/// or up to the highest variable ID used if `free_variable_id` is not given (0).
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Stats {
     pub num_public_inputs: u64,
@@ -14,6 +27,12 @@ pub struct Stats {
     pub additions_a: u64,
     pub additions_b: u64,
     pub additions_c: u64,
+    pub num_headers: u64,
+
+    #[serde(skip)]
+    free_variable_id: u64,
+    #[serde(skip)]
+    max_variable_id: u64,
 }
 
 impl Stats {
@@ -21,34 +40,444 @@ impl Stats {
         for msg in ws.iter_messages() {
             match msg {
                 Message::Header(header) => {
-                    self.num_public_inputs = header.instance_variables.variable_ids.len() as u64;
-                    self.num_private_variables = header.free_variable_id - self.num_public_inputs - 1;
+                    self.ingest_header_parts(&header.instance_variables.variable_ids, header.free_variable_id);
                 }
 
                 Message::ConstraintSystem(cs) => {
-                    self.multiplications += cs.constraints.len() as u64;
-
                     for constraint in &cs.constraints {
-                        let len_a = constraint.linear_combination_a.variable_ids.len() as u64;
-                        if len_a > 0 {
-                            self.additions_a += len_a - 1;
-                        }
-
-                        let len_b = constraint.linear_combination_b.variable_ids.len() as u64;
-                        if len_b > 0 {
-                            self.additions_b += len_b - 1;
-                        }
-
-                        let len_c = constraint.linear_combination_c.variable_ids.len() as u64;
-                        if len_c > 0 {
-                            self.additions_c += len_c - 1;
-                        }
+                        self.ingest_constraint_terms(
+                            &constraint.linear_combination_a.variable_ids,
+                            &constraint.linear_combination_b.variable_ids,
+                            &constraint.linear_combination_c.variable_ids);
                     }
-                    self.additions = self.additions_a + self.additions_b + self.additions_c;
+                }
+
+                Message::Witness(witness) => {
+                    self.ingest_variable_ids(&witness.assigned_variables.variable_ids);
                 }
 
                 _ => {}
             }
         }
     }
+
+    /// Like `ingest_workspace`, for one message borrowed from a FlatBuffers buffer.
+    pub fn ingest_root(&mut self, root: &fb::Root) {
+        if let Some(header) = root.message_as_circuit_header() {
+            self.ingest_header_parts(fb_ids(header.instance_variables()), header.free_variable_id());
+        }
+
+        if let Some(constraints) = root.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+            for i in 0..constraints.len() {
+                let constraint = constraints.get(i);
+                self.ingest_constraint_terms(
+                    fb_ids(constraint.linear_combination_a()),
+                    fb_ids(constraint.linear_combination_b()),
+                    fb_ids(constraint.linear_combination_c()));
+            }
+        }
+
+        if let Some(witness) = root.message_as_witness() {
+            self.ingest_variable_ids(fb_ids(witness.assigned_variables()));
+        }
+    }
+
+    fn ingest_header_parts(&mut self, instance_ids: &[u64], free_variable_id: u64) {
+        if self.num_headers == 0 {
+            self.num_public_inputs = instance_ids.len() as u64;
+        }
+        self.num_headers += 1;
+        self.free_variable_id = self.free_variable_id.max(free_variable_id);
+        self.ingest_variable_ids(instance_ids);
+    }
+
+    fn ingest_constraint_terms(&mut self, a: &[u64], b: &[u64], c: &[u64]) {
+        self.multiplications += 1;
+        self.additions_a += (a.len() as u64).saturating_sub(1);
+        self.additions_b += (b.len() as u64).saturating_sub(1);
+        self.additions_c += (c.len() as u64).saturating_sub(1);
+        self.additions = self.additions_a + self.additions_b + self.additions_c;
+
+        for ids in &[a, b, c] {
+            self.ingest_variable_ids(ids);
+        }
+    }
+
+    fn ingest_variable_ids(&mut self, ids: &[u64]) {
+        if let Some(&max) = ids.iter().max() {
+            self.max_variable_id = self.max_variable_id.max(max);
+        }
+        // Variables from 1 until free_variable_id, or the highest ID seen, excluding the public inputs.
+        let num_variables = self.free_variable_id.max(self.max_variable_id + 1).saturating_sub(1);
+        self.num_private_variables = num_variables.saturating_sub(self.num_public_inputs);
+    }
+}
+
+/// The variable IDs of a `Variables` table. Missing tables are empty.
+fn fb_ids<'a>(vars: Option<fb::Variables<'a>>) -> &'a [u64] {
+    vars.and_then(|vars| vars.variable_ids())
+        .map_or(&[][..], |ids| ids.safe_slice())
+}
+
+/// The contribution of one message to a statement.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Serialize)]
+pub struct MessageStats {
+    pub origin: MessageOrigin,
+    pub message_type: String,
+    pub constraints: u64,
+    pub terms: u64,
+    pub assigned_variables: u64,
+}
+
+/// The contribution of one file to a statement.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Serialize)]
+pub struct FileStats {
+    /// The file, or None for stdin.
+    pub path: Option<PathBuf>,
+    pub messages: u64,
+    pub constraints: u64,
+    pub terms: u64,
+    pub assigned_variables: u64,
+}
+
+/// Breakdown of the constraints and assignments by file and by message,
+/// to find which parts of a statement are the largest.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Serialize)]
+pub struct Breakdown {
+    pub files: Vec<FileStats>,
+    pub messages: Vec<MessageStats>,
+}
+
+impl Breakdown {
+    pub fn ingest_root_from(&mut self, root: &fb::Root, origin: &MessageOrigin) {
+        let num_terms = |vars: Option<fb::Variables>| vars
+            .and_then(|vars| vars.variable_ids())
+            .map_or(0, |ids| ids.len() as u64);
+
+        let mut msg = MessageStats {
+            origin: origin.clone(),
+            message_type: format!("{:?}", root.message_type()),
+            ..MessageStats::default()
+        };
+        if let Some(constraints) = root.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+            msg.constraints = constraints.len() as u64;
+            for i in 0..constraints.len() {
+                let constraint = constraints.get(i);
+                msg.terms += num_terms(constraint.linear_combination_a())
+                    + num_terms(constraint.linear_combination_b())
+                    + num_terms(constraint.linear_combination_c());
+            }
+        }
+        if let Some(witness) = root.message_as_witness() {
+            msg.assigned_variables = num_terms(witness.assigned_variables());
+        }
+
+        if self.files.last().map(|file| &file.path) != Some(&origin.path) {
+            self.files.push(FileStats { path: origin.path.clone(), ..FileStats::default() });
+        }
+        let file = self.files.last_mut().unwrap();
+        file.messages += 1;
+        file.constraints += msg.constraints;
+        file.terms += msg.terms;
+        file.assigned_variables += msg.assigned_variables;
+
+        self.messages.push(msg);
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total: u64 = self.files.iter().map(|file| file.constraints).sum();
+        let share = |constraints: u64| if total == 0 { 0.0 } else { 100.0 * constraints as f64 / total as f64 };
+        let name = |path: &Option<PathBuf>| path.as_ref().map_or("stdin".to_string(), |path| path.display().to_string());
+
+        writeln!(f, "{:>12}  {:>7}  {:>12}  {:>12}  {:>8}  file", "constraints", "share", "terms", "assignments", "messages")?;
+        for file in &self.files {
+            writeln!(f, "{:>12}  {:>6.1}%  {:>12}  {:>12}  {:>8}  {}",
+                     file.constraints, share(file.constraints), file.terms, file.assigned_variables, file.messages, name(&file.path))?;
+        }
+
+        writeln!(f, "\n{:>12}  {:>7}  {:>12}  {:>12}  {:<16}  message", "constraints", "share", "terms", "assignments", "type")?;
+        for msg in &self.messages {
+            writeln!(f, "{:>12}  {:>6.1}%  {:>12}  {:>12}  {:<16}  {}",
+                     msg.constraints, share(msg.constraints), msg.terms, msg.assigned_variables, msg.message_type, msg.origin)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Extended statistics about the constraints, in addition to `Stats`.
+///
+/// Histograms map a size to the number of times it occurs.
+/// Call `finish` after ingesting all messages to compute the summaries.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::stats::DetailedStats;
+/// use zkinterface::consumers::mapped_workspace::MappedWorkspace;
+/// use zkinterface::{WorkspaceSink, Sink};
+/// use zkinterface::producers::examples::*;
+///
+/// let mut sink = WorkspaceSink::new("local/test_detailed_stats_example").unwrap();
+/// sink.push_header(example_circuit_header()).unwrap();
+/// sink.push_constraints(example_constraints()).unwrap();
+///
+/// let mut stats = DetailedStats::default();
+/// for root in MappedWorkspace::from_dir(&sink.workspace).unwrap().iter_roots() {
+///     stats.ingest_root(&root.unwrap());
+/// }
+/// let stats = stats.finish();
+///
+/// // Two constraints have one term in C, and one has two terms in B.
+/// assert_eq!(stats.lc_lengths_c.get(&1), Some(&3));
+/// assert_eq!(stats.lc_lengths_b.get(&2), Some(&1));
+/// ```
+#[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
+pub struct DetailedStats {
+    #[serde(flatten)]
+    pub stats: Stats,
+    /// Histograms of the number of terms in the linear combinations A, B, and C.
+    pub lc_lengths_a: BTreeMap<u64, u64>,
+    pub lc_lengths_b: BTreeMap<u64, u64>,
+    pub lc_lengths_c: BTreeMap<u64, u64>,
+    /// Histogram of the number of terms in A, B, and C together, a measure of density.
+    pub terms_per_constraint: BTreeMap<u64, u64>,
+    /// Histogram of the number of constraints in which each variable occurs (fan-in), except the constant one.
+    pub variable_occurrences: BTreeMap<u64, u64>,
+    /// The most used variables, as (variable ID, number of constraints), in decreasing order.
+    pub most_used_variables: Vec<(u64, u64)>,
+    /// Constraints that involve only the constant one.
+    pub constant_constraints: u64,
+    /// Constraints of the forms x * (x - 1) = 0 or x * x = x.
+    pub boolean_constraints: u64,
+    /// Histogram of the size of coefficients, in bits.
+    pub coefficient_bits: BTreeMap<u64, u64>,
+    /// Rough estimates of the memory used by common backends, in bytes.
+    pub estimated_memory: BTreeMap<String, u64>,
+
+    #[serde(skip)]
+    field: Option<Field>,
+    #[serde(skip)]
+    occurrences: HashMap<u64, u64>,
+    #[serde(skip)]
+    num_terms: u64,
+}
+
+/// How many of the most used variables are reported.
+const MOST_USED_COUNT: usize = 10;
+
+impl DetailedStats {
+    pub fn ingest_root(&mut self, root: &fb::Root) {
+        self.stats.ingest_root(root);
+
+        if let Some(header) = root.message_as_circuit_header() {
+            self.field = header.field_maximum().and_then(|max| Field::from_maximum(max).ok());
+        }
+
+        if let Some(constraints) = root.message_as_constraint_system().and_then(|cs| cs.constraints()) {
+            for i in 0..constraints.len() {
+                let constraint = constraints.get(i);
+                self.ingest_constraint(
+                    &fb_variables(constraint.linear_combination_a()),
+                    &fb_variables(constraint.linear_combination_b()),
+                    &fb_variables(constraint.linear_combination_c()));
+            }
+        }
+    }
+
+    fn ingest_constraint(&mut self, a: &[Variable], b: &[Variable], c: &[Variable]) {
+        *self.lc_lengths_a.entry(a.len() as u64).or_insert(0) += 1;
+        *self.lc_lengths_b.entry(b.len() as u64).or_insert(0) += 1;
+        *self.lc_lengths_c.entry(c.len() as u64).or_insert(0) += 1;
+        let num_terms = (a.len() + b.len() + c.len()) as u64;
+        *self.terms_per_constraint.entry(num_terms).or_insert(0) += 1;
+        self.num_terms += num_terms;
+
+        let mut ids: Vec<u64> = a.iter().chain(b).chain(c).map(|term| term.id).collect();
+        for term in a.iter().chain(b).chain(c) {
+            let bits = BigUint::from_bytes_le(term.value).bits();
+            *self.coefficient_bits.entry(bits).or_insert(0) += 1;
+        }
+
+        if ids.iter().all(|&id| id == 0) {
+            self.constant_constraints += 1;
+        }
+        if self.is_boolean_check(a, b, c) {
+            self.boolean_constraints += 1;
+        }
+
+        // Count each variable once per constraint, except the constant one.
+        ids.sort_unstable();
+        ids.dedup();
+        for id in ids.into_iter().filter(|&id| id != 0) {
+            *self.occurrences.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    /// Recognize x * (x - 1) = 0, with any scaling and order, and x * x = x.
+    fn is_boolean_check(&self, a: &[Variable], b: &[Variable], c: &[Variable]) -> bool {
+        let field = match &self.field {
+            Some(field) => field,
+            None => return false,
+        };
+        let coeff = |term: &Variable| field.decode(term.value);
+
+        match (a, b, c) {
+            // x * x = x
+            ([ta], [tb], [tc]) if ta.id != 0 && ta.id == tb.id && tb.id == tc.id =>
+                field.mul(&coeff(ta), &coeff(tb)) == coeff(tc),
+            // x * (x - 1) = 0, in any order.
+            ([single], [t1, t2], []) | ([t1, t2], [single], []) => {
+                let (tx, t0) = if t1.id == 0 { (t2, t1) } else { (t1, t2) };
+                single.id != 0 && tx.id == single.id && t0.id == 0
+                    && !field.is_zero(&coeff(tx))
+                    && field.is_zero(&field.add(&coeff(tx), &coeff(t0)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Compute the summaries: variable fan-in, most used variables, and memory estimates.
+    pub fn finish(mut self) -> Self {
+        self.variable_occurrences.clear();
+        for &count in self.occurrences.values() {
+            *self.variable_occurrences.entry(count).or_insert(0) += 1;
+        }
+
+        let mut most_used: Vec<(u64, u64)> = self.occurrences.iter().map(|(&id, &count)| (id, count)).collect();
+        most_used.sort_by(|(id_a, count_a), (id_b, count_b)| count_b.cmp(count_a).then(id_a.cmp(id_b)));
+        most_used.truncate(MOST_USED_COUNT);
+        self.most_used_variables = most_used;
+
+        // Field elements are stored in 64-bit limbs, 256 bits if the field is unknown.
+        let element_size = self.field.as_ref().map_or(32, |field| (field.byte_len() as u64).div_ceil(8) * 8);
+        let num_constraints = self.stats.multiplications;
+        let num_variables = 1 + self.stats.num_public_inputs + self.stats.num_private_variables;
+        self.estimated_memory.clear();
+        self.estimated_memory.insert("r1cs_sparse_matrices".into(), self.num_terms * (8 + element_size));
+        self.estimated_memory.insert("witness_vector".into(), num_variables * element_size);
+        // Uncompressed points of BLS12-381: 96 bytes in G1, 192 bytes in G2.
+        self.estimated_memory.insert("groth16_bls12_381_proving_key".into(),
+                                     96 * (2 * num_variables + num_constraints + self.stats.num_private_variables) + 192 * num_variables);
+        self
+    }
+}
+
+impl fmt::Display for DetailedStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = &self.stats;
+        writeln!(f, "{:<32}{:>12}", "Public inputs", s.num_public_inputs)?;
+        writeln!(f, "{:<32}{:>12}", "Private variables", s.num_private_variables)?;
+        writeln!(f, "{:<32}{:>12}", "Multiplications (constraints)", s.multiplications)?;
+        writeln!(f, "{:<32}{:>12}", "Additions", s.additions)?;
+        writeln!(f, "{:<32}{:>12}", "Constant-only constraints", self.constant_constraints)?;
+        writeln!(f, "{:<32}{:>12}", "Boolean constraints", self.boolean_constraints)?;
+
+        let histogram = |f: &mut fmt::Formatter, title: &str, unit: &str, histogram: &BTreeMap<u64, u64>| -> fmt::Result {
+            writeln!(f, "\n{}", title)?;
+            writeln!(f, "  {:>12}  {:>12}", unit, "count")?;
+            for (size, count) in histogram {
+                writeln!(f, "  {:>12}  {:>12}", size, count)?;
+            }
+            Ok(())
+        };
+        histogram(f, "Terms in A", "terms", &self.lc_lengths_a)?;
+        histogram(f, "Terms in B", "terms", &self.lc_lengths_b)?;
+        histogram(f, "Terms in C", "terms", &self.lc_lengths_c)?;
+        histogram(f, "Terms per constraint", "terms", &self.terms_per_constraint)?;
+        histogram(f, "Constraints per variable", "constraints", &self.variable_occurrences)?;
+        histogram(f, "Coefficient sizes", "bits", &self.coefficient_bits)?;
+
+        writeln!(f, "\nMost used variables")?;
+        writeln!(f, "  {:>12}  {:>12}", "variable", "constraints")?;
+        for (id, count) in &self.most_used_variables {
+            writeln!(f, "  {:>12}  {:>12}", id, count)?;
+        }
+
+        writeln!(f, "\nEstimated memory")?;
+        for (backend, bytes) in &self.estimated_memory {
+            writeln!(f, "  {:<32}{:>12} bytes", backend, bytes)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_detailed_stats() -> crate::Result<()> {
+    use crate::{ConstraintSystem, Sink, WorkspaceSink};
+    use crate::consumers::mapped_workspace::MappedWorkspace;
+    use crate::producers::examples::*;
+    use std::path::PathBuf;
+
+    let workspace = PathBuf::from("local/test_detailed_stats");
+    let _ = std::fs::remove_dir_all(&workspace);
+    let mut sink = WorkspaceSink::new(&workspace)?;
+    sink.push_header(example_circuit_header())?;
+    sink.push_constraints(example_constraints())?;
+
+    // Boolean checks of x: x * (x - 1) = 0, (1 - x) * x = 0, and x * x = x.
+    let booleans: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![1], vec![1]), (vec![0, 1], serialize_small(&[NEG_ONE, 1])), (vec![], vec![])),
+        ((vec![0, 1], serialize_small(&[1, NEG_ONE])), (vec![1], vec![1]), (vec![], vec![])),
+        ((vec![1], vec![1]), (vec![1], vec![1]), (vec![1], vec![1])),
+        ((vec![0], vec![1]), (vec![0], vec![1]), (vec![0], vec![1])),
+    ];
+    sink.push_constraints(ConstraintSystem::from(booleans))?;
+
+    let mut stats = DetailedStats::default();
+    for root in MappedWorkspace::from_dir(&workspace)?.iter_roots() {
+        stats.ingest_root(&root?);
+    }
+    let stats = stats.finish();
+
+    assert_eq!(stats.stats.multiplications, 7);
+    assert_eq!(stats.boolean_constraints, 3);
+    assert_eq!(stats.constant_constraints, 1);
+    assert_eq!(stats.most_used_variables[0], (1, 4));
+    assert_eq!(stats.terms_per_constraint.get(&3), Some(&6));
+    assert!(stats.estimated_memory["witness_vector"] > 0);
+    assert!(stats.to_string().contains("Boolean constraints"));
+
+    Ok(())
+}
+
+#[test]
+fn test_stats_headers() -> crate::Result<()> {
+    use crate::{CircuitHeader, Sink, WorkspaceSink};
+    use crate::consumers::mapped_workspace::MappedWorkspace;
+    use crate::producers::examples::*;
+    use std::path::PathBuf;
+
+    // A header without free_variable_id, and a gadget response.
+    let workspace = PathBuf::from("local/test_stats_headers");
+    let _ = std::fs::remove_dir_all(&workspace);
+    let mut sink = WorkspaceSink::new(&workspace)?;
+    sink.push_header(CircuitHeader { free_variable_id: 0, ..example_circuit_header() })?;
+    sink.push_witness(example_witness())?;
+    sink.push_constraints(example_constraints())?;
+    let mut response = vec![];
+    CircuitHeader::simple_outputs(5, 1, 2).write_into(&mut response)?;
+    std::fs::write(workspace.join("response.zkif"), response)?;
+
+    let mut stats = Stats::default();
+    let mut breakdown = Breakdown::default();
+    for res in MappedWorkspace::from_dir(&workspace)?.iter_roots_with_origin() {
+        let (origin, root) = res?;
+        stats.ingest_root(&root);
+        breakdown.ingest_root_from(&root, &origin);
+    }
+    assert_eq!(stats.num_headers, 2);
+    assert_eq!(stats.num_public_inputs, 3);
+    // Variables up to the free_variable_id 9 of the response.
+    assert_eq!(stats.num_private_variables, 5);
+    assert_eq!(stats.multiplications, 3);
+
+    assert_eq!(breakdown.files.len(), 4);
+    assert_eq!(breakdown.messages.len(), 4);
+    assert_eq!(breakdown.files.iter().map(|file| file.constraints).sum::<u64>(), 3);
+    assert!(breakdown.to_string().contains("constraints_0.zkif"));
+
+    Ok(())
 }
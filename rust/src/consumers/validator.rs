@@ -1,10 +1,11 @@
-use crate::{CircuitHeader, Witness, ConstraintSystem, Variables, Message};
+use crate::{CircuitHeader, Witness, ConstraintSystem, Message, Field};
+use crate::consumers::reader::{Variable, fb_variables};
+use crate::zkinterface_generated::zkinterface as fb;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use num_bigint::BigUint;
 
 type Var = u64;
-type Field = BigUint;
 
 #[derive(Copy, Clone, PartialEq)]
 enum Status {
@@ -15,6 +16,14 @@ enum Status {
 
 use Status::*;
 
+/// The state of a gadget call: the variables of the request, and the bound given by the response.
+#[derive(Clone, Default)]
+struct GadgetFlow {
+    inputs: HashSet<Var>,
+    first_local_id: Var,
+    got_response: bool,
+}
+
 
 #[derive(Clone, Default)]
 pub struct Validator {
@@ -22,8 +31,9 @@ pub struct Validator {
 
     variables: HashMap<Var, Status>,
     got_header: bool,
-    field_maximum: Option<Field>,
+    field: Option<Field>,
     free_variable_id: Option<Var>,
+    gadget: Option<GadgetFlow>,
 
     violations: Vec<String>,
 }
@@ -37,11 +47,28 @@ impl Validator {
         Validator { as_prover: true, ..Self::default() }
     }
 
+    /// Validate a gadget call: the first header is the request, and the second header is the response.
+    /// The request and the response must be ingested before the constraints and witnesses of the gadget.
+    ///
+    /// As a verifier, this corresponds to a call with `constraints_generation`.
+    pub fn new_as_gadget_verifier() -> Validator {
+        Validator { gadget: Some(GadgetFlow::default()), ..Self::default() }
+    }
+
+    /// Like `new_as_gadget_verifier`, for a call with `witness_generation`.
+    /// The inputs and the outputs must carry values, and the witness must assign the local variables.
+    pub fn new_as_gadget_prover() -> Validator {
+        Validator { as_prover: true, gadget: Some(GadgetFlow::default()), ..Self::default() }
+    }
+
     pub fn get_violations(mut self) -> Vec<String> {
         self.ensure_all_variables_used();
         if !self.got_header {
             self.violate("Missing header.");
         }
+        if self.gadget.as_ref().is_some_and(|gadget| !gadget.got_response) {
+            self.violate("Missing gadget response.");
+        }
         self.violations
     }
 
@@ -55,39 +82,138 @@ impl Validator {
         }
     }
 
+    /// Like `ingest_message`, for a message borrowed from a FlatBuffers buffer, without copying it.
+    pub fn ingest_root(&mut self, root: &fb::Root) {
+        match root.message_type() {
+            fb::Message::CircuitHeader => {
+                let header = root.message_as_circuit_header().unwrap();
+                self.ingest_header_parts(
+                    header.field_maximum(),
+                    header.free_variable_id(),
+                    &fb_variables(header.instance_variables()));
+            }
+            fb::Message::ConstraintSystem => {
+                self.ensure_header();
+                let system = root.message_as_constraint_system().unwrap();
+                if let Some(constraints) = system.constraints() {
+                    for i in 0..constraints.len() {
+                        let constraint = constraints.get(i);
+                        self.validate_terms(&fb_variables(constraint.linear_combination_a()));
+                        self.validate_terms(&fb_variables(constraint.linear_combination_b()));
+                        self.validate_terms(&fb_variables(constraint.linear_combination_c()));
+                    }
+                }
+            }
+            fb::Message::Witness => {
+                let witness = root.message_as_witness().unwrap();
+                self.ingest_assignment(&fb_variables(witness.assigned_variables()));
+            }
+            fb::Message::Command => {}
+            fb::Message::NONE => self.violate("Invalid message type"),
+        }
+    }
+
     pub fn ingest_header(&mut self, header: &CircuitHeader) {
+        self.ingest_header_parts(
+            header.field_maximum.as_deref(),
+            header.free_variable_id,
+            &header.instance_variables.get_variables());
+    }
+
+    fn ingest_header_parts(&mut self, field_maximum: Option<&[u8]>, free_variable_id: Var, instance_variables: &[Variable]) {
+        if self.got_header && self.gadget.is_some() {
+            return self.ingest_gadget_response(field_maximum, free_variable_id, instance_variables);
+        }
         if self.got_header {
             self.violate("Multiple headers.");
         }
         self.got_header = true;
 
         // Set the field.
-        if let Some(max) = header.field_maximum.as_ref() {
-            self.field_maximum = Some(BigUint::from_bytes_le(max));
-        } else {
-            self.violate("No field_maximum provided.");
+        match field_maximum {
+            Some(max) => match Field::from_maximum(max) {
+                Ok(field) => self.field = Some(field),
+                Err(err) => self.violate(format!("Invalid field_maximum: {}", err)),
+            },
+            None => self.violate("No field_maximum provided."),
         }
 
         // Set a bound on variable count, if provided.
-        if header.free_variable_id > 0 {
-            self.free_variable_id = Some(header.free_variable_id);
+        // A gadget allocates variables from the free_variable_id of the request, up to the one of the response.
+        if let Some(ref mut gadget) = self.gadget {
+            gadget.inputs = instance_variables.iter().map(|var| var.id).collect();
+            gadget.first_local_id = free_variable_id;
+        } else if free_variable_id > 0 {
+            self.free_variable_id = Some(free_variable_id);
         }
 
         // Constant one with ID 0.
         self.set_status(0, Defined);
 
         // Set instance variable values.
-        for var in header.instance_variables.get_variables() {
-            self.define(var.id, var.value, || format!("value of the instance variable_{}", var.id));
+        for var in instance_variables {
+            if self.gadget.is_some() && !self.as_prover && var.value.is_empty() {
+                // Without witness generation, the inputs of a gadget have no values.
+                self.define_without_value(var.id, || format!("instance variable_{}", var.id));
+            } else {
+                self.define(var.id, var.value, || format!("value of the instance variable_{}", var.id));
+            }
+        }
+    }
+
+    fn ingest_gadget_response(&mut self, field_maximum: Option<&[u8]>, free_variable_id: Var, outputs: &[Variable]) {
+        let gadget = self.gadget.as_mut().unwrap();
+        if gadget.got_response {
+            self.violate("Multiple gadget responses.");
+            return;
+        }
+        gadget.got_response = true;
+
+        let first_local_id = gadget.first_local_id;
+        if free_variable_id <= first_local_id {
+            self.violate(format!("The response free_variable_id ({}) must be greater than the request free_variable_id ({}).",
+                                 free_variable_id, first_local_id));
+        }
+        self.free_variable_id = Some(free_variable_id);
+
+        if let Some(max) = field_maximum {
+            let same_field = match (Field::from_maximum(max), self.field.as_ref()) {
+                (Ok(field), Some(request_field)) => field.modulus() == request_field.modulus(),
+                _ => false,
+            };
+            if !same_field {
+                self.violate("The response field_maximum is different from the request.");
+            }
+        }
+
+        for var in outputs {
+            if var.id < first_local_id || var.id >= free_variable_id {
+                self.violate(format!("The output variable_{} is not newly allocated by the gadget (should be in [{}, {})).",
+                                     var.id, first_local_id, free_variable_id));
+            }
+            if self.as_prover {
+                if var.value.is_empty() {
+                    self.violate(format!("The output variable_{} has no value, but witness generation was requested.", var.id));
+                    self.define_without_value(var.id, || format!("output variable_{}", var.id));
+                } else {
+                    self.define(var.id, var.value, || format!("value of the output variable_{}", var.id));
+                }
+            } else {
+                self.define_without_value(var.id, || format!("output variable_{}", var.id));
+            }
         }
     }
 
     pub fn ingest_witness(&mut self, witness: &Witness) {
+        self.ingest_assignment(&witness.assigned_variables.get_variables());
+    }
+
+    fn ingest_assignment(&mut self, assigned_variables: &[Variable]) {
         if !self.as_prover { return; }
 
         self.ensure_header();
 
-        for var in witness.assigned_variables.get_variables() {
+        for var in assigned_variables {
             self.define(var.id, var.value, || format!("value of the witness variable_{}", var.id));
         }
     }
@@ -96,14 +222,14 @@ impl Validator {
         self.ensure_header();
 
         for constraint in &system.constraints {
-            self.validate_terms(&constraint.linear_combination_a);
-            self.validate_terms(&constraint.linear_combination_b);
-            self.validate_terms(&constraint.linear_combination_c);
+            self.validate_terms(&constraint.linear_combination_a.get_variables());
+            self.validate_terms(&constraint.linear_combination_b.get_variables());
+            self.validate_terms(&constraint.linear_combination_c.get_variables());
         }
     }
 
-    fn validate_terms(&mut self, terms: &Variables) {
-        for term in terms.get_variables() {
+    fn validate_terms(&mut self, terms: &[Variable]) {
+        for term in terms {
             self.ensure_defined(term.id);
             self.ensure_value_in_field(term.value, || format!("coefficient for variable_{}", term.id));
             self.set_status(term.id, Used);
@@ -127,6 +253,13 @@ impl Validator {
         self.set_status(id, Defined);
     }
 
+    fn define_without_value(&mut self, id: Var, name: impl Fn() -> String) {
+        if self.status(id) != Undefined {
+            self.violate(format!("Multiple definition of the {}", name()));
+        }
+        self.set_status(id, Defined);
+    }
+
     fn ensure_defined(&mut self, id: Var) {
         if self.status(id) == Undefined {
             self.ensure_id_bound(id);
@@ -138,6 +271,12 @@ impl Validator {
     }
 
     fn ensure_id_bound(&mut self, id: Var) {
+        // A gadget may only access its inputs among the variables of the caller.
+        if let Some(ref gadget) = self.gadget {
+            if id != 0 && id < gadget.first_local_id && !gadget.inputs.contains(&id) {
+                self.violate(format!("The variable_{} of the caller is accessed, but it is not an input of the gadget.", id));
+            }
+        }
         if let Some(max) = self.free_variable_id {
             if id >= max {
                 self.violate(format!("Using variable ID {} beyond what was claimed in the header free_variable_id (should be less than {})", id, max));
@@ -150,10 +289,10 @@ impl Validator {
             self.violate(format!("The {} is empty.", name()));
         }
 
-        if let Some(max) = self.field_maximum.as_ref() {
-            let int = &Field::from_bytes_le(value);
-            if int > max {
-                let msg = format!("The {} cannot be represented in the field specified in CircuitHeader ({} > {}).", name(), int, max);
+        if let Some(field) = self.field.as_ref() {
+            if !field.is_canonical(value) {
+                let msg = format!("The {} cannot be represented in the field specified in CircuitHeader ({} > {}).",
+                                  name(), BigUint::from_bytes_le(value), field.modulus() - 1_u32);
                 self.violate(msg);
             }
         }
@@ -169,6 +308,8 @@ impl Validator {
         for (id, status) in self.variables.iter() {
             match *status {
                 Undefined => self.violations.push(format!("variable_{} was accessed but not defined.", id)),
+                // The constant one is defined by every header, so a statement without constant terms is valid.
+                Defined if *id == 0 => { /* ok */ }
                 Defined => self.violations.push(format!("variable_{} was defined but not used.", id)),
                 Used => { /* ok */ }
             }
@@ -201,3 +342,91 @@ fn test_validator() -> crate::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_validator_unused_one() -> crate::Result<()> {
+    use crate::Variables;
+    use crate::producers::examples::*;
+
+    // x * x = y, where the constant one is not used.
+    let header = CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![1, 2], values: Some(serialize_small(&[3, 9])), info: None },
+        free_variable_id: 3,
+        ..example_circuit_header()
+    };
+    let constraints: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![1], vec![1]), (vec![1], vec![1]), (vec![2], vec![1])),
+    ];
+
+    let mut validator = Validator::new_as_prover();
+    validator.ingest_header(&header);
+    validator.ingest_constraint_system(&ConstraintSystem::from(constraints));
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    // Other unused variables are still reported.
+    let mut validator = Validator::new_as_prover();
+    validator.ingest_header(&CircuitHeader { free_variable_id: 4, ..header });
+    validator.ingest_witness(&Witness {
+        assigned_variables: Variables { variable_ids: vec![3], values: Some(serialize_small(&[1])), info: None },
+    });
+    validator.ingest_constraint_system(&ConstraintSystem::from(constraints));
+    assert_eq!(validator.get_violations(), vec!["variable_3 was defined but not used.".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_validator_gadget() -> crate::Result<()> {
+    use crate::Variables;
+    use crate::producers::examples::*;
+
+    // The example as a gadget: inputs x and y, output z, locals xx and yy.
+    let request = CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![1, 2], values: Some(serialize_small(&[3, 4])), info: None },
+        free_variable_id: 3,
+        ..example_circuit_header()
+    };
+    let response = CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![3], values: Some(serialize_small(&[25])), info: None },
+        free_variable_id: 6,
+        field_maximum: None,
+        configuration: None,
+    };
+    let mut validator = Validator::new_as_gadget_prover();
+    validator.ingest_header(&request);
+    validator.ingest_header(&response);
+    validator.ingest_witness(&example_witness());
+    validator.ingest_constraint_system(&example_constraints());
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    // As a verifier, without values.
+    let mut validator = Validator::new_as_gadget_verifier();
+    validator.ingest_header(&CircuitHeader { instance_variables: Variables { values: None, ..request.instance_variables.clone() }, ..request.clone() });
+    validator.ingest_header(&CircuitHeader { instance_variables: Variables { values: None, ..response.instance_variables.clone() }, ..response.clone() });
+    validator.ingest_constraint_system(&example_constraints());
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+
+    // A gadget that allocates nothing, returns an old variable, and accesses a variable of the caller.
+    let mut validator = Validator::new_as_gadget_prover();
+    validator.ingest_header(&CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![1], values: Some(serialize_small(&[3])), info: None },
+        ..request.clone()
+    });
+    validator.ingest_header(&CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![3], values: None, info: None },
+        free_variable_id: 3,
+        ..response.clone()
+    });
+    validator.ingest_constraint_system(&example_constraints());
+    let violations = validator.get_violations();
+    for expected in &[
+        "The response free_variable_id (3) must be greater than the request free_variable_id (3).",
+        "The output variable_3 is not newly allocated by the gadget (should be in [3, 3)).",
+        "The output variable_3 has no value, but witness generation was requested.",
+        "The variable_2 of the caller is accessed, but it is not an input of the gadget.",
+    ] {
+        assert!(violations.contains(&expected.to_string()), "Missing violation: {}", expected);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,350 @@
+//! Verification of untrusted buffers before they are decoded.
+//!
+//! The FlatBuffers accessors trust their input: an offset out of bounds panics, and an invalid union type,
+//! boolean, or string is undefined behaviour. `Limits::verify` checks that a size-prefixed buffer is a
+//! well-formed zkInterface message, so that it can be decoded safely.
+
+use std::convert::TryInto;
+use std::mem::size_of;
+use flatbuffers::{SIZE_UOFFSET, VOffsetT};
+
+use crate::zkinterface_generated::zkinterface as fb;
+use crate::{Result, Error};
+
+/// Limits on the messages that are accepted from untrusted input.
+///
+/// # Example
+/// ```
+/// use zkinterface::consumers::verifier::Limits;
+/// use zkinterface::producers::examples::example_circuit_header;
+///
+/// let mut buf = vec![];
+/// example_circuit_header().write_into(&mut buf).unwrap();
+/// assert!(Limits::default().verify(&buf).is_ok());
+///
+/// buf[8] = 0xff; // Corrupt the offset of the root table.
+/// assert!(Limits::default().verify(&buf).is_err());
+///
+/// let tiny = Limits { max_message_size: 16, ..Limits::default() };
+/// assert!(tiny.verify(&buf).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum size of a message in bytes, including its size prefix.
+    pub max_message_size: usize,
+    /// The maximum nesting of tables, the root table being at depth 1.
+    pub max_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_message_size: 1 << 30,
+            max_depth: 16,
+        }
+    }
+}
+
+impl Limits {
+    /// Check the size prefix of a message against `max_message_size`. The size includes the prefix.
+    pub fn check_size(&self, size: usize) -> Result<()> {
+        if size > self.max_message_size {
+            return Err(Error::Framing(format!(
+                "message of {} bytes exceeds the limit of {} bytes", size, self.max_message_size)));
+        }
+        Ok(())
+    }
+
+    /// Verify that `buf` starts with a well-formed size-prefixed message.
+    /// Bytes after the message are ignored.
+    pub fn verify(&self, buf: &[u8]) -> Result<()> {
+        if buf.len() < SIZE_UOFFSET {
+            return Err(Error::Framing(format!("{} bytes is too short for a size prefix", buf.len())));
+        }
+        let size = SIZE_UOFFSET + u32::from_le_bytes(buf[..SIZE_UOFFSET].try_into().unwrap()) as usize;
+        self.check_size(size)?;
+        if size > buf.len() {
+            return Err(Error::Framing(format!("message of {} bytes is truncated at {} bytes", size, buf.len())));
+        }
+
+        let mut verifier = Verifier {
+            buf: &buf[..size],
+            limits: self,
+            // A table takes at least 4 bytes, so a buffer without shared tables has no more than this.
+            tables_left: size / 4,
+        };
+        let root = verifier.uoffset(SIZE_UOFFSET)?;
+        verifier.root(root)
+    }
+}
+
+/// Verify a size-prefixed message with the default limits.
+pub fn verify_message(buf: &[u8]) -> Result<()> {
+    Limits::default().verify(buf)
+}
+
+struct Verifier<'a> {
+    buf: &'a [u8],
+    limits: &'a Limits,
+    tables_left: usize,
+}
+
+/// The position of a table and of its vtable.
+#[derive(Clone, Copy)]
+struct Table {
+    loc: usize,
+    vtable: usize,
+    vtable_size: usize,
+    object_size: usize,
+    depth: usize,
+}
+
+fn invalid<T>(msg: impl ToString) -> Result<T> {
+    Err(Error::InvalidMessage(msg.to_string()))
+}
+
+impl<'a> Verifier<'a> {
+    fn range(&self, loc: usize, len: usize) -> Result<&'a [u8]> {
+        match loc.checked_add(len) {
+            Some(end) if end <= self.buf.len() => Ok(&self.buf[loc..end]),
+            _ => invalid(format!("{} bytes at offset {} are out of bounds", len, loc)),
+        }
+    }
+
+    /// FlatBuffers reads scalars through pointers, which must be aligned in memory.
+    fn aligned(&self, loc: usize, align: usize) -> Result<()> {
+        if (self.buf.as_ptr() as usize + loc) % align != 0 {
+            return invalid(format!("offset {} is not aligned to {} bytes", loc, align));
+        }
+        Ok(())
+    }
+
+    fn u16(&self, loc: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.range(loc, 2)?.try_into().unwrap()))
+    }
+
+    fn u32(&self, loc: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.range(loc, 4)?.try_into().unwrap()))
+    }
+
+    /// Follow an unsigned offset, relative to its own position.
+    fn uoffset(&self, loc: usize) -> Result<usize> {
+        self.aligned(loc, SIZE_UOFFSET)?;
+        let target = loc + self.u32(loc)? as usize;
+        if target >= self.buf.len() {
+            return invalid(format!("offset at {} points out of bounds", loc));
+        }
+        Ok(target)
+    }
+
+    fn table(&mut self, loc: usize, depth: usize) -> Result<Table> {
+        if depth > self.limits.max_depth {
+            return invalid(format!("tables are nested deeper than {}", self.limits.max_depth));
+        }
+        if self.tables_left == 0 {
+            return invalid("too many tables");
+        }
+        self.tables_left -= 1;
+
+        self.aligned(loc, 4)?;
+        let soffset = self.u32(loc)? as i32 as i64;
+        let vtable = loc as i64 - soffset;
+        if vtable < 0 || vtable as usize >= self.buf.len() {
+            return invalid(format!("vtable of the table at {} is out of bounds", loc));
+        }
+        let vtable = vtable as usize;
+        self.aligned(vtable, 2)?;
+        let vtable_size = self.u16(vtable)? as usize;
+        let object_size = self.u16(vtable + 2)? as usize;
+        if vtable_size < 4 || vtable_size % 2 != 0 {
+            return invalid(format!("invalid vtable size {} at {}", vtable_size, vtable));
+        }
+        self.range(vtable, vtable_size)?;
+        self.range(loc, object_size.max(4))?;
+        Ok(Table { loc, vtable, vtable_size, object_size, depth })
+    }
+
+    /// The position of a field of `size` bytes, if present.
+    fn field(&self, table: &Table, slot: VOffsetT, size: usize) -> Result<Option<usize>> {
+        let slot = slot as usize;
+        if slot + 2 > table.vtable_size {
+            return Ok(None);
+        }
+        let offset = self.u16(table.vtable + slot)? as usize;
+        if offset == 0 {
+            return Ok(None);
+        }
+        if offset < 4 || offset + size > table.object_size {
+            return invalid(format!("field {} of the table at {} is out of the table", slot, table.loc));
+        }
+        self.aligned(table.loc + offset, size)?;
+        Ok(Some(table.loc + offset))
+    }
+
+    fn scalar_field(&self, table: &Table, slot: VOffsetT, size: usize) -> Result<()> {
+        self.field(table, slot, size).map(|_| ())
+    }
+
+    fn bool_field(&self, table: &Table, slot: VOffsetT) -> Result<()> {
+        if let Some(loc) = self.field(table, slot, 1)? {
+            if self.buf[loc] > 1 {
+                return invalid(format!("invalid boolean {} at {}", self.buf[loc], loc));
+            }
+        }
+        Ok(())
+    }
+
+    /// The position of the object pointed to by an offset field, if present.
+    /// The owned structures expect some fields to be present, so these are required.
+    fn offset_field(&self, table: &Table, slot: VOffsetT, required: bool) -> Result<Option<usize>> {
+        match self.field(table, slot, SIZE_UOFFSET)? {
+            Some(loc) => Ok(Some(self.uoffset(loc)?)),
+            None if required => invalid(format!("missing field {} in the table at {}", slot, table.loc)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check a vector of scalars, and return its length.
+    fn vector(&self, loc: usize, elem_size: usize) -> Result<usize> {
+        self.aligned(loc, SIZE_UOFFSET)?;
+        let len = self.u32(loc)? as usize;
+        let data = loc + SIZE_UOFFSET;
+        self.range(data, len.checked_mul(elem_size).ok_or_else(|| Error::InvalidMessage("vector length overflow".into()))?)?;
+        // Vectors of scalars are accessed as slices.
+        self.aligned(data, elem_size)?;
+        Ok(len)
+    }
+
+    fn vector_field(&self, table: &Table, slot: VOffsetT, elem_size: usize) -> Result<()> {
+        if let Some(loc) = self.offset_field(table, slot, false)? {
+            self.vector(loc, elem_size)?;
+        }
+        Ok(())
+    }
+
+    fn string_field(&self, table: &Table, slot: VOffsetT, required: bool) -> Result<()> {
+        if let Some(loc) = self.offset_field(table, slot, required)? {
+            let len = self.vector(loc, 1)?;
+            let bytes = self.range(loc + SIZE_UOFFSET, len)?;
+            if std::str::from_utf8(bytes).is_err() {
+                return invalid(format!("string at {} is not valid UTF-8", loc));
+            }
+        }
+        Ok(())
+    }
+
+    fn table_field(&mut self, parent: &Table, slot: VOffsetT, required: bool, verify: fn(&mut Self, Table) -> Result<()>) -> Result<()> {
+        if let Some(loc) = self.offset_field(parent, slot, required)? {
+            let table = self.table(loc, parent.depth + 1)?;
+            verify(self, table)?;
+        }
+        Ok(())
+    }
+
+    fn table_vector_field(&mut self, parent: &Table, slot: VOffsetT, required: bool, verify: fn(&mut Self, Table) -> Result<()>) -> Result<()> {
+        if let Some(loc) = self.offset_field(parent, slot, required)? {
+            let len = self.vector(loc, SIZE_UOFFSET)?;
+            for i in 0..len {
+                let elem = self.uoffset(loc + SIZE_UOFFSET + i * SIZE_UOFFSET)?;
+                let table = self.table(elem, parent.depth + 1)?;
+                verify(self, table)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn root(&mut self, loc: usize) -> Result<()> {
+        let root = self.table(loc, 1)?;
+        let message_type = match self.field(&root, fb::Root::VT_MESSAGE_TYPE, 1)? {
+            Some(loc) => self.buf[loc],
+            None => fb::Message::NONE as u8,
+        };
+        let verify: fn(&mut Self, Table) -> Result<()> = match message_type {
+            // An empty root is well-formed; consumers report it as an invalid message type.
+            t if t == fb::Message::NONE as u8 => return Ok(()),
+            t if t == fb::Message::CircuitHeader as u8 => Self::circuit_header,
+            t if t == fb::Message::ConstraintSystem as u8 => Self::constraint_system,
+            t if t == fb::Message::Witness as u8 => Self::witness,
+            t if t == fb::Message::Command as u8 => Self::command,
+            t => return invalid(format!("unknown message type {}", t)),
+        };
+        let loc = self.offset_field(&root, fb::Root::VT_MESSAGE, true)?.unwrap();
+        let table = self.table(loc, 2)?;
+        verify(self, table)
+    }
+
+    fn circuit_header(&mut self, t: Table) -> Result<()> {
+        self.table_field(&t, fb::CircuitHeader::VT_INSTANCE_VARIABLES, true, Self::variables)?;
+        self.scalar_field(&t, fb::CircuitHeader::VT_FREE_VARIABLE_ID, size_of::<u64>())?;
+        self.vector_field(&t, fb::CircuitHeader::VT_FIELD_MAXIMUM, 1)?;
+        self.table_vector_field(&t, fb::CircuitHeader::VT_CONFIGURATION, false, Self::key_value)
+    }
+
+    fn constraint_system(&mut self, t: Table) -> Result<()> {
+        self.table_vector_field(&t, fb::ConstraintSystem::VT_CONSTRAINTS, false, Self::bilinear_constraint)?;
+        self.table_vector_field(&t, fb::ConstraintSystem::VT_INFO, false, Self::key_value)
+    }
+
+    fn witness(&mut self, t: Table) -> Result<()> {
+        self.table_field(&t, fb::Witness::VT_ASSIGNED_VARIABLES, true, Self::variables)
+    }
+
+    fn command(&mut self, t: Table) -> Result<()> {
+        self.bool_field(&t, fb::Command::VT_CONSTRAINTS_GENERATION)?;
+        self.bool_field(&t, fb::Command::VT_WITNESS_GENERATION)?;
+        self.table_vector_field(&t, fb::Command::VT_PARAMETERS, false, Self::key_value)
+    }
+
+    fn bilinear_constraint(&mut self, t: Table) -> Result<()> {
+        self.table_field(&t, fb::BilinearConstraint::VT_LINEAR_COMBINATION_A, true, Self::variables)?;
+        self.table_field(&t, fb::BilinearConstraint::VT_LINEAR_COMBINATION_B, true, Self::variables)?;
+        self.table_field(&t, fb::BilinearConstraint::VT_LINEAR_COMBINATION_C, true, Self::variables)
+    }
+
+    fn variables(&mut self, t: Table) -> Result<()> {
+        self.vector_field(&t, fb::Variables::VT_VARIABLE_IDS, size_of::<u64>())?;
+        self.vector_field(&t, fb::Variables::VT_VALUES, 1)?;
+        self.table_vector_field(&t, fb::Variables::VT_INFO, false, Self::key_value)
+    }
+
+    fn key_value(&mut self, t: Table) -> Result<()> {
+        self.string_field(&t, fb::KeyValue::VT_KEY, true)?;
+        self.vector_field(&t, fb::KeyValue::VT_DATA, 1)?;
+        self.string_field(&t, fb::KeyValue::VT_TEXT, false)?;
+        self.scalar_field(&t, fb::KeyValue::VT_NUMBER, size_of::<i64>())
+    }
+}
+
+
+#[test]
+fn test_verifier() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let mut buf = vec![];
+    example_circuit_header().write_into(&mut buf)?;
+    example_witness().write_into(&mut buf)?;
+    example_constraints().write_into(&mut buf)?;
+    for msg in crate::consumers::reader::split_messages(&buf) {
+        verify_message(msg)?;
+    }
+
+    // Truncations and corruptions of a message are all rejected or accepted without a panic.
+    for msg in crate::consumers::reader::split_messages(&buf) {
+        for len in 0..msg.len() {
+            assert!(verify_message(&msg[..len]).is_err());
+        }
+        for i in 0..msg.len() {
+            for byte in &[0x00, 0x01, 0x7f, 0x80, 0xff] {
+                let mut corrupted = msg.to_vec();
+                corrupted[i] = *byte;
+                let _ = crate::Message::from(&corrupted[..]);
+            }
+        }
+    }
+
+    let msg = crate::consumers::reader::split_messages(&buf)[0];
+    let shallow = Limits { max_depth: 2, ..Limits::default() };
+    assert_eq!(shallow.verify(msg), Err(Error::InvalidMessage("tables are nested deeper than 2".into())));
+
+    Ok(())
+}
@@ -3,8 +3,11 @@ use std::fs::{File, read_dir};
 use std::iter;
 use std::io::{Read, stdin};
 use std::ffi::OsStr;
+use std::fmt;
+use std::convert::TryFrom;
+use serde::Serialize;
 use crate::consumers::reader::read_buffer;
-use crate::{Result, Message, Messages};
+use crate::{Result, Error, Message, Messages, CircuitHeader};
 
 
 /// Workspace finds and reads zkInterface messages from a directory.
@@ -61,16 +64,7 @@ impl Workspace {
         if paths == vec![PathBuf::from("-")] {
             Workspace { paths: vec![], stdin: true }
         } else {
-            paths.sort();
-            paths.sort_by_key(|path| {
-                let name = path.file_name().unwrap().to_str().unwrap();
-                match () {
-                    _ if name.contains("header") => 0,
-                    _ if name.contains("witness") => 1,
-                    _ if name.contains("constraint") => 3,
-                    _ => 4,
-                }
-            });
+            sort_workspace_files(&mut paths);
             Workspace { paths, stdin: false }
         }
     }
@@ -85,11 +79,43 @@ impl Workspace {
         buffers.map(|buffer| Message::from(&buffer[..]))
     }
 
+    /// Like `iter_messages`, but also tell where each message comes from.
+    pub fn iter_messages_with_origin<'w>(&'w self) -> impl Iterator<Item=(MessageOrigin, Message)> + 'w {
+        let buffers: Box<dyn Iterator<Item=(MessageOrigin, Vec<u8>)>> = if self.stdin {
+            Box::new(iterate_stream(stdin()).enumerate().map(|(index, buffer)|
+                (MessageOrigin { path: None, index }, buffer)))
+        } else {
+            Box::new(self.paths.iter().flat_map(|path|
+                iterate_file(path).enumerate().map(move |(index, buffer)|
+                    (MessageOrigin { path: Some(path.clone()), index }, buffer))))
+        };
+
+        buffers.map(|(origin, buffer)| (origin, Message::from(&buffer[..])))
+    }
+
     pub fn read_all_messages(&self) -> Messages {
         Messages::from(self)
     }
 }
 
+/// The location of a message in a workspace.
+#[derive(Clone, Default, Debug, Eq, PartialEq, Hash, Serialize)]
+pub struct MessageOrigin {
+    /// The file containing the message, or None for stdin.
+    pub path: Option<PathBuf>,
+    /// The position of the message within its file or stream, starting from 0.
+    pub index: usize,
+}
+
+impl fmt::Display for MessageOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}, message {}", path.display(), self.index),
+            None => write!(f, "stdin, message {}", self.index),
+        }
+    }
+}
+
 pub fn iterate_files<'w>(paths: &'w [PathBuf]) -> impl Iterator<Item=Vec<u8>> + 'w {
     paths.iter().flat_map(|path|
         iterate_file(path))
@@ -128,6 +154,70 @@ pub fn has_zkif_extension(path: &Path) -> bool {
     path.extension() == Some(OsStr::new("zkif"))
 }
 
+/// The directory where `WorkspaceSink` saves the response headers of gadget calls.
+/// It is a subdirectory of the workspace, so that responses are never read as part of the statement.
+pub fn gadget_responses_dir(workspace: &Path) -> PathBuf {
+    workspace.join("responses")
+}
+
+/// Read the response headers of the gadget calls recorded in a workspace, in the order of the calls.
+pub fn read_gadget_responses(workspace: &Path) -> Result<Vec<CircuitHeader>> {
+    let dir = gadget_responses_dir(workspace);
+    if !dir.is_dir() { return Ok(vec![]); }
+    let mut paths: Vec<PathBuf> = read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| has_zkif_extension(path))
+        .collect();
+    sort_workspace_files(&mut paths);
+
+    iterate_files(&paths)
+        .map(|buffer| CircuitHeader::try_from(&buffer[..]))
+        .collect()
+}
+
+/// Sort files in the order in which messages should be consumed: headers, witnesses, then constraints.
+/// Numbers in names are compared by value, so that constraints_2.zkif comes before constraints_10.zkif.
+pub fn sort_workspace_files(paths: &mut [PathBuf]) {
+    paths.sort_by_cached_key(|path| natural_key(&path.to_string_lossy()));
+    paths.sort_by_key(|path| {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        match () {
+            _ if name.contains("header") => 0,
+            _ if name.contains("witness") => 1,
+            _ if name.contains("constraint") => 3,
+            _ => 4,
+        }
+    });
+}
+
+/// A part of a name, for natural ordering: numbers by value, then other text.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+enum NamePart {
+    /// The number of significant digits, and the digits.
+    Number(usize, String),
+    Text(String),
+}
+
+fn natural_key(name: &str) -> Vec<NamePart> {
+    let mut parts: Vec<NamePart> = vec![];
+    for c in name.chars() {
+        match (parts.last_mut(), c.is_ascii_digit()) {
+            (Some(NamePart::Number(_, digits)), true) => digits.push(c),
+            (Some(NamePart::Text(text)), false) => text.push(c),
+            (_, true) => parts.push(NamePart::Number(0, c.to_string())),
+            (_, false) => parts.push(NamePart::Text(c.to_string())),
+        }
+    }
+    for part in &mut parts {
+        if let NamePart::Number(len, digits) = part {
+            *digits = digits.trim_start_matches('0').to_string();
+            *len = digits.len();
+        }
+    }
+    parts
+}
+
 pub fn list_workspace_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
     let mut all_paths = vec![];
 
@@ -135,7 +225,7 @@ pub fn list_workspace_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
         if has_zkif_extension(path) {
             all_paths.push(path.clone());
         } else if path == Path::new("-") {
-            if paths.len() > 1 { return Err("Cannot combine files and stdin".into()); }
+            if paths.len() > 1 { return Err(Error::InvalidArgument("Cannot combine files and stdin".into())); }
             all_paths.push(path.clone());
         } else {
             for file in read_dir(path)? {
@@ -155,3 +245,23 @@ pub fn list_workspace_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
     }
     Ok(all_paths)
 }
+
+
+#[test]
+fn test_sort_workspace_files() {
+    let mut paths: Vec<PathBuf> = (1..=12).rev()
+        .map(|i| PathBuf::from(format!("ws/constraints_{}.zkif", i)))
+        .chain(vec![
+            PathBuf::from("ws/constraints.zkif"),
+            PathBuf::from("ws/witness_10.zkif"),
+            PathBuf::from("ws/witness_9.zkif"),
+            PathBuf::from("ws/header.zkif"),
+        ])
+        .collect();
+    sort_workspace_files(&mut paths);
+
+    let names: Vec<String> = paths.iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect();
+    let mut expected = vec!["header.zkif".to_string(), "witness_9.zkif".to_string(), "witness_10.zkif".to_string(), "constraints.zkif".to_string()];
+    expected.extend((1..=12).map(|i| format!("constraints_{}.zkif", i)));
+    assert_eq!(names, expected);
+}
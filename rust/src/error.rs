@@ -0,0 +1,223 @@
+use std::fmt;
+use std::io;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The error type of the crate.
+///
+/// Errors from other crates convert into a specific variant, so `?` works as usual.
+///
+/// # Example
+/// ```
+/// use zkinterface::{Error, Field, CircuitHeader};
+///
+/// match Field::from_header(&CircuitHeader::default()) {
+///     Err(Error::Field(msg)) => assert_eq!(msg, "No field_maximum specified"),
+///     _ => panic!("Expected a field error"),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or a stream failed.
+    Io(io::Error),
+    /// A message is truncated, or its size prefix is invalid.
+    Framing(String),
+    /// A buffer is not a valid FlatBuffers message of the expected type.
+    InvalidMessage(String),
+    /// A header must be provided before other messages.
+    MissingHeader,
+    /// A statement must have a single header.
+    MultipleHeaders,
+    /// An invalid `field_maximum`, or a value that is not an element of the field.
+    Field(String),
+    /// A call to a gadget failed, or its response is inconsistent.
+    GadgetCall(String),
+    /// A file in the text, iden3, JSON, or YAML format cannot be parsed, or a statement cannot be converted to it.
+    Format(String),
+    /// A constraint does not hold, or a value required to check it is missing.
+    Unsatisfied(String),
+    /// A check found problems in a statement, such as lint errors or differences, after reporting them.
+    Check(String),
+    /// An invalid argument or option, or a combination of inputs that is not supported.
+    InvalidArgument(String),
+    /// Any other error, described by a message.
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Framing(msg) => write!(f, "Invalid message framing: {}", msg),
+            Error::InvalidMessage(msg) => write!(f, "Invalid message: {}", msg),
+            Error::MissingHeader => write!(f, "A header must be provided before other messages."),
+            Error::MultipleHeaders => write!(f, "Multiple headers are not supported."),
+            Error::Field(msg) => write!(f, "{}", msg),
+            Error::GadgetCall(msg) => write!(f, "Gadget call failed: {}", msg),
+            Error::Format(msg) => write!(f, "{}", msg),
+            Error::Unsatisfied(msg) => write!(f, "{}", msg),
+            Error::Check(msg) => write!(f, "{}", msg),
+            Error::InvalidArgument(msg) => write!(f, "{}", msg),
+            Error::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Errors are kept in `Message::Err`, which is cloned, compared, and serialized.
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        match self {
+            Error::Io(err) => Error::Io(io::Error::new(err.kind(), err.to_string())),
+            Error::Framing(msg) => Error::Framing(msg.clone()),
+            Error::InvalidMessage(msg) => Error::InvalidMessage(msg.clone()),
+            Error::MissingHeader => Error::MissingHeader,
+            Error::MultipleHeaders => Error::MultipleHeaders,
+            Error::Field(msg) => Error::Field(msg.clone()),
+            Error::GadgetCall(msg) => Error::GadgetCall(msg.clone()),
+            Error::Format(msg) => Error::Format(msg.clone()),
+            Error::Unsatisfied(msg) => Error::Unsatisfied(msg.clone()),
+            Error::Check(msg) => Error::Check(msg.clone()),
+            Error::InvalidArgument(msg) => Error::InvalidArgument(msg.clone()),
+            Error::Other(msg) => Error::Other(msg.clone()),
+        }
+    }
+}
+
+/// Errors are equal if they have the same variant and payload. I/O errors are compared by their message only,
+/// because their kind is not serialized.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Error::Io(a), Error::Io(b)) => a.to_string() == b.to_string(),
+            (Error::Framing(a), Error::Framing(b)) => a == b,
+            (Error::InvalidMessage(a), Error::InvalidMessage(b)) => a == b,
+            (Error::MissingHeader, Error::MissingHeader) => true,
+            (Error::MultipleHeaders, Error::MultipleHeaders) => true,
+            (Error::Field(a), Error::Field(b)) => a == b,
+            (Error::GadgetCall(a), Error::GadgetCall(b)) => a == b,
+            (Error::Format(a), Error::Format(b)) => a == b,
+            (Error::Unsatisfied(a), Error::Unsatisfied(b)) => a == b,
+            (Error::Check(a), Error::Check(b)) => a == b,
+            (Error::InvalidArgument(a), Error::InvalidArgument(b)) => a == b,
+            (Error::Other(a), Error::Other(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self { Error::Io(err) }
+}
+
+/// Convert errors of other crates into a variant, keeping their message.
+macro_rules! impl_from_other {
+    ($variant:ident: $($err:ty),*) => {$(
+        impl From<$err> for Error {
+            fn from(err: $err) -> Self { Error::$variant(err.to_string()) }
+        }
+    )*};
+}
+
+impl_from_other!(Format:
+    serde_json::Error,
+    serde_yaml::Error,
+    num_bigint::ParseBigIntError,
+    std::num::ParseIntError,
+    std::num::TryFromIntError,
+    std::array::TryFromSliceError,
+    std::string::FromUtf8Error
+);
+
+impl_from_other!(InvalidArgument: structopt::clap::Error);
+
+#[cfg(feature = "groth16")]
+impl_from_other!(Other: bellman::SynthesisError);
+
+/// The serialized form of `Error`, tagged by variant. I/O errors keep only their message.
+#[derive(Serialize, Deserialize)]
+enum ErrorRepr {
+    Io(String),
+    Framing(String),
+    InvalidMessage(String),
+    MissingHeader,
+    MultipleHeaders,
+    Field(String),
+    GadgetCall(String),
+    Format(String),
+    Unsatisfied(String),
+    Check(String),
+    InvalidArgument(String),
+    Other(String),
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let repr = match self.clone() {
+            Error::Io(err) => ErrorRepr::Io(err.to_string()),
+            Error::Framing(msg) => ErrorRepr::Framing(msg),
+            Error::InvalidMessage(msg) => ErrorRepr::InvalidMessage(msg),
+            Error::MissingHeader => ErrorRepr::MissingHeader,
+            Error::MultipleHeaders => ErrorRepr::MultipleHeaders,
+            Error::Field(msg) => ErrorRepr::Field(msg),
+            Error::GadgetCall(msg) => ErrorRepr::GadgetCall(msg),
+            Error::Format(msg) => ErrorRepr::Format(msg),
+            Error::Unsatisfied(msg) => ErrorRepr::Unsatisfied(msg),
+            Error::Check(msg) => ErrorRepr::Check(msg),
+            Error::InvalidArgument(msg) => ErrorRepr::InvalidArgument(msg),
+            Error::Other(msg) => ErrorRepr::Other(msg),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Error {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(match ErrorRepr::deserialize(deserializer)? {
+            ErrorRepr::Io(msg) => Error::Io(io::Error::other(msg)),
+            ErrorRepr::Framing(msg) => Error::Framing(msg),
+            ErrorRepr::InvalidMessage(msg) => Error::InvalidMessage(msg),
+            ErrorRepr::MissingHeader => Error::MissingHeader,
+            ErrorRepr::MultipleHeaders => Error::MultipleHeaders,
+            ErrorRepr::Field(msg) => Error::Field(msg),
+            ErrorRepr::GadgetCall(msg) => Error::GadgetCall(msg),
+            ErrorRepr::Format(msg) => Error::Format(msg),
+            ErrorRepr::Unsatisfied(msg) => Error::Unsatisfied(msg),
+            ErrorRepr::Check(msg) => Error::Check(msg),
+            ErrorRepr::InvalidArgument(msg) => Error::InvalidArgument(msg),
+            ErrorRepr::Other(msg) => Error::Other(msg),
+        })
+    }
+}
+
+
+#[test]
+fn test_error_serde() -> crate::Result<()> {
+    use crate::Message;
+
+    let errors = vec![
+        Error::Io(io::Error::new(io::ErrorKind::NotFound, "No such file")),
+        Error::MissingHeader,
+        Error::Field("No field_maximum specified".into()),
+        Error::Format("Unexpected end of file".into()),
+    ];
+    for err in errors {
+        let msg = Message::Err(err);
+        let json = serde_json::to_string(&msg)?;
+        assert_eq!(serde_json::from_str::<Message>(&json)?, msg);
+    }
+
+    assert_eq!(serde_json::to_string(&Error::Field("bad".into()))?, r#"{"Field":"bad"}"#);
+    assert_ne!(Error::Field("bad".into()), Error::Other("bad".into()));
+    assert_ne!(Error::Field("bad".into()), Error::Field("worse".into()));
+    Ok(())
+}
@@ -0,0 +1,438 @@
+//! Arithmetic in the prime field described by `CircuitHeader.field_maximum`.
+
+use num_bigint::{BigInt, BigUint, RandBigInt, Sign};
+use num_integer::Integer;
+use num_traits::{One, Zero};
+use rand::Rng;
+
+use crate::{Result, CircuitHeader, Error};
+
+/// A finite field of integers modulo a prime, as declared in a `CircuitHeader`.
+///
+/// Fields with a modulus of up to 64 bits and odd moduli of up to 256 bits use fast fixed-limb
+/// arithmetic. Other moduli fall back to arbitrary-precision integers.
+///
+/// # Example
+/// ```
+/// use zkinterface::Field;
+/// use zkinterface::producers::examples::example_circuit_header;
+///
+/// let field = Field::from_header(&example_circuit_header()).unwrap();
+/// let x = field.decode(&[3]);
+/// let y = field.decode(&[100, 0, 0, 0]);
+/// assert_eq!(field.add(&x, &y), field.from_u64(2));
+/// assert_eq!(field.mul(&x, &field.inverse(&x).unwrap()), field.one());
+/// assert_eq!(field.encode(&field.neg(&x)), vec![98]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    modulus: BigUint,
+    byte_len: usize,
+    arith: Arith,
+}
+
+/// An element of a `Field`, in an internal representation specific to that field.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FieldElement(Repr);
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Repr {
+    Small(u64),
+    Limbs([u64; 4]),
+    Big(BigUint),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Arith {
+    Small(u64),
+    Mont(Mont256),
+    Big,
+}
+
+impl Field {
+    /// Create a field from the canonical little-endian encoding of its largest element.
+    pub fn from_maximum(field_maximum: &[u8]) -> Result<Field> {
+        Self::from_modulus(&(BigUint::from_bytes_le(field_maximum) + 1_u32))
+    }
+
+    /// Create the field declared in `header.field_maximum`.
+    pub fn from_header(header: &CircuitHeader) -> Result<Field> {
+        let max = header.field_maximum.as_ref().ok_or_else(|| Error::Field("No field_maximum specified".into()))?;
+        Self::from_maximum(max)
+    }
+
+    /// Create a field from its order. The primality of the modulus is not checked.
+    pub fn from_modulus(modulus: &BigUint) -> Result<Field> {
+        if modulus < &BigUint::from(2_u32) {
+            return Err(Error::Field(format!("Invalid field modulus {}", modulus)));
+        }
+        let maximum = modulus - 1_u32;
+        let byte_len = maximum.to_bytes_le().len();
+
+        let arith = if modulus.bits() <= 64 {
+            Arith::Small(low_u64(modulus))
+        } else if modulus.bits() <= 256 && modulus.is_odd() {
+            Arith::Mont(Mont256::new(modulus))
+        } else {
+            Arith::Big
+        };
+
+        Ok(Field { modulus: modulus.clone(), byte_len, arith })
+    }
+
+    pub fn modulus(&self) -> &BigUint { &self.modulus }
+
+    /// The size of the canonical encoding of elements, which is the size of `field_maximum`.
+    pub fn byte_len(&self) -> usize { self.byte_len }
+
+    /// The canonical encoding of the largest element, as found in `CircuitHeader.field_maximum`.
+    pub fn encoded_maximum(&self) -> Vec<u8> {
+        self.encode(&self.neg(&self.one()))
+    }
+
+    pub fn zero(&self) -> FieldElement {
+        self.from_u64(0)
+    }
+
+    pub fn one(&self) -> FieldElement {
+        self.from_u64(1)
+    }
+
+    pub fn from_u64(&self, value: u64) -> FieldElement {
+        match self.arith {
+            Arith::Small(p) => FieldElement(Repr::Small(value % p)),
+            _ => self.from_biguint(&BigUint::from(value)),
+        }
+    }
+
+    /// Convert an integer into the field, reducing it modulo the field order.
+    pub fn from_biguint(&self, value: &BigUint) -> FieldElement {
+        let value = value % &self.modulus;
+        FieldElement(match &self.arith {
+            Arith::Small(_) => Repr::Small(low_u64(&value)),
+            Arith::Mont(mont) => Repr::Limbs(mont.to_mont(&to_limbs(&value))),
+            Arith::Big => Repr::Big(value),
+        })
+    }
+
+    /// The canonical integer representing an element, in the range [0, modulus).
+    pub fn to_biguint(&self, element: &FieldElement) -> BigUint {
+        match (&self.arith, &element.0) {
+            (_, Repr::Small(v)) => BigUint::from(*v),
+            (Arith::Mont(mont), Repr::Limbs(v)) => from_limbs(&mont.to_canonical(v)),
+            (_, Repr::Big(v)) => v.clone(),
+            _ => panic!("Element from another field"),
+        }
+    }
+
+    /// Decode a little-endian value, possibly truncated, and reduce it modulo the field order.
+    pub fn decode(&self, encoded: &[u8]) -> FieldElement {
+        if let Arith::Small(p) = self.arith {
+            if encoded.iter().skip(8).all(|b| *b == 0) {
+                let mut bytes = [0u8; 8];
+                let len = encoded.len().min(8);
+                bytes[..len].copy_from_slice(&encoded[..len]);
+                return FieldElement(Repr::Small(u64::from_le_bytes(bytes) % p));
+            }
+        }
+        self.from_biguint(&BigUint::from_bytes_le(encoded))
+    }
+
+    /// Whether a little-endian value, possibly truncated, is lower than the field order.
+    pub fn is_canonical(&self, encoded: &[u8]) -> bool {
+        BigUint::from_bytes_le(encoded) < self.modulus
+    }
+
+    /// Decode a little-endian value, possibly truncated, which must be lower than the field order.
+    pub fn decode_canonical(&self, encoded: &[u8]) -> Result<FieldElement> {
+        if !self.is_canonical(encoded) {
+            return Err(Error::Field(format!("The value {} is not an element of the field of order {}",
+                                            BigUint::from_bytes_le(encoded), self.modulus)));
+        }
+        Ok(self.decode(encoded))
+    }
+
+    /// The canonical little-endian encoding, padded to the size of `field_maximum`.
+    pub fn encode(&self, element: &FieldElement) -> Vec<u8> {
+        self.encode_with_width(element, self.byte_len)
+    }
+
+    /// The little-endian encoding truncated or padded to `width` bytes.
+    /// The width must be at least `encoded_size(element)`.
+    pub fn encode_with_width(&self, element: &FieldElement, width: usize) -> Vec<u8> {
+        let mut bytes = match &element.0 {
+            Repr::Small(v) => v.to_le_bytes().to_vec(),
+            _ => self.to_biguint(element).to_bytes_le(),
+        };
+        let size = trim_zeros(&bytes).len();
+        assert!(size <= width, "Cannot encode a field element in {} bytes", width);
+        bytes.resize(width, 0);
+        bytes
+    }
+
+    /// The minimal number of bytes to encode an element, without trailing zeros.
+    pub fn encoded_size(&self, element: &FieldElement) -> usize {
+        match &element.0 {
+            Repr::Small(v) => trim_zeros(&v.to_le_bytes()).len(),
+            _ => trim_zeros(&self.to_biguint(element).to_bytes_le()).len(),
+        }
+    }
+
+    /// Concatenate the canonical encodings of multiple elements, as in `Variables.values`.
+    pub fn encode_all(&self, elements: &[FieldElement]) -> Vec<u8> {
+        elements.iter().flat_map(|e| self.encode(e)).collect()
+    }
+
+    pub fn is_zero(&self, element: &FieldElement) -> bool {
+        match &element.0 {
+            Repr::Small(v) => *v == 0,
+            Repr::Limbs(v) => v.iter().all(|l| *l == 0),
+            Repr::Big(v) => v.is_zero(),
+        }
+    }
+
+    pub fn add(&self, a: &FieldElement, b: &FieldElement) -> FieldElement {
+        FieldElement(match (&self.arith, &a.0, &b.0) {
+            (Arith::Small(p), Repr::Small(a), Repr::Small(b)) =>
+                Repr::Small(((*a as u128 + *b as u128) % *p as u128) as u64),
+            (Arith::Mont(mont), Repr::Limbs(a), Repr::Limbs(b)) =>
+                Repr::Limbs(mont.add(a, b)),
+            (Arith::Big, Repr::Big(a), Repr::Big(b)) =>
+                Repr::Big((a + b) % &self.modulus),
+            _ => panic!("Element from another field"),
+        })
+    }
+
+    pub fn neg(&self, a: &FieldElement) -> FieldElement {
+        FieldElement(match (&self.arith, &a.0) {
+            (Arith::Small(p), Repr::Small(a)) =>
+                Repr::Small(if *a == 0 { 0 } else { p - a }),
+            (Arith::Mont(mont), Repr::Limbs(a)) =>
+                Repr::Limbs(mont.sub(&[0; 4], a)),
+            (Arith::Big, Repr::Big(a)) =>
+                Repr::Big(if a.is_zero() { BigUint::zero() } else { &self.modulus - a }),
+            _ => panic!("Element from another field"),
+        })
+    }
+
+    pub fn sub(&self, a: &FieldElement, b: &FieldElement) -> FieldElement {
+        self.add(a, &self.neg(b))
+    }
+
+    pub fn mul(&self, a: &FieldElement, b: &FieldElement) -> FieldElement {
+        FieldElement(match (&self.arith, &a.0, &b.0) {
+            (Arith::Small(p), Repr::Small(a), Repr::Small(b)) =>
+                Repr::Small(((*a as u128 * *b as u128) % *p as u128) as u64),
+            (Arith::Mont(mont), Repr::Limbs(a), Repr::Limbs(b)) =>
+                Repr::Limbs(mont.mul(a, b)),
+            (Arith::Big, Repr::Big(a), Repr::Big(b)) =>
+                Repr::Big((a * b) % &self.modulus),
+            _ => panic!("Element from another field"),
+        })
+    }
+
+    /// The multiplicative inverse, or None for zero.
+    pub fn inverse(&self, a: &FieldElement) -> Option<FieldElement> {
+        let a = BigInt::from_biguint(Sign::Plus, self.to_biguint(a));
+        let modulus = BigInt::from_biguint(Sign::Plus, self.modulus.clone());
+        let egcd = a.extended_gcd(&modulus);
+        if !egcd.gcd.is_one() {
+            return None;
+        }
+        let inv = egcd.x.mod_floor(&modulus);
+        Some(self.from_biguint(&inv.to_biguint().unwrap()))
+    }
+
+    /// A uniformly random element.
+    pub fn random(&self, rng: &mut impl Rng) -> FieldElement {
+        self.from_biguint(&rng.gen_biguint_below(&self.modulus))
+    }
+}
+
+/// Remove the trailing zeros of a little-endian number.
+pub(crate) fn trim_zeros(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    &bytes[..len]
+}
+
+fn low_u64(value: &BigUint) -> u64 {
+    value.to_u64_digits().first().cloned().unwrap_or(0)
+}
+
+fn to_limbs(value: &BigUint) -> [u64; 4] {
+    let mut limbs = [0u64; 4];
+    for (limb, digit) in limbs.iter_mut().zip(value.to_u64_digits()) {
+        *limb = digit;
+    }
+    limbs
+}
+
+fn from_limbs(limbs: &[u64]) -> BigUint {
+    let bytes: Vec<u8> = limbs.iter().flat_map(|l| l.to_le_bytes().to_vec()).collect();
+    BigUint::from_bytes_le(&bytes)
+}
+
+/// Montgomery arithmetic with four 64-bit limbs, for odd moduli below 2^256.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Mont256 {
+    p: [u64; 4],
+    /// -p^-1 mod 2^64
+    inv: u64,
+    /// R^2 mod p, with R = 2^256
+    r2: [u64; 4],
+}
+
+impl Mont256 {
+    fn new(modulus: &BigUint) -> Mont256 {
+        let p = to_limbs(modulus);
+
+        // Newton iteration for p^-1 mod 2^64; each step doubles the number of correct bits.
+        let mut inv = p[0];
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2_u64.wrapping_sub(p[0].wrapping_mul(inv)));
+        }
+
+        let r2 = (BigUint::one() << 512_usize) % modulus;
+        Mont256 { p, inv: inv.wrapping_neg(), r2: to_limbs(&r2) }
+    }
+
+    fn to_mont(&self, a: &[u64; 4]) -> [u64; 4] {
+        self.mul(a, &self.r2)
+    }
+
+    fn to_canonical(&self, a: &[u64; 4]) -> [u64; 4] {
+        self.mul(a, &[1, 0, 0, 0])
+    }
+
+    fn add(&self, a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+        let (sum, carry) = add_limbs(a, b);
+        if carry || !less_than(&sum, &self.p) {
+            sub_limbs(&sum, &self.p).0
+        } else {
+            sum
+        }
+    }
+
+    fn sub(&self, a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+        let (diff, borrow) = sub_limbs(a, b);
+        if borrow { add_limbs(&diff, &self.p).0 } else { diff }
+    }
+
+    /// Coarsely Integrated Operand Scanning (CIOS) Montgomery multiplication.
+    fn mul(&self, a: &[u64; 4], b: &[u64; 4]) -> [u64; 4] {
+        let mut t = [0u64; 6];
+        for b_i in b.iter() {
+            let mut carry = 0;
+            for j in 0..4 {
+                let (lo, hi) = mac(t[j], a[j], *b_i, carry);
+                t[j] = lo;
+                carry = hi;
+            }
+            let (lo, hi) = mac(t[4], 1, carry, 0);
+            t[4] = lo;
+            t[5] = hi;
+
+            let m = t[0].wrapping_mul(self.inv);
+            let (_, mut carry) = mac(t[0], m, self.p[0], 0);
+            for j in 1..4 {
+                let (lo, hi) = mac(t[j], m, self.p[j], carry);
+                t[j - 1] = lo;
+                carry = hi;
+            }
+            let (lo, hi) = mac(t[4], 1, carry, 0);
+            t[3] = lo;
+            t[4] = t[5] + hi;
+        }
+
+        let result = [t[0], t[1], t[2], t[3]];
+        if t[4] != 0 || !less_than(&result, &self.p) {
+            sub_limbs(&result, &self.p).0
+        } else {
+            result
+        }
+    }
+}
+
+/// a + b * c + carry, as (low, high) words.
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + (b as u128) * (c as u128) + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+fn add_limbs(a: &[u64; 4], b: &[u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut carry = false;
+    for i in 0..4 {
+        let (s1, c1) = a[i].overflowing_add(b[i]);
+        let (s2, c2) = s1.overflowing_add(carry as u64);
+        out[i] = s2;
+        carry = c1 || c2;
+    }
+    (out, carry)
+}
+
+fn sub_limbs(a: &[u64; 4], b: &[u64; 4]) -> ([u64; 4], bool) {
+    let mut out = [0u64; 4];
+    let mut borrow = false;
+    for i in 0..4 {
+        let (d1, b1) = a[i].overflowing_sub(b[i]);
+        let (d2, b2) = d1.overflowing_sub(borrow as u64);
+        out[i] = d2;
+        borrow = b1 || b2;
+    }
+    (out, borrow)
+}
+
+fn less_than(a: &[u64; 4], b: &[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+
+#[test]
+fn test_field_arithmetic() -> Result<()> {
+    use num_traits::Num;
+
+    let moduli = [
+        "2",
+        "65",
+        "FFFFFFFFFFFFFFC5", // 2**64 - 59
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFF61", // 2**128 - 159
+        "73EDA753299D7D483339D80809A1D80553BDA402FFFE5BFEFFFFFFFF00000001", // BLS12-381 scalar field
+        "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF43", // 2**256 - 189
+        "10000000000000000000000000000000000000000000000000000000000000129", // Above 2**256
+    ];
+    let mut rng = rand::thread_rng();
+
+    for hex in moduli.iter() {
+        let p = BigUint::from_str_radix(hex, 16)?;
+        let field = Field::from_modulus(&p)?;
+        assert_eq!(field.decode(&field.encoded_maximum()), field.neg(&field.one()));
+
+        for _ in 0..50 {
+            let a = rng.gen_biguint_below(&p);
+            let b = rng.gen_biguint_below(&p);
+            let (fa, fb) = (field.from_biguint(&a), field.from_biguint(&b));
+
+            assert_eq!(field.to_biguint(&fa), a);
+            assert_eq!(field.decode(&a.to_bytes_le()), fa);
+            assert_eq!(field.decode(&field.encode(&fa)), fa);
+            assert_eq!(field.to_biguint(&field.add(&fa, &fb)), (&a + &b) % &p);
+            assert_eq!(field.to_biguint(&field.mul(&fa, &fb)), (&a * &b) % &p);
+            assert_eq!(field.to_biguint(&field.sub(&fa, &fb)), (&a + &p - &b) % &p);
+            assert!(field.is_zero(&field.add(&fa, &field.neg(&fa))));
+            if let Some(inv) = field.inverse(&fa) {
+                assert_eq!(field.mul(&fa, &inv), field.one());
+            }
+        }
+
+        assert!(field.inverse(&field.zero()).is_none());
+        assert!(!field.is_canonical(&p.to_bytes_le()));
+        assert!(field.decode_canonical(&p.to_bytes_le()).is_err());
+    }
+    Ok(())
+}
@@ -0,0 +1,367 @@
+//! Conversion from and to the binary formats of iden3 and circom: `.r1cs` for constraints and `.wtns` for witnesses.
+//!
+//! Wires map to variables with the same IDs. Wire 0 is the constant one. It is followed by the public outputs,
+//! then the public inputs, which together form the `instance_variables`, in this order.
+//! The remaining wires are witness variables.
+//!
+//! The number of public outputs is kept in the header configuration under the key `NUM_PUBLIC_OUTPUTS_KEY`,
+//! so that a statement converted back to `.r1cs` keeps the same outputs and inputs.
+
+use std::convert::TryFrom;
+use std::io::Write;
+use num_bigint::BigUint;
+
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, BilinearConstraint, Variables, Witness, KeyValue, Field};
+
+const R1CS_MAGIC: &[u8] = b"r1cs";
+const WTNS_MAGIC: &[u8] = b"wtns";
+const R1CS_VERSION: u32 = 1;
+const WTNS_VERSION: u32 = 2;
+
+const R1CS_HEADER_SECTION: u32 = 1;
+const R1CS_CONSTRAINTS_SECTION: u32 = 2;
+const R1CS_WIRE_TO_LABEL_SECTION: u32 = 3;
+const WTNS_HEADER_SECTION: u32 = 1;
+const WTNS_VALUES_SECTION: u32 = 2;
+
+/// The header configuration key holding the number of public outputs among the instance variables.
+pub const NUM_PUBLIC_OUTPUTS_KEY: &str = "iden3_num_public_outputs";
+
+/// Read a `.r1cs` file into a header and constraints.
+/// The header has no instance values; see `read_wtns` to assign them.
+pub fn read_r1cs(buf: &[u8]) -> Result<(CircuitHeader, ConstraintSystem)> {
+    let sections = read_sections(buf, R1CS_MAGIC, R1CS_VERSION)?;
+
+    let mut header = Cursor::new(find_section(&sections, R1CS_HEADER_SECTION)?);
+    let n8 = header.read_u32()? as usize;
+    let prime = BigUint::from_bytes_le(header.read_bytes(n8)?);
+    let n_wires = header.read_u32()? as u64;
+    let n_pub_out = header.read_u32()? as u64;
+    let n_pub_in = header.read_u32()? as u64;
+    let _n_prv_in = header.read_u32()?;
+    let _n_labels = header.read_u64()?;
+    let n_constraints = header.read_u32()?;
+
+    let field = Field::from_modulus(&prime)?;
+    let num_public = n_pub_out + n_pub_in;
+    if num_public >= n_wires {
+        return Err(Error::Format(format!("Invalid .r1cs header: {} public wires out of {}", num_public, n_wires)));
+    }
+
+    // Counts come from the file, so capacities are bounded by the bytes left to read.
+    let mut section = Cursor::new(find_section(&sections, R1CS_CONSTRAINTS_SECTION)?);
+    let mut constraints = Vec::with_capacity((n_constraints as usize).min(section.remaining() / (3 * 4)));
+    let mut read_lc = || -> Result<Variables> {
+        let n_terms = section.read_u32()? as usize;
+        let capacity = n_terms.min(section.remaining() / (4 + n8));
+        let mut lc = Variables { variable_ids: Vec::with_capacity(capacity), values: Some(Vec::with_capacity(capacity * n8)), info: None };
+        for _ in 0..n_terms {
+            let wire = section.read_u32()? as u64;
+            if wire >= n_wires {
+                return Err(Error::Format(format!("Invalid .r1cs constraint: wire {} out of {}", wire, n_wires)));
+            }
+            lc.variable_ids.push(wire);
+            lc.values.as_mut().unwrap().extend_from_slice(section.read_bytes(n8)?);
+        }
+        Ok(lc)
+    };
+    for _ in 0..n_constraints {
+        constraints.push(BilinearConstraint {
+            linear_combination_a: read_lc()?,
+            linear_combination_b: read_lc()?,
+            linear_combination_c: read_lc()?,
+        });
+    }
+
+    let header = CircuitHeader {
+        instance_variables: Variables {
+            variable_ids: (1..=num_public).collect(),
+            values: None,
+            info: None,
+        },
+        free_variable_id: n_wires,
+        field_maximum: Some(field.encoded_maximum()),
+        configuration: Some(vec![KeyValue::from((NUM_PUBLIC_OUTPUTS_KEY, n_pub_out as i64))]),
+    };
+    Ok((header, ConstraintSystem { constraints }))
+}
+
+/// Read a `.wtns` file. Assign the values of instance variables into the header,
+/// and return the values of the other variables as a witness.
+pub fn read_wtns(buf: &[u8], header: &mut CircuitHeader) -> Result<Witness> {
+    let sections = read_sections(buf, WTNS_MAGIC, WTNS_VERSION)?;
+
+    let mut section = Cursor::new(find_section(&sections, WTNS_HEADER_SECTION)?);
+    let n8 = section.read_u32()? as usize;
+    let prime = BigUint::from_bytes_le(section.read_bytes(n8)?);
+    let n_values = section.read_u32()? as u64;
+
+    let field = Field::from_header(header)?;
+    if &prime != field.modulus() {
+        return Err(Error::Field(format!("The .wtns field {} does not match the field of the header {}", prime, field.modulus())));
+    }
+    if n_values != header.free_variable_id {
+        return Err(Error::Format(format!("The .wtns file has {} values but the circuit has {} wires", n_values, header.free_variable_id)));
+    }
+
+    let mut section = Cursor::new(find_section(&sections, WTNS_VALUES_SECTION)?);
+    let mut instance_values = vec![];
+    let mut witness = Variables { variable_ids: vec![], values: Some(vec![]), info: None };
+    let num_public = header.instance_variables.variable_ids.len() as u64;
+
+    section.read_bytes(n8)?; // The constant one.
+    for id in 1..n_values {
+        let value = section.read_bytes(n8)?;
+        if id <= num_public {
+            instance_values.extend_from_slice(value);
+        } else {
+            witness.variable_ids.push(id);
+            witness.values.as_mut().unwrap().extend_from_slice(value);
+        }
+    }
+
+    header.instance_variables.values = Some(instance_values);
+    Ok(Witness { assigned_variables: witness })
+}
+
+/// Write a header and constraints in the `.r1cs` format.
+/// The instance variables must be the first variables, with IDs from 1.
+pub fn write_r1cs(header: &CircuitHeader, systems: &[ConstraintSystem], writer: &mut impl Write) -> Result<()> {
+    let field = Field::from_header(header)?;
+    let n8 = field_size(&field);
+    let (n_pub_out, n_pub_in) = public_wires(header)?;
+    let n_wires = u32::try_from(header.free_variable_id)?;
+    let constraints: Vec<&BilinearConstraint> = systems.iter().flat_map(|cs| &cs.constraints).collect();
+
+    let mut section = vec![];
+    section.extend_from_slice(&(n8 as u32).to_le_bytes());
+    section.extend_from_slice(&le_bytes(field.modulus(), n8));
+    section.extend_from_slice(&n_wires.to_le_bytes());
+    section.extend_from_slice(&n_pub_out.to_le_bytes());
+    section.extend_from_slice(&n_pub_in.to_le_bytes());
+    section.extend_from_slice(&0u32.to_le_bytes()); // Private inputs are not distinguished.
+    section.extend_from_slice(&(n_wires as u64).to_le_bytes()); // Labels.
+    section.extend_from_slice(&u32::try_from(constraints.len())?.to_le_bytes());
+    let header_section = section;
+
+    let mut section = vec![];
+    for constraint in constraints {
+        for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+            let terms = lc.get_variables();
+            section.extend_from_slice(&u32::try_from(terms.len())?.to_le_bytes());
+            for term in terms {
+                if term.id >= header.free_variable_id {
+                    return Err(Error::InvalidMessage(format!("variable_{} is beyond free_variable_id {}", term.id, header.free_variable_id)));
+                }
+                section.extend_from_slice(&(term.id as u32).to_le_bytes());
+                section.extend_from_slice(&le_bytes(&field.to_biguint(&field.decode(term.value)), n8));
+            }
+        }
+    }
+    let constraints_section = section;
+
+    // Labels are the wire IDs.
+    let wire_to_label_section: Vec<u8> = (0..n_wires as u64).flat_map(|id| id.to_le_bytes().to_vec()).collect();
+
+    write_sections(writer, R1CS_MAGIC, R1CS_VERSION, &[
+        (R1CS_HEADER_SECTION, &header_section),
+        (R1CS_CONSTRAINTS_SECTION, &constraints_section),
+        (R1CS_WIRE_TO_LABEL_SECTION, &wire_to_label_section),
+    ])
+}
+
+/// Write the values of all variables in the `.wtns` format, from the instance values of the header and from witnesses.
+pub fn write_wtns(header: &CircuitHeader, witnesses: &[Witness], writer: &mut impl Write) -> Result<()> {
+    let field = Field::from_header(header)?;
+    let n8 = field_size(&field);
+    public_wires(header)?;
+    let n_wires = u32::try_from(header.free_variable_id)?;
+
+    let mut values: Vec<Option<&[u8]>> = vec![None; n_wires as usize];
+    let all_vars = Some(&header.instance_variables).into_iter()
+        .chain(witnesses.iter().map(|w| &w.assigned_variables));
+    for vars in all_vars {
+        for var in vars.get_variables() {
+            if var.id >= header.free_variable_id {
+                return Err(Error::InvalidMessage(format!("variable_{} is beyond free_variable_id {}", var.id, header.free_variable_id)));
+            }
+            if var.has_value() {
+                values[var.id as usize] = Some(var.value);
+            }
+        }
+    }
+
+    let mut header_section = vec![];
+    header_section.extend_from_slice(&(n8 as u32).to_le_bytes());
+    header_section.extend_from_slice(&le_bytes(field.modulus(), n8));
+    header_section.extend_from_slice(&n_wires.to_le_bytes());
+
+    let mut values_section = le_bytes(&BigUint::from(1u32), n8);
+    for (id, value) in values.iter().enumerate().skip(1) {
+        let value = value.ok_or_else(|| Error::Unsatisfied(format!("No value given for variable_{}", id)))?;
+        values_section.extend_from_slice(&le_bytes(&field.to_biguint(&field.decode(value)), n8));
+    }
+
+    write_sections(writer, WTNS_MAGIC, WTNS_VERSION, &[
+        (WTNS_HEADER_SECTION, &header_section),
+        (WTNS_VALUES_SECTION, &values_section),
+    ])
+}
+
+/// The number of public outputs and inputs, checking that instance variables come first.
+fn public_wires(header: &CircuitHeader) -> Result<(u32, u32)> {
+    let ids = &header.instance_variables.variable_ids;
+    if ids.iter().enumerate().any(|(i, id)| *id != i as u64 + 1) {
+        return Err(Error::Format("The instance variables must have IDs 1, 2, 3, ... to be converted to the iden3 format.".into()));
+    }
+    let num_public = u32::try_from(ids.len())
+        .map_err(|_| Error::Format("Too many instance variables for the iden3 format.".into()))?;
+    let n_pub_out = match header.configuration.iter().flatten().find(|kv| kv.key == NUM_PUBLIC_OUTPUTS_KEY) {
+        Some(kv) => u32::try_from(kv.number)
+            .map_err(|_| Error::Format(format!("Invalid number of public outputs: {}", kv.number)))?,
+        None => 0,
+    };
+    let n_pub_out = n_pub_out.min(num_public);
+    Ok((n_pub_out, num_public - n_pub_out))
+}
+
+/// The size of field elements in bytes, a multiple of 8 as in circom.
+fn field_size(field: &Field) -> usize {
+    field.byte_len().div_ceil(8) * 8
+}
+
+fn le_bytes(value: &BigUint, width: usize) -> Vec<u8> {
+    let mut bytes = value.to_bytes_le();
+    bytes.resize(width, 0);
+    bytes
+}
+
+fn read_sections<'a>(buf: &'a [u8], magic: &[u8], version: u32) -> Result<Vec<(u32, &'a [u8])>> {
+    let mut cursor = Cursor::new(buf);
+    if cursor.read_bytes(4)? != magic {
+        return Err(Error::Format(format!("Not a .{} file", String::from_utf8_lossy(magic))));
+    }
+    let file_version = cursor.read_u32()?;
+    if file_version != version {
+        return Err(Error::Format(format!("Unsupported .{} version {}", String::from_utf8_lossy(magic), file_version)));
+    }
+    let n_sections = cursor.read_u32()?;
+    let mut sections = vec![];
+    for _ in 0..n_sections {
+        let section_type = cursor.read_u32()?;
+        let size = usize::try_from(cursor.read_u64()?)?;
+        sections.push((section_type, cursor.read_bytes(size)?));
+    }
+    Ok(sections)
+}
+
+fn find_section<'a>(sections: &[(u32, &'a [u8])], section_type: u32) -> Result<&'a [u8]> {
+    sections.iter()
+        .find(|(t, _)| *t == section_type)
+        .map(|(_, content)| *content)
+        .ok_or_else(|| Error::Format(format!("Missing section {}", section_type)))
+}
+
+fn write_sections(writer: &mut impl Write, magic: &[u8], version: u32, sections: &[(u32, &[u8])]) -> Result<()> {
+    writer.write_all(magic)?;
+    writer.write_all(&version.to_le_bytes())?;
+    writer.write_all(&(sections.len() as u32).to_le_bytes())?;
+    for (section_type, content) in sections {
+        writer.write_all(&section_type.to_le_bytes())?;
+        writer.write_all(&(content.len() as u64).to_le_bytes())?;
+        writer.write_all(content)?;
+    }
+    Ok(())
+}
+
+/// Read little-endian numbers and byte strings, with bounds checks.
+struct Cursor<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Cursor { buf }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.buf.len() {
+            return Err(Error::Format("Unexpected end of file".into()));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(<[u8; 4]>::try_from(self.read_bytes(4)?)?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(<[u8; 8]>::try_from(self.read_bytes(8)?)?))
+    }
+}
+
+
+#[test]
+fn test_iden3() -> Result<()> {
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::*;
+
+    let header = example_circuit_header();
+    let cs = example_constraints();
+    let witness = example_witness();
+
+    let mut r1cs = vec![];
+    write_r1cs(&header, &[cs.clone()], &mut r1cs)?;
+    let mut wtns = vec![];
+    write_wtns(&header, &[witness.clone()], &mut wtns)?;
+
+    let (mut header2, cs2) = read_r1cs(&r1cs)?;
+    let witness2 = read_wtns(&wtns, &mut header2)?;
+    assert_eq!(header2.instance_variables.variable_ids, header.instance_variables.variable_ids);
+    assert_eq!(header2.free_variable_id, header.free_variable_id);
+    let field = Field::from_header(&header)?;
+    assert_eq!(Field::from_header(&header2)?.modulus(), field.modulus());
+    assert_eq!(witness2.assigned_variables.variable_ids, vec![4, 5]);
+
+    assert_eq!(cs2.normalize(&field), cs.normalize(&field));
+
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&header2)?;
+    simulator.ingest_witness(&witness2)?;
+    simulator.ingest_constraint_system(&cs2)?;
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+
+    // The outputs are kept through a round-trip.
+    header2.configuration = Some(vec![KeyValue::from((NUM_PUBLIC_OUTPUTS_KEY, 1 as i64))]);
+    let mut r1cs = vec![];
+    write_r1cs(&header2, &[cs2], &mut r1cs)?;
+    let (header3, _) = read_r1cs(&r1cs)?;
+    assert_eq!(header3.configuration, header2.configuration);
+
+    // A number of outputs that does not fit is an error, not a wrapped value.
+    header2.configuration = Some(vec![KeyValue::from((NUM_PUBLIC_OUTPUTS_KEY, -1 as i64))]);
+    assert!(matches!(write_r1cs(&header2, &[], &mut vec![]), Err(Error::Format(_))));
+
+    assert!(read_r1cs(&r1cs[..r1cs.len() - 1]).is_err());
+    assert!(read_r1cs(&wtns).is_err());
+
+    // Huge counts in a short file are an error, not a huge allocation.
+    let mut header_section = vec![];
+    for value in &[8_u32, 101, 0, 5, 0, 3, 1, 0, 0, u32::MAX] {
+        header_section.extend_from_slice(&value.to_le_bytes());
+    }
+    let constraints_section = u32::MAX.to_le_bytes();
+    let mut hostile = vec![];
+    write_sections(&mut hostile, R1CS_MAGIC, R1CS_VERSION, &[
+        (R1CS_HEADER_SECTION, &header_section),
+        (R1CS_CONSTRAINTS_SECTION, &constraints_section),
+    ])?;
+    assert!(read_r1cs(&hostile).is_err());
+    Ok(())
+}
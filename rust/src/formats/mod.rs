@@ -0,0 +1,2 @@
+pub mod iden3;
+pub mod text;
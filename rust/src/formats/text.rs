@@ -0,0 +1,549 @@
+//! A human-readable text format for statements, with a parser and a printer.
+//!
+//! ```text
+//! # x^2 + y^2 = zz
+//! field 101
+//! config Name = "example"
+//! instance x = 3
+//! instance y = 4
+//! instance zz = 25
+//! witness xx = 9
+//! witness yy = 16
+//! (x) * (x) = (xx)
+//! (y) * (y) = (yy)
+//! (1) * (xx + yy) = (zz)
+//! ```
+//!
+//! - `field` declares the order of the field. It comes first.
+//! - `config` adds an entry to the header configuration, with a text, a number, or bytes `[1, 2]`.
+//!   Texts are quoted, with the escapes `\"`, `\\`, `\n`, `\r`, and `\t`.
+//! - `instance` and `witness` declare variables, with an optional value. A negative value is reduced modulo the field.
+//!   Variables get IDs 1, 2, 3… in the order of declaration. The constant one is written `1`.
+//!   Names of the form `var_N` are not recorded in the variables `info`.
+//!   Names of witness variables are kept in the witness, which exists only if some value is given.
+//! - Constraints are written `(A) * (B) = (C)`, where each linear combination is a sum of terms
+//!   like `x`, `3*x`, `- x`, or a constant `5`. The empty combination is written `(0)`.
+//! - `#` starts a comment.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use num_bigint::BigUint;
+
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, BilinearConstraint, Variables, Witness, KeyValue, Messages, Field, FieldElement};
+
+/// Parse a statement in the text format into a header, constraints, and a witness if any value was given.
+///
+/// # Example
+/// ```
+/// use zkinterface::formats::text::read_text;
+///
+/// let messages = read_text("
+///     field 101
+///     instance x = 3
+///     witness xx = 9
+///     (x) * (x) = (xx)
+/// ").unwrap();
+///
+/// assert_eq!(messages.circuit_headers[0].free_variable_id, 3);
+/// assert_eq!(messages.constraint_systems[0].constraints.len(), 1);
+/// assert_eq!(messages.witnesses[0].assigned_variables.get_names().get(&2).unwrap(), "xx");
+/// ```
+pub fn read_text(text: &str) -> Result<Messages> {
+    let mut parser = Parser::default();
+    for (i, line) in text.lines().enumerate() {
+        parser.parse_line(line)
+            .map_err(|err| Error::Format(format!("Line {}: {}", i + 1, err)))?;
+    }
+    parser.finish()
+}
+
+/// Print a statement in the text format.
+/// The first header gives the field. Constraints and witnesses of all messages are printed.
+pub fn write_text(messages: &Messages, out: &mut impl Write) -> Result<()> {
+    let header = messages.circuit_headers.first().ok_or(Error::MissingHeader)?;
+    let field = Field::from_header(header)?;
+
+    let mut instance_values = HashMap::new();
+    let mut witness_values = HashMap::new();
+    let mut names = HashMap::new();
+    for header in &messages.circuit_headers {
+        collect_values(&field, &header.instance_variables, &mut instance_values, &mut names);
+    }
+    for witness in &messages.witnesses {
+        collect_values(&field, &witness.assigned_variables, &mut witness_values, &mut names);
+    }
+
+    // Declare all variables up to the last one, so that their IDs are kept.
+    let mut last_id = header.free_variable_id.saturating_sub(1);
+    for constraint in messages.constraint_systems.iter().flat_map(|cs| &cs.constraints) {
+        for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+            last_id = lc.variable_ids.iter().cloned().chain(Some(last_id)).max().unwrap();
+        }
+    }
+    let instance_ids: HashSet<u64> = messages.circuit_headers.iter()
+        .flat_map(|h| h.instance_variables.variable_ids.iter().cloned())
+        .collect();
+
+    let mut taken = HashSet::new();
+    let mut var_names = HashMap::new();
+    for id in 1..=last_id {
+        let name = match names.get(&id) {
+            Some(name) if is_identifier(name) && !name.starts_with("var_") && !taken.contains(name) => name.clone(),
+            _ => format!("var_{}", id),
+        };
+        taken.insert(name.clone());
+        var_names.insert(id, name);
+    }
+
+    writeln!(out, "field {}", field.modulus())?;
+    for kv in header.configuration.iter().flatten() {
+        match (&kv.text, &kv.data) {
+            (Some(text), _) => writeln!(out, "config {} = {}", kv.key, quote(text))?,
+            (None, Some(data)) => writeln!(out, "config {} = {:?}", kv.key, data)?,
+            (None, None) => writeln!(out, "config {} = {}", kv.key, kv.number)?,
+        }
+    }
+
+    for id in 1..=last_id {
+        let (kind, values) = if instance_ids.contains(&id) {
+            ("instance", &instance_values)
+        } else {
+            ("witness", &witness_values)
+        };
+        match values.get(&id) {
+            Some(value) => writeln!(out, "{} {} = {}", kind, var_names[&id], value)?,
+            None => writeln!(out, "{} {}", kind, var_names[&id])?,
+        }
+    }
+
+    for constraint in messages.constraint_systems.iter().flat_map(|cs| &cs.constraints) {
+        writeln!(out, "({}) * ({}) = ({})",
+                 format_lc(&field, &var_names, &constraint.linear_combination_a),
+                 format_lc(&field, &var_names, &constraint.linear_combination_b),
+                 format_lc(&field, &var_names, &constraint.linear_combination_c))?;
+    }
+    Ok(())
+}
+
+fn collect_values(field: &Field, vars: &Variables, values: &mut HashMap<u64, BigUint>, names: &mut HashMap<u64, String>) {
+    for var in vars.get_variables() {
+        if var.has_value() {
+            values.insert(var.id, field.to_biguint(&field.decode(var.value)));
+        }
+    }
+    names.extend(vars.get_names());
+}
+
+fn format_lc(field: &Field, names: &HashMap<u64, String>, lc: &Variables) -> String {
+    let half = field.modulus() / 2_u32;
+    let mut out = String::new();
+    for term in lc.get_variables() {
+        let mut coeff = field.to_biguint(&field.decode(term.value));
+        // Print large coefficients as negative numbers.
+        let negative = coeff > half;
+        if negative {
+            coeff = field.modulus() - coeff;
+        }
+        match (out.is_empty(), negative) {
+            (true, false) => {}
+            (true, true) => out.push_str("- "),
+            (false, false) => out.push_str(" + "),
+            (false, true) => out.push_str(" - "),
+        }
+        let name = match names.get(&term.id) {
+            Some(name) => name.clone(),
+            None => format!("var_{}", term.id),
+        };
+        if term.id == 0 {
+            out.push_str(&coeff.to_string());
+        } else if coeff == BigUint::from(1_u32) {
+            out.push_str(&name);
+        } else {
+            out.push_str(&format!("{}*{}", coeff, name));
+        }
+    }
+    if out.is_empty() { "0".to_string() } else { out }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+
+#[derive(Default)]
+struct Parser {
+    field: Option<Field>,
+    configuration: Vec<KeyValue>,
+    ids: HashMap<String, u64>,
+    next_id: u64,
+    instance: Vec<(u64, Option<FieldElement>)>,
+    instance_names: Vec<(u64, String)>,
+    witness: Variables,
+    witness_values: Vec<FieldElement>,
+    constraints: Vec<BilinearConstraint>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'a> {
+    Number(&'a str),
+    Ident(&'a str),
+    Text(String),
+    Punct(char),
+}
+
+impl Parser {
+    fn parse_line(&mut self, line: &str) -> Result<()> {
+        let tokens = tokenize(strip_comment(line))?;
+        match tokens.first() {
+            None => Ok(()),
+            Some(Token::Ident("field")) => self.parse_field(&tokens[1..]),
+            Some(Token::Ident("config")) => self.parse_config(&tokens[1..]),
+            Some(Token::Ident("instance")) => self.parse_declaration(&tokens[1..], true),
+            Some(Token::Ident("witness")) => self.parse_declaration(&tokens[1..], false),
+            Some(Token::Punct('(')) => self.parse_constraint(&tokens),
+            Some(token) => Err(Error::Format(format!("Unexpected {:?}", token))),
+        }
+    }
+
+    fn parse_field(&mut self, tokens: &[Token]) -> Result<()> {
+        if self.field.is_some() {
+            return Err(Error::Format("The field is declared twice".into()));
+        }
+        match tokens {
+            [Token::Number(order)] => {
+                self.field = Some(Field::from_modulus(&parse_number(order)?)?);
+                self.next_id = 1;
+                Ok(())
+            }
+            _ => Err(Error::Format("Expected: field <order>".into())),
+        }
+    }
+
+    fn parse_config(&mut self, tokens: &[Token]) -> Result<()> {
+        let (key, value) = match tokens {
+            [Token::Ident(key), Token::Punct('='), value @ ..] => (key.to_string(), value),
+            _ => return Err(Error::Format("Expected: config <key> = <value>".into())),
+        };
+        let kv = match value {
+            [Token::Text(text)] => KeyValue::from((key, text.clone())),
+            [Token::Number(number)] => KeyValue::from((key, number.parse::<i64>()?)),
+            [Token::Punct('-'), Token::Number(number)] => KeyValue::from((key, -number.parse::<i64>()?)),
+            [Token::Punct('['), .., Token::Punct(']')] => {
+                let mut data = vec![];
+                for (i, token) in value[1..value.len() - 1].iter().enumerate() {
+                    match (i % 2, token) {
+                        (0, Token::Number(byte)) => data.push(byte.parse::<u8>()?),
+                        (1, Token::Punct(',')) => {}
+                        _ => return Err(Error::Format(format!("Unexpected {:?} in bytes", token))),
+                    }
+                }
+                KeyValue::from((key, data))
+            }
+            _ => return Err(Error::Format("Expected a text, a number, or bytes".into())),
+        };
+        self.configuration.push(kv);
+        Ok(())
+    }
+
+    fn parse_declaration(&mut self, tokens: &[Token], is_instance: bool) -> Result<()> {
+        let field = self.field.as_ref().ok_or_else(|| Error::Format("The field must be declared first".into()))?;
+        let (name, value) = match tokens {
+            [Token::Ident(name)] => (name, None),
+            [Token::Ident(name), Token::Punct('='), Token::Number(value)] =>
+                (name, Some(field.from_biguint(&parse_number(value)?))),
+            // A negative value is reduced modulo the field.
+            [Token::Ident(name), Token::Punct('='), Token::Punct('-'), Token::Number(value)] =>
+                (name, Some(field.neg(&field.from_biguint(&parse_number(value)?)))),
+            _ => return Err(Error::Format("Expected: instance|witness <name> [= [-]<value>]".into())),
+        };
+        if self.ids.contains_key(*name) {
+            return Err(Error::Format(format!("Variable {} is declared twice", name)));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ids.insert(name.to_string(), id);
+        let named = !name.starts_with("var_");
+
+        if is_instance {
+            self.instance.push((id, value));
+            if named {
+                self.instance_names.push((id, name.to_string()));
+            }
+        } else {
+            if let Some(value) = value {
+                self.witness.variable_ids.push(id);
+                self.witness_values.push(value);
+            }
+            if named {
+                self.witness.set_name(id, name);
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_constraint(&mut self, tokens: &[Token]) -> Result<()> {
+        let mut rest = tokens;
+        let a = self.parse_lc(&mut rest)?;
+        expect(&mut rest, '*')?;
+        let b = self.parse_lc(&mut rest)?;
+        expect(&mut rest, '=')?;
+        let c = self.parse_lc(&mut rest)?;
+        if let Some(token) = rest.first() {
+            return Err(Error::Format(format!("Unexpected {:?} after the constraint", token)));
+        }
+        self.constraints.push(BilinearConstraint {
+            linear_combination_a: a,
+            linear_combination_b: b,
+            linear_combination_c: c,
+        });
+        Ok(())
+    }
+
+    /// Parse `( term [+|- term]* )`.
+    fn parse_lc(&self, tokens: &mut &[Token]) -> Result<Variables> {
+        let field = self.field.as_ref().ok_or_else(|| Error::Format("The field must be declared first".into()))?;
+        expect(tokens, '(')?;
+
+        let mut ids = vec![];
+        let mut coeffs = vec![];
+        let mut negative = matches!(tokens.first(), Some(Token::Punct('-')));
+        if negative { *tokens = &tokens[1..]; }
+
+        loop {
+            let (id, coeff) = match *tokens {
+                [Token::Number(k), Token::Punct('*'), Token::Ident(name), ..] => {
+                    *tokens = &tokens[3..];
+                    (self.lookup(name)?, field.from_biguint(&parse_number(k)?))
+                }
+                [Token::Number(k), ..] => {
+                    *tokens = &tokens[1..];
+                    (0, field.from_biguint(&parse_number(k)?))
+                }
+                [Token::Ident(name), ..] => {
+                    *tokens = &tokens[1..];
+                    (self.lookup(name)?, field.one())
+                }
+                _ => return Err(Error::Format("Expected a term".into())),
+            };
+            let coeff = if negative { field.neg(&coeff) } else { coeff };
+            if !field.is_zero(&coeff) {
+                ids.push(id);
+                coeffs.push(coeff);
+            }
+
+            match tokens.first() {
+                Some(Token::Punct('+')) => negative = false,
+                Some(Token::Punct('-')) => negative = true,
+                _ => break,
+            }
+            *tokens = &tokens[1..];
+        }
+
+        expect(tokens, ')')?;
+        Ok(Variables { variable_ids: ids, values: Some(field.encode_all(&coeffs)), info: None })
+    }
+
+    fn lookup(&self, name: &str) -> Result<u64> {
+        self.ids.get(name).cloned().ok_or_else(|| Error::Format(format!("Unknown variable {}", name)))
+    }
+
+    fn finish(self) -> Result<Messages> {
+        let field = self.field.ok_or_else(|| Error::Format("No field declared".into()))?;
+
+        let mut instance_variables = Variables {
+            variable_ids: self.instance.iter().map(|(id, _)| *id).collect(),
+            values: None,
+            info: None,
+        };
+        let instance_values: Vec<FieldElement> = self.instance.iter().filter_map(|(_, v)| v.clone()).collect();
+        if !instance_values.is_empty() {
+            if instance_values.len() != self.instance.len() {
+                return Err(Error::Format("Either all instance variables or none must have a value".into()));
+            }
+            instance_variables.values = Some(field.encode_all(&instance_values));
+        }
+        for (id, name) in &self.instance_names {
+            instance_variables.set_name(*id, name);
+        }
+
+        let header = CircuitHeader {
+            instance_variables,
+            free_variable_id: self.next_id,
+            field_maximum: Some(field.encoded_maximum()),
+            configuration: if self.configuration.is_empty() { None } else { Some(self.configuration) },
+        };
+
+        let mut witnesses = vec![];
+        if !self.witness.variable_ids.is_empty() {
+            let mut assigned_variables = self.witness;
+            assigned_variables.values = Some(field.encode_all(&self.witness_values));
+            witnesses.push(Witness { assigned_variables });
+        }
+
+        Ok(Messages {
+            circuit_headers: vec![header],
+            constraint_systems: vec![ConstraintSystem { constraints: self.constraints }],
+            witnesses,
+        })
+    }
+}
+
+fn expect(tokens: &mut &[Token], punct: char) -> Result<()> {
+    match tokens.first() {
+        Some(Token::Punct(c)) if *c == punct => {
+            *tokens = &tokens[1..];
+            Ok(())
+        }
+        Some(token) => Err(Error::Format(format!("Expected '{}', found {:?}", punct, token))),
+        None => Err(Error::Format(format!("Expected '{}' at the end of the line", punct))),
+    }
+}
+
+fn parse_number(digits: &str) -> Result<BigUint> {
+    BigUint::parse_bytes(digits.as_bytes(), 10)
+        .ok_or_else(|| Error::Format(format!("Invalid number {}", digits)))
+}
+
+/// Quote a text with the escapes that `tokenize` decodes. Other characters are written as they are.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Remove a comment, but not a # within a quoted text.
+fn strip_comment(line: &str) -> &str {
+    let mut in_text = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_text => escaped = true,
+            '"' => in_text = !in_text,
+            '#' if !in_text => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = vec![];
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_ascii_digit() || c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') { break; }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &line[start..end];
+            tokens.push(if c.is_ascii_digit() { Token::Number(word) } else { Token::Ident(word) });
+        } else if c == '"' {
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 'r')) => text.push('\r'),
+                        Some((_, 't')) => text.push('\t'),
+                        Some((_, c)) => text.push(c),
+                        None => return Err(Error::Format("Unterminated text".into())),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(Error::Format("Unterminated text".into())),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else if "()*+-=[],".contains(c) {
+            tokens.push(Token::Punct(c));
+        } else {
+            return Err(Error::Format(format!("Unexpected character '{}'", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+
+#[test]
+fn test_text() -> Result<()> {
+    use crate::producers::examples::*;
+
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+
+    let mut text = vec![];
+    write_text(&messages, &mut text)?;
+    let text = String::from_utf8(text)?;
+    assert_eq!(text, "\
+field 101
+config Name = \"example\"
+instance var_1 = 3
+instance var_2 = 4
+instance var_3 = 25
+witness var_4 = 9
+witness var_5 = 16
+(var_1) * (var_1) = (var_4)
+(var_2) * (var_2) = (var_5)
+(1) * (var_4 + var_5) = (var_3)
+");
+
+    // Parse it back into the same statement, up to the encoding of values.
+    let parsed = read_text(&text)?;
+    let field = Field::from_header(&messages.circuit_headers[0])?;
+    assert_eq!(parsed.circuit_headers[0].free_variable_id, 6);
+    assert_eq!(parsed.circuit_headers[0].configuration, messages.circuit_headers[0].configuration);
+    assert_eq!(parsed.circuit_headers[0].instance_variables.normalize(&field),
+               messages.circuit_headers[0].instance_variables.normalize(&field));
+    assert_eq!(parsed.constraint_systems[0].normalize(&field), messages.constraint_systems[0].normalize(&field));
+    assert_eq!(parsed.witnesses[0].assigned_variables.normalize(&field),
+               messages.witnesses[0].assigned_variables.normalize(&field));
+
+    // Names, negative terms, and errors.
+    let parsed = read_text("field 101\nwitness x = 2 # comment\nwitness y\n(x - 3*y + 4) * (1) = (0)\n")?;
+    let mut text = vec![];
+    write_text(&parsed, &mut text)?;
+    assert_eq!(String::from_utf8(text)?, "field 101\nwitness x = 2\nwitness y\n(x - 3*y + 4) * (1) = (0)\n");
+
+    // Negative values are reduced modulo the field.
+    let parsed = read_text("field 101\ninstance x = -1\nwitness y = -3\n(x) * (y) = (3)\n")?;
+    assert_eq!(parsed.circuit_headers[0].instance_variables.values, Some(vec![100]));
+    assert_eq!(parsed.witnesses[0].assigned_variables.values, Some(vec![98]));
+
+    // Texts with quotes, control and non-ASCII characters are kept through a round-trip.
+    let mut header = example_circuit_header();
+    header.configuration = Some(vec![KeyValue::from(("Name", "l\u{e9}a \"#1\"\r\n\t\\\0 \u{1F600}".to_string()))]);
+    let messages = Messages { circuit_headers: vec![header], constraint_systems: vec![], witnesses: vec![] };
+    let mut text = vec![];
+    write_text(&messages, &mut text)?;
+    let parsed = read_text(&String::from_utf8(text)?)?;
+    assert_eq!(parsed.circuit_headers[0].configuration, messages.circuit_headers[0].configuration);
+
+    assert_eq!(read_text("field 101\n(x) * (x) = (x)").unwrap_err().to_string(), "Line 2: Unknown variable x");
+    assert!(read_text("witness x").is_err());
+
+    Ok(())
+}
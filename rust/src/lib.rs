@@ -34,6 +34,7 @@
 //!         instance_variables: zkinterface::Variables {
 //!            variable_ids: vec![1, 2, 3],  // x, y, zz
 //!            values: Some(serialize_small(&[x, y, zz])),
+//!            info: None,
 //!        },
 //!        free_variable_id: 6,
 //!        field_maximum: Some(serialize_small(&[NEG_ONE])),
@@ -58,6 +59,7 @@
 //!                 x * x, // var_4 = xx = x^2
 //!                 y * y, // var_5 = yy = y^2
 //!             ])),
+//!             info: None,
 //!        }
 //!    };
 //! ```
@@ -146,25 +148,55 @@
 //! - `zkif to-json`     Convert to JSON on a single line.
 //! - `zkif to-yaml`     Convert to YAML.
 //! - `zkif explain`     Print the content in a human-readable form.
+//! - `zkif from-json`   Convert from JSON.
+//! - `zkif from-yaml`   Convert from YAML.
+//! - `zkif to-text`     Print in a human-readable text format.
+//! - `zkif from-text`   Convert from the text format.
+//! - `zkif call-gadget` Call a gadget program over its stdin and stdout.
+//! - `zkif split`       Split constraints and witnesses into messages and files of limited size.
+//! - `zkif link`        Link several statements into one, with fresh variable IDs and connected instance variables.
 //! - `zkif validate`    Validate the format and semantics of a statement, as seen by a verifier.
 //! - `zkif simulate`    Simulate a proving system as prover by verifying that the statement is true.
 //! - `zkif stats`       Calculate statistics about the circuit.
+//! - `zkif lint`        Report suspicious constraints and variables.
+//! - `zkif check-determinism` Search for alternative witnesses to find under-constrained private variables.
+//! - `zkif solve`       Complete a partial witness using the constraints.
+//! - `zkif optimize`    Remove linear constraints by substitution.
+//! - `zkif normalize`   Write constraints in canonical form.
+//! - `zkif diff`        Compare two statements.
+//! - `zkif hash`        Print a stable fingerprint of the constraints.
+//! - `zkif from-r1cs`   Convert from the circom formats .r1cs and .wtns.
+//! - `zkif to-r1cs`     Convert to the circom formats .r1cs and .wtns.
+//! - `zkif prove`       Prove the statement with Groth16 over BLS12-381 (requires the feature `groth16`).
+//! - `zkif verify`      Verify a Groth16 proof of the statement (requires the feature `groth16`).
 //! - `zkif clean`       Clean workspace by deleting all *.zkif files in it.
 
 #[allow(unused_imports)]
 /// All CLI related logic.
 pub mod cli;
 
-/// Various zkInterface consumers including: validator, simulator, stats, reader and a workspace
+/// Various zkInterface consumers including: validator, simulator, stats, lint, determinism, reader and a workspace
 pub mod consumers;
 
-/// Various zkInterface producers including: examples, builder, gadget_caller and workspace
+/// Various zkInterface producers including: examples, builder, gadget_caller, gadget_process, gadget_server and workspace
 pub mod producers;
 
 /// Fully-owned version of each data structure
 /// These structures may be easier to work with than the no-copy versions found in zkinterface_generated and Reader
 pub mod structs;
 
+/// Arithmetic in the finite field of a statement
+pub mod field;
+
+/// The error type of the crate
+pub mod error;
+
+/// Transformations of statements: optimizer and linker
+pub mod transformations;
+
+/// Conversion from and to other formats: iden3 .r1cs and .wtns, and a human-readable text format
+pub mod formats;
+
 /// Automatically generated by the FlatBuffers compiler
 #[allow(unused_imports)]
 pub mod zkinterface_generated;
@@ -175,11 +207,14 @@ pub extern crate serde;
 pub use consumers::{
     reader::Reader,
     workspace::Workspace,
+    mapped_workspace::MappedWorkspace,
 };
 pub use producers::{
     builder::{Sink, StatementBuilder},
     workspace::{WorkspaceSink, clean_workspace},
 };
+pub use field::{Field, FieldElement};
+pub use error::Error;
 pub use structs::{
     header::CircuitHeader,
     command::Command,
@@ -191,8 +226,5 @@ pub use structs::{
     witness::Witness,
 };
 
-// Common definitions.
-use std::error::Error;
-
-/// A result type with a predefined error type
-pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
\ No newline at end of file
+/// A result type with the error type of the crate
+pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
@@ -38,6 +38,7 @@ impl<S: Sink> StatementBuilder<S> {
                 instance_variables: Variables {
                     variable_ids: vec![],
                     values: Some(vec![]),
+                    info: None,
                 },
                 free_variable_id: 1,
                 ..CircuitHeader::default()
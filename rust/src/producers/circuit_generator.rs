@@ -1,8 +1,8 @@
 use rand;
-use num_bigint::{BigUint, RandBigInt};
-use num_traits::{One, Num};
+use num_bigint::BigUint;
+use num_traits::Num;
 
-use crate::{Result, ConstraintSystem, Variables, Witness, StatementBuilder, Sink, WorkspaceSink};
+use crate::{Result, ConstraintSystem, Variables, Witness, StatementBuilder, Sink, WorkspaceSink, Field, FieldElement};
 use std::path::Path;
 use rand::Rng;
 
@@ -58,10 +58,6 @@ impl BenchmarkParameter {
     }
 }
 
-macro_rules! bits_to_bytes {
-    ($val:expr) => (($val + 7) / 8);
-}
-
 /// This function will generate R1CS constraints systems for all parameters
 /// set here.
 ///
@@ -140,11 +136,11 @@ pub fn generate_metrics_data(sink: impl Sink, hexaprime: &str, wit_nbr: u64, ins
     let mut rng = rand::thread_rng();
 
     let bp = BenchmarkParameter::new(ins_nbr, wit_nbr, &hexaprime)?;
-    let size_in_bytes = bits_to_bytes!(&bp.modulus.bits()) as usize;
-    let witnesses: Vec<BigUint> = (0..wit_nbr).map(|_| rng.gen_biguint_below(&bp.modulus)).collect();
+    let field = Field::from_modulus(&bp.modulus)?;
+    let witnesses: Vec<FieldElement> = (0..wit_nbr).map(|_| field.random(&mut rng)).collect();
     let mut builder = StatementBuilder::new(sink);
 
-    builder.header.field_maximum = Some(serialize_biguint(&bp.modulus - BigUint::one(), size_in_bytes));
+    builder.header.field_maximum = Some(field.encoded_maximum());
     let wit_idx = ((ins_nbr+1)..(ins_nbr + wit_nbr + 1)).collect::<Vec<u64>>();
 
     let constraints_start: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
@@ -157,9 +153,10 @@ pub fn generate_metrics_data(sink: impl Sink, hexaprime: &str, wit_nbr: u64, ins
         let b1: Vec<u8> = (0..wit_nbr).map(|_| rng.gen_range(0, 2)).collect();
         let b2: Vec<u8> = (0..wit_nbr).map(|_| rng.gen_range(0, 2)).collect();
 
-        let mut result_of_equation = compute_equation(&witnesses, &b1) * compute_equation(&witnesses, &b2);
-        result_of_equation %= &bp.modulus;
-        let buf = serialize_biguint(result_of_equation, size_in_bytes);
+        let result_of_equation = field.mul(
+            &compute_equation(&field, &witnesses, &b1),
+            &compute_equation(&field, &witnesses, &b2));
+        let buf = field.encode(&result_of_equation);
 
         let instance_id = builder.allocate_instance_var(&buf);
 
@@ -170,12 +167,13 @@ pub fn generate_metrics_data(sink: impl Sink, hexaprime: &str, wit_nbr: u64, ins
 
     }
 
-    let witness_buffer = serialize_biguints(witnesses, size_in_bytes);
+    let witness_buffer = field.encode_all(&witnesses);
 
     builder.push_witness(Witness {
         assigned_variables: Variables {
             variable_ids: wit_idx, // xx, yy
             values: Some(witness_buffer),
+            info: None,
         }
     })?;
     builder.header.free_variable_id += wit_nbr;
@@ -183,33 +181,13 @@ pub fn generate_metrics_data(sink: impl Sink, hexaprime: &str, wit_nbr: u64, ins
     Ok(())
 }
 
-/// serialize `BigUint` as a vector of u8 (in Little-endian), of a given size, padded with 0's if required.
-fn serialize_biguint(biguint: BigUint, byte_len: usize) -> Vec<u8> {
-    let mut ret: Vec<u8> = Vec::new();
-    let mut binary = biguint.to_bytes_le();
-    let len = binary.len();
-    ret.append(&mut binary);
-    ret.append(&mut vec![0; byte_len - len]);
-
-    ret
-}
-
-/// serialize a `Vec<BigUint>` as a vector of u8, each `BigUint` exported a fixed-size vector of u8
-/// padded with 0's if required.
-fn serialize_biguints(biguints: Vec<BigUint>, byte_len: usize) -> Vec<u8> {
-    let mut ret: Vec<u8> = Vec::new();
-
-    for biguint in biguints.iter() {
-        ret.append(&mut serialize_biguint(biguint.clone(), byte_len));
-    }
-    ret
-}
-
-/// Compute the BigUint value resulting from the inner product of a vector of BigUint, and a vector
-/// of bit values.
-/// It simply computes SUM_i(big_i * bit_i)
-fn compute_equation(witnesses: &Vec<BigUint>, bit_vector: &[u8]) -> BigUint {
-    witnesses.iter().zip(bit_vector.iter()).map(|(buint, bit)| buint * bit ).sum::<BigUint>()
+/// Compute the field element resulting from the inner product of a vector of field elements, and
+/// a vector of bit values.
+/// It simply computes SUM_i(w_i * bit_i)
+fn compute_equation(field: &Field, witnesses: &[FieldElement], bit_vector: &[u8]) -> FieldElement {
+    witnesses.iter().zip(bit_vector.iter())
+        .filter(|(_, bit)| **bit != 0)
+        .fold(field.zero(), |sum, (w, _)| field.add(&sum, w))
 }
 
 
@@ -17,6 +17,7 @@ pub fn example_circuit_header_in_field(field_max: Vec<u8>) -> CircuitHeader {
             variable_ids: vec![1, 2, 3],
             // Values of                   x, y, zz.
             values: Some(serialize_small(&[3, 4, 25])),
+            info: None,
         },
         free_variable_id: 6,
         field_maximum: Some(field_max),
@@ -49,6 +50,7 @@ pub fn example_witness_inputs(x: u32, y: u32) -> Witness {
                 x * x, // var_4 = xx = x^2
                 y * y, // var_5 = yy = y^2
             ])),
+            info: None,
         }
     }
 }
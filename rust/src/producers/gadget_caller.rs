@@ -1,7 +1,9 @@
-use crate::{Result, CircuitHeader};
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, Witness, Messages};
 use super::builder::{StatementBuilder, Sink};
 use super::workspace::WorkspaceSink;
-use std::io::Write;
+use crate::consumers::workspace::gadget_responses_dir;
+use std::convert::TryFrom;
+use std::fs::{File, create_dir_all};
 
 
 /// Structures that implement GadgetCallbacks can be used to receive the raw outputs
@@ -24,7 +26,7 @@ impl<S: Sink + GadgetCallbacks> GadgetCallbacks for StatementBuilder<S> {
 
     fn receive_gadget_response(&mut self, request: &CircuitHeader, response: &CircuitHeader) -> Result<()> {
         if self.header.free_variable_id > response.free_variable_id {
-            return Err("free_variable_id returned from the gadget must be higher than the current one.".into());
+            return Err(Error::GadgetCall("free_variable_id returned from the gadget must be higher than the current one.".into()));
         }
         self.header.free_variable_id = response.free_variable_id;
 
@@ -32,21 +34,76 @@ impl<S: Sink + GadgetCallbacks> GadgetCallbacks for StatementBuilder<S> {
     }
 }
 
+/// Collect the outputs of gadgets in memory. Response headers are not collected.
+impl GadgetCallbacks for Messages {
+    fn receive_constraints(&mut self, msg: &[u8]) -> Result<()> {
+        self.push_constraints(ConstraintSystem::try_from(msg)?)
+    }
+
+    fn receive_witness(&mut self, msg: &[u8]) -> Result<()> {
+        self.push_witness(Witness::try_from(msg)?)
+    }
+}
+
+/// Write the outputs of gadgets into numbered constraints files and the witness file,
+/// and the response headers into numbered response files in the `responses` subdirectory.
 impl GadgetCallbacks for WorkspaceSink {
-    fn receive_constraints(&mut self, _msg: &[u8]) -> Result<()> {
-        unimplemented!();
-        /*
-        if let Some(ref mut file) = self.constraints_file {
-            file.write_all(msg)?;
-        }
-        Ok(())
-        */
+    fn receive_constraints(&mut self, msg: &[u8]) -> Result<()> {
+        self.start_constraints();
+        self.write_constraints_message(msg)
     }
 
     fn receive_witness(&mut self, msg: &[u8]) -> Result<()> {
-        if let Some(ref mut file) = self.witness_file {
-            file.write_all(msg)?;
-        }
+        self.write_witness_message(msg)
+    }
+
+    fn receive_gadget_response(&mut self, _request: &CircuitHeader, response: &CircuitHeader) -> Result<()> {
+        let dir = gadget_responses_dir(&self.workspace);
+        create_dir_all(&dir)?;
+        let path = dir.join(format!("response_{}.zkif", self.gadget_responses.len()));
+        response.write_into(&mut File::create(path)?)?;
+        self.gadget_responses.push(response.clone());
         Ok(())
     }
 }
+
+
+#[test]
+fn test_workspace_callbacks() -> Result<()> {
+    use std::fs::remove_dir_all;
+    use std::path::PathBuf;
+    use crate::{Workspace, Sink};
+    use crate::consumers::workspace::read_gadget_responses;
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_workspace_callbacks");
+    let _ = remove_dir_all(&dir);
+    let mut sink = WorkspaceSink::new(&dir)?;
+    sink.push_header(example_circuit_header())?;
+
+    // Two gadget calls, with raw messages.
+    let request = CircuitHeader::simple_inputs(3);
+    for _ in 0..2 {
+        let (mut cs_msg, mut witness_msg) = (vec![], vec![]);
+        example_constraints().write_into(&mut cs_msg)?;
+        example_witness().write_into(&mut witness_msg)?;
+        sink.receive_constraints(&cs_msg)?;
+        sink.receive_witness(&witness_msg)?;
+        sink.receive_gadget_response(&request, &CircuitHeader::simple_outputs(3, 1, 2))?;
+    }
+    assert_eq!(sink.gadget_responses, vec![CircuitHeader::simple_outputs(3, 1, 2); 2]);
+    assert_eq!(read_gadget_responses(&dir)?, vec![CircuitHeader::simple_outputs(3, 1, 2); 2]);
+
+    let messages = Messages::from(&Workspace::from_dir(&dir)?);
+    assert_eq!(messages.circuit_headers, vec![example_circuit_header()]);
+    assert_eq!(messages.constraint_systems, vec![example_constraints(); 2]);
+    assert_eq!(messages.witnesses, vec![example_witness(); 2]);
+
+    // A statement file may be named like a response file.
+    example_constraints().write_into(&mut File::create(dir.join("response_0.zkif"))?)?;
+    let messages = Messages::from(&Workspace::from_dir(&dir)?);
+    assert_eq!(messages.constraint_systems, vec![example_constraints(); 3]);
+    assert_eq!(read_gadget_responses(&dir)?.len(), 2);
+
+    Ok(())
+}
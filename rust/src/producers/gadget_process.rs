@@ -0,0 +1,158 @@
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command as Process, Stdio};
+use std::thread;
+
+use crate::{Result, Error, CircuitHeader, Command};
+use crate::consumers::reader::read_buffer;
+use crate::consumers::verifier::verify_message;
+use crate::zkinterface_generated::zkinterface as fb;
+use super::gadget_caller::GadgetCallbacks;
+
+/// GadgetProcess calls a gadget implemented by an external program, over its stdin and stdout.
+///
+/// The request is written to the stdin of the program: the `Command`, then the `CircuitHeader` whose
+/// instance variables are the inputs of the gadget. The program writes back `ConstraintSystem` and `Witness`
+/// messages, and a response `CircuitHeader` whose instance variables are the outputs of the gadget.
+/// Messages are passed to the callbacks as they arrive, and the response at the end.
+///
+/// # Example
+/// ```no_run
+/// use zkinterface::producers::gadget_process::GadgetProcess;
+/// use zkinterface::{Command, CircuitHeader, Messages};
+///
+/// let gadget = GadgetProcess::new("./my_gadget").arg("--sha256");
+/// let command = Command { constraints_generation: true, witness_generation: false };
+/// let mut outputs = Messages::default();
+/// let response = gadget.call(&command, &CircuitHeader::simple_inputs(3), &mut outputs).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct GadgetProcess {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+}
+
+impl GadgetProcess {
+    pub fn new(program: impl Into<PathBuf>) -> GadgetProcess {
+        GadgetProcess { program: program.into(), args: vec![] }
+    }
+
+    pub fn arg(mut self, arg: impl Into<OsString>) -> GadgetProcess {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Run the gadget on a request, and return its response header.
+    pub fn call(&self, command: &Command, request: &CircuitHeader, callbacks: &mut impl GadgetCallbacks) -> Result<CircuitHeader> {
+        let mut child = Process::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| Error::GadgetCall(format!("cannot run {}: {}", self.program.display(), err)))?;
+
+        let mut input = vec![];
+        command.write_into(&mut input)?;
+        request.write_into(&mut input)?;
+
+        // Write from another thread, so that a gadget may answer before reading all of its input.
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&input));
+
+        let mut stdout = child.stdout.take().unwrap();
+        let response = match read_outputs(&mut stdout, callbacks) {
+            Ok(response) => response,
+            Err(err) => {
+                // Stop a misbehaving gadget, and reap it.
+                let _ = child.kill();
+                let _ = child.wait();
+                let _ = writer.join();
+                return Err(err);
+            }
+        };
+
+        let status = child.wait()?;
+        // A gadget may exit without reading its input, which is not an error by itself.
+        let _ = writer.join();
+        if !status.success() {
+            return Err(Error::GadgetCall(format!("{} exited with {}", self.program.display(), status)));
+        }
+
+        let response = response.ok_or_else(|| Error::GadgetCall("the gadget returned no response header".into()))?;
+        callbacks.receive_gadget_response(request, &response)?;
+        Ok(response)
+    }
+}
+
+/// Pass the messages of the gadget to the callbacks until the end of its output, and return the response header.
+fn read_outputs(stdout: &mut impl Read, callbacks: &mut impl GadgetCallbacks) -> Result<Option<CircuitHeader>> {
+    let mut response = None;
+    loop {
+        let buffer = read_buffer(&mut *stdout)?;
+        if buffer.is_empty() { break; }
+        verify_message(&buffer)?;
+
+        match fb::get_size_prefixed_root_as_root(&buffer).message_type() {
+            fb::Message::ConstraintSystem => callbacks.receive_constraints(&buffer)?,
+            fb::Message::Witness => callbacks.receive_witness(&buffer)?,
+            fb::Message::CircuitHeader => {
+                if response.is_some() {
+                    return Err(Error::GadgetCall("the gadget returned multiple headers".into()));
+                }
+                response = Some(CircuitHeader::try_from(&buffer[..])?);
+            }
+            other => return Err(Error::GadgetCall(format!("unexpected {:?} message from the gadget", other))),
+        }
+    }
+    Ok(response)
+}
+
+
+#[cfg(unix)]
+#[test]
+fn test_gadget_process() -> Result<()> {
+    use std::fs::{create_dir_all, File};
+    use crate::{Messages, StatementBuilder};
+    use crate::producers::examples::*;
+
+    let dir = PathBuf::from("local/test_gadget_process");
+    create_dir_all(&dir)?;
+
+    // A fake gadget that consumes its input, and answers with prepared messages.
+    let output_path = dir.join("output.zkif");
+    let mut output = File::create(&output_path)?;
+    example_constraints().write_into(&mut output)?;
+    example_witness().write_into(&mut output)?;
+    CircuitHeader { free_variable_id: 10, ..CircuitHeader::simple_inputs(3) }.write_into(&mut output)?;
+    let gadget = GadgetProcess::new("sh").arg("-c").arg(format!("cat > /dev/null; cat {}", output_path.display()));
+
+    let mut builder = StatementBuilder::new(Messages::default());
+    let request = CircuitHeader::simple_inputs(3);
+    let command = Command { constraints_generation: true, witness_generation: true };
+    let response = gadget.call(&command, &request, &mut builder)?;
+
+    assert_eq!(response.free_variable_id, 10);
+    assert_eq!(builder.header.free_variable_id, 10);
+    assert_eq!(builder.sink.constraint_systems, vec![example_constraints()]);
+    assert_eq!(builder.sink.witnesses, vec![example_witness()]);
+
+    // A gadget that fails.
+    let failing = GadgetProcess::new("sh").arg("-c").arg("exit 3");
+    match failing.call(&command, &request, &mut Messages::default()) {
+        Err(Error::GadgetCall(_)) => {}
+        other => panic!("Expected a gadget call error, got {:?}", other),
+    }
+
+    // A gadget that misbehaves and then hangs is stopped.
+    let hanging = GadgetProcess::new("sh").arg("-c").arg(format!("cat {} {}; sleep 60", output_path.display(), output_path.display()));
+    let start = std::time::Instant::now();
+    match hanging.call(&command, &request, &mut Messages::default()) {
+        Err(Error::GadgetCall(_)) => {}
+        other => panic!("Expected a gadget call error, got {:?}", other),
+    }
+    assert!(start.elapsed().as_secs() < 30);
+
+    Ok(())
+}
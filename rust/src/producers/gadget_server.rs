@@ -0,0 +1,322 @@
+use std::collections::HashSet;
+use std::io::{stdin, stdout, Read, Write};
+
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, Witness, Variables, Command, Message, Messages};
+use crate::consumers::reader::{read_buffer, Reader};
+use super::builder::{StatementBuilder, Sink};
+
+/// GadgetSink writes the messages of a gadget to its caller, following the flags of the `Command`:
+/// constraints are only written if `constraints_generation` is set, and witnesses only if `witness_generation` is set.
+pub struct GadgetSink<'w> {
+    pub command: Command,
+    writer: &'w mut dyn Write,
+}
+
+impl<'w> GadgetSink<'w> {
+    pub fn new(command: Command, writer: &'w mut dyn Write) -> GadgetSink<'w> {
+        GadgetSink { command, writer }
+    }
+}
+
+impl<'w> Sink for GadgetSink<'w> {
+    fn push_header(&mut self, _header: CircuitHeader) -> Result<()> {
+        Err(Error::GadgetCall("A gadget must not push headers, it returns its outputs instead.".into()))
+    }
+
+    fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
+        if self.command.constraints_generation {
+            cs.write_into(&mut self.writer)?;
+        }
+        Ok(())
+    }
+
+    fn push_witness(&mut self, witness: Witness) -> Result<()> {
+        if self.command.witness_generation {
+            witness.write_into(&mut self.writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// The builder given to a gadget. It allocates variables from the `free_variable_id` of the request.
+pub type GadgetBuilder<'w> = StatementBuilder<GadgetSink<'w>>;
+
+/// Run a gadget on a request, write its messages and its response header, and return the response.
+///
+/// The gadget receives the command, the request header whose instance variables are its inputs,
+/// and a builder to allocate variables and push constraints and witnesses.
+/// It returns its output variables, with their values if `witness_generation` is set.
+pub fn run_gadget<F>(gadget: F, command: &Command, request: &CircuitHeader, writer: &mut impl Write) -> Result<CircuitHeader>
+    where F: Fn(&Command, &CircuitHeader, &mut GadgetBuilder) -> Result<Variables>
+{
+    let mut builder = StatementBuilder::new(GadgetSink::new(command.clone(), writer));
+    builder.header = CircuitHeader {
+        instance_variables: Variables::default(),
+        free_variable_id: request.free_variable_id,
+        field_maximum: request.field_maximum.clone(),
+        configuration: None,
+    };
+
+    let mut outputs = gadget(command, request, &mut builder)?;
+    if !command.witness_generation {
+        outputs.values = None;
+    }
+
+    let response = CircuitHeader {
+        instance_variables: outputs,
+        ..builder.header
+    };
+    response.write_into(&mut builder.sink.writer)?;
+    Ok(response)
+}
+
+/// Serve a gadget as an executable: read a `Command` and a request `CircuitHeader` from stdin,
+/// run the gadget, and write its messages and its response header to stdout.
+/// This is the counterpart of `GadgetProcess`.
+///
+/// # Example
+/// ```no_run
+/// use zkinterface::producers::gadget_server::serve_gadget;
+///
+/// fn main() -> zkinterface::Result<()> {
+///     // A gadget that returns its inputs as outputs, without constraints.
+///     serve_gadget(|_command, request, _builder| Ok(request.instance_variables.clone()))
+/// }
+/// ```
+pub fn serve_gadget<F>(gadget: F) -> Result<()>
+    where F: Fn(&Command, &CircuitHeader, &mut GadgetBuilder) -> Result<Variables>
+{
+    serve_gadget_from(gadget, stdin().lock(), &mut stdout().lock())
+}
+
+/// Like `serve_gadget`, with any input and output streams.
+pub fn serve_gadget_from<F>(gadget: F, mut input: impl Read, output: &mut impl Write) -> Result<()>
+    where F: Fn(&Command, &CircuitHeader, &mut GadgetBuilder) -> Result<Variables>
+{
+    let (command, request) = read_request(&mut input)?;
+    run_gadget(gadget, &command, &request, output)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Read a request, until both a command and a header are received.
+/// The input is not read further, so the caller does not need to close it.
+fn read_request(mut input: impl Read) -> Result<(Command, CircuitHeader)> {
+    let mut command = None;
+    let mut request = None;
+
+    while command.is_none() || request.is_none() {
+        let buffer = read_buffer(&mut input)?;
+        if buffer.is_empty() {
+            return Err(Error::GadgetCall("The request must contain a Command and a CircuitHeader.".into()));
+        }
+        match Message::from(&buffer[..]) {
+            Message::Command(c) => command = Some(c),
+            Message::Header(h) => request = Some(h),
+            Message::Err(err) => return Err(err),
+            _ => return Err(Error::GadgetCall("Unexpected message in a gadget request.".into())),
+        }
+    }
+    Ok((command.unwrap(), request.unwrap()))
+}
+
+
+/// Run a gadget in memory, and check its response against the rules of gadget calls.
+/// Return the list of violations, empty if the gadget behaved correctly.
+pub fn check_gadget<F>(gadget: F, command: &Command, request: &CircuitHeader) -> Result<Vec<String>>
+    where F: Fn(&Command, &CircuitHeader, &mut GadgetBuilder) -> Result<Variables>
+{
+    let mut output = vec![];
+    run_gadget(gadget, command, request, &mut output)?;
+
+    let mut reader = Reader::new();
+    reader.read_from(&output[..])?;
+    let mut messages = Messages::from(&reader);
+    let response = messages.circuit_headers.pop()
+        .ok_or_else(|| Error::GadgetCall("The gadget returned no response header.".into()))?;
+    if !messages.circuit_headers.is_empty() {
+        return Ok(vec!["The gadget returned multiple headers.".into()]);
+    }
+    Ok(check_gadget_response(command, request, &messages, &response))
+}
+
+/// Check the messages and the response of a gadget against the rules of gadget calls:
+/// - The response allocates variables from the `free_variable_id` of the request, so its `free_variable_id` is greater.
+/// - The outputs are new variables, with values if and only if `witness_generation` is set.
+/// - Constraints are only returned if `constraints_generation` is set, witnesses only if `witness_generation` is set.
+/// - Constraints only use the constant one, the inputs, and the new variables.
+/// - The witness assigns all new variables other than the outputs, and nothing else.
+///
+/// This also applies to external gadgets, called with `GadgetProcess` into `Messages`.
+pub fn check_gadget_response(command: &Command, request: &CircuitHeader, outputs: &Messages, response: &CircuitHeader) -> Vec<String> {
+    let mut violations = vec![];
+    let first_id = request.free_variable_id;
+    let free_id = response.free_variable_id;
+    let is_new = |id: u64| first_id <= id && id < free_id;
+
+    if free_id <= first_id {
+        violations.push(format!("The response free_variable_id ({}) must be greater than the request free_variable_id ({}).", free_id, first_id));
+    }
+    if response.field_maximum.is_some() && response.field_maximum != request.field_maximum {
+        violations.push("The response field_maximum differs from the request.".into());
+    }
+
+    // Outputs.
+    let output_ids = &response.instance_variables.variable_ids;
+    let mut seen = HashSet::new();
+    for &id in output_ids {
+        if !is_new(id) {
+            violations.push(format!("Output variable_{} was not allocated by the gadget.", id));
+        }
+        if !seen.insert(id) {
+            violations.push(format!("Output variable_{} is returned multiple times.", id));
+        }
+    }
+    let values_len = response.instance_variables.values.as_ref().map_or(0, Vec::len);
+    let has_values = values_len > 0;
+    // Output values have the size of the input values, or any common size if there are no inputs.
+    let value_size = match request.instance_variables.value_size() {
+        0 => response.instance_variables.value_size().max(1),
+        size => size,
+    };
+    if command.witness_generation {
+        if !output_ids.is_empty() && !has_values {
+            violations.push("The outputs have no values, but witness_generation was requested.".into());
+        } else if has_values && values_len != output_ids.len() * value_size {
+            violations.push("The output values do not match the number of outputs.".into());
+        }
+    } else if has_values {
+        violations.push("The outputs have values, but witness_generation was not requested.".into());
+    }
+
+    // Constraints.
+    if !command.constraints_generation && !outputs.constraint_systems.is_empty() {
+        violations.push("The gadget returned constraints, but constraints_generation was not requested.".into());
+    }
+    let input_ids: HashSet<u64> = request.instance_variables.variable_ids.iter().cloned().collect();
+    let mut reported = HashSet::new();
+    for cs in &outputs.constraint_systems {
+        for constraint in &cs.constraints {
+            for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+                for &id in &lc.variable_ids {
+                    if id != 0 && !input_ids.contains(&id) && !is_new(id) && reported.insert(id) {
+                        violations.push(format!("Constraints use variable_{}, which is neither an input nor allocated by the gadget.", id));
+                    }
+                }
+            }
+        }
+    }
+
+    // Witness.
+    if !command.witness_generation && !outputs.witnesses.is_empty() {
+        violations.push("The gadget returned a witness, but witness_generation was not requested.".into());
+    }
+    let mut assigned = HashSet::new();
+    for witness in &outputs.witnesses {
+        for &id in &witness.assigned_variables.variable_ids {
+            if !is_new(id) || seen.contains(&id) {
+                violations.push(format!("The witness assigns variable_{}, which is not a local variable of the gadget.", id));
+            }
+            if !assigned.insert(id) {
+                violations.push(format!("The witness assigns variable_{} multiple times.", id));
+            }
+        }
+    }
+    if command.witness_generation {
+        for id in first_id..free_id {
+            if !seen.contains(&id) && !assigned.contains(&id) {
+                violations.push(format!("The witness does not assign the local variable_{}.", id));
+            }
+        }
+    }
+
+    violations
+}
+
+
+#[test]
+fn test_gadget_server() -> Result<()> {
+    use crate::BilinearConstraint;
+    use crate::producers::examples::serialize_small;
+
+    // A gadget that computes xy = x * y, and out = xy + y.
+    let gadget = |command: &Command, request: &CircuitHeader, builder: &mut GadgetBuilder| -> Result<Variables> {
+        let (x, y) = (request.instance_variables.variable_ids[0], request.instance_variables.variable_ids[1]);
+        let xy = builder.allocate_var();
+        let out = builder.allocate_var();
+
+        builder.push_constraints(ConstraintSystem {
+            constraints: vec![
+                BilinearConstraint {
+                    linear_combination_a: Variables { variable_ids: vec![x], values: Some(vec![1]), info: None },
+                    linear_combination_b: Variables { variable_ids: vec![y], values: Some(vec![1]), info: None },
+                    linear_combination_c: Variables { variable_ids: vec![xy], values: Some(vec![1]), info: None },
+                },
+                BilinearConstraint {
+                    linear_combination_a: Variables { variable_ids: vec![0], values: Some(vec![1]), info: None },
+                    linear_combination_b: Variables { variable_ids: vec![xy, y], values: Some(vec![1, 1]), info: None },
+                    linear_combination_c: Variables { variable_ids: vec![out], values: Some(vec![1]), info: None },
+                },
+            ],
+        })?;
+
+        let mut values = None;
+        if command.witness_generation {
+            let inputs = request.instance_variables.values.as_ref().unwrap();
+            let (x_val, y_val) = (inputs[0] as u32, inputs[4] as u32);
+            builder.push_witness(Witness {
+                assigned_variables: Variables { variable_ids: vec![xy], values: Some(serialize_small(&[x_val * y_val])), info: None },
+            })?;
+            values = Some(serialize_small(&[x_val * y_val + y_val]));
+        }
+        Ok(Variables { variable_ids: vec![out], values, info: None })
+    };
+
+    let request = CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![1, 2], values: Some(serialize_small(&[3, 4])), info: None },
+        ..CircuitHeader::simple_inputs(2)
+    };
+    let prover = Command { constraints_generation: true, witness_generation: true };
+    let verifier = Command { constraints_generation: true, witness_generation: false };
+    assert_eq!(check_gadget(gadget, &prover, &request)?, Vec::<String>::new());
+    assert_eq!(check_gadget(gadget, &verifier, &request)?, Vec::<String>::new());
+
+    // Serve the gadget over streams.
+    let mut input = vec![];
+    prover.write_into(&mut input)?;
+    request.write_into(&mut input)?;
+    let mut output = vec![];
+    serve_gadget_from(gadget, &input[..], &mut output)?;
+
+    let mut reader = Reader::new();
+    reader.read_from(&output[..])?;
+    let messages = Messages::from(&reader);
+    assert_eq!(messages.constraint_systems[0].constraints.len(), 2);
+    assert_eq!(messages.witnesses[0].assigned_variables.values, Some(serialize_small(&[12])));
+    let response = &messages.circuit_headers[0];
+    assert_eq!(response.free_variable_id, 5);
+    assert_eq!(response.instance_variables.variable_ids, vec![4]);
+    assert_eq!(response.instance_variables.values, Some(serialize_small(&[16])));
+
+    // A gadget that breaks the rules.
+    let bad_gadget = |_: &Command, _: &CircuitHeader, builder: &mut GadgetBuilder| -> Result<Variables> {
+        builder.allocate_var();
+        Ok(Variables { variable_ids: vec![1], values: None, info: None })
+    };
+    assert_eq!(check_gadget(bad_gadget, &prover, &request)?, vec![
+        "Output variable_1 was not allocated by the gadget.".to_string(),
+        "The outputs have no values, but witness_generation was requested.".to_string(),
+        "The witness does not assign the local variable_3.".to_string(),
+    ]);
+
+    // A gadget that returns more values than outputs.
+    let wide_gadget = |_: &Command, _: &CircuitHeader, builder: &mut GadgetBuilder| -> Result<Variables> {
+        let out = builder.allocate_var();
+        Ok(Variables { variable_ids: vec![out], values: Some(serialize_small(&[16, 0])), info: None })
+    };
+    assert_eq!(check_gadget(wide_gadget, &prover, &request)?, vec![
+        "The output values do not match the number of outputs.".to_string(),
+    ]);
+
+    Ok(())
+}
@@ -1,6 +1,8 @@
 pub mod examples;
 pub mod builder;
 pub mod gadget_caller;
+pub mod gadget_process;
+pub mod gadget_server;
 pub mod workspace;
 
 pub mod circuit_generator;
@@ -1,8 +1,12 @@
 use std::path::{Path, PathBuf};
 use std::fs::{remove_file, File, create_dir_all, read_dir};
 use std::ffi::OsStr;
-use crate::{Result, CircuitHeader, ConstraintSystem, Witness};
+use std::io::Write;
+use std::ops::Range;
+use crate::{Result, Error, CircuitHeader, ConstraintSystem, BilinearConstraint, Witness, Variables};
 use crate::producers::builder::Sink;
+use crate::consumers::workspace::gadget_responses_dir;
+use crate::structs::variables::NAME_KEY;
 
 pub fn clean_workspace(workspace: impl AsRef<Path>) -> Result<()> {
     let workspace = workspace.as_ref();
@@ -14,26 +18,140 @@ pub fn clean_workspace(workspace: impl AsRef<Path>) -> Result<()> {
             remove_file(f.path())?;
         }
 
+    let responses = gadget_responses_dir(workspace);
+    if responses.is_dir() {
+        clean_workspace(responses)?;
+    }
+
     Ok(())
 }
 
 
+/// Limits on the messages and files written by a `WorkspaceSink`. Zero means no limit.
+///
+/// Constraints and witnesses are re-chunked into messages with at most `max_items` elements
+/// (constraints, or assigned variables), and at most `max_message_bytes` bytes.
+/// Files roll over to a new one when they would exceed `max_file_bytes`.
+/// Without a file limit, each pushed ConstraintSystem goes into a new file, and the witness into a single file.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SplitLimits {
+    pub max_items: usize,
+    pub max_message_bytes: usize,
+    pub max_file_bytes: u64,
+}
+
 /// Store messages into files using conventional filenames inside of a workspace.
+///
+/// As `GadgetCallbacks`, it writes the raw outputs of gadgets into the same files.
+/// Response headers are recorded in `gadget_responses`, and saved into files `responses/response_N.zkif`
+/// which are not read as part of the statement (see `consumers::workspace::read_gadget_responses`).
 pub struct WorkspaceSink {
     pub workspace: PathBuf,
     pub witness_file: Option<File>,
+    pub gadget_responses: Vec<CircuitHeader>,
+    pub limits: SplitLimits,
+    constraints_file: Option<File>,
     cs_file_counter: u32,
+    cs_file_size: u64,
+    witness_file_counter: u32,
+    witness_file_size: u64,
 }
 
 impl WorkspaceSink {
     pub fn new(workspace: impl AsRef<Path>) -> Result<WorkspaceSink> {
+        Self::with_limits(workspace, SplitLimits::default())
+    }
+
+    /// Like `new`, and split constraints and witnesses into messages and files according to `limits`.
+    pub fn with_limits(workspace: impl AsRef<Path>, limits: SplitLimits) -> Result<WorkspaceSink> {
         create_dir_all(workspace.as_ref())?;
         Ok(WorkspaceSink {
             workspace: workspace.as_ref().to_path_buf(),
             witness_file: None,
+            gadget_responses: vec![],
+            limits,
+            constraints_file: None,
             cs_file_counter: 0,
+            cs_file_size: 0,
+            witness_file_counter: 0,
+            witness_file_size: 0,
         })
     }
+
+    /// Write a ConstraintSystem message into the current constraints file, or into the next numbered file.
+    pub(crate) fn write_constraints_message(&mut self, msg: &[u8]) -> Result<()> {
+        if self.constraints_file.is_none() || self.file_is_full(self.cs_file_size, msg.len()) {
+            self.constraints_file = Some(File::create(self.workspace.join(format!("constraints_{}.zkif", &self.cs_file_counter)))?);
+            self.cs_file_counter += 1;
+            self.cs_file_size = 0;
+        }
+        self.constraints_file.as_mut().unwrap().write_all(msg)?;
+        self.cs_file_size += msg.len() as u64;
+        Ok(())
+    }
+
+    /// Write a Witness message into the witness file, or into the next numbered file.
+    pub(crate) fn write_witness_message(&mut self, msg: &[u8]) -> Result<()> {
+        if self.witness_file.is_none() || self.file_is_full(self.witness_file_size, msg.len()) {
+            let name = match self.witness_file_counter {
+                0 => "witness.zkif".to_string(),
+                n => format!("witness_{}.zkif", n),
+            };
+            self.witness_file = Some(File::create(self.workspace.join(name))?);
+            self.witness_file_counter += 1;
+            self.witness_file_size = 0;
+        }
+        self.witness_file.as_mut().unwrap().write_all(msg)?;
+        self.witness_file_size += msg.len() as u64;
+        Ok(())
+    }
+
+    /// Start a new constraints file for each ConstraintSystem, unless files are filled up to a limit.
+    pub(crate) fn start_constraints(&mut self) {
+        if self.limits.max_file_bytes == 0 {
+            self.constraints_file = None;
+        }
+    }
+
+    fn file_is_full(&self, file_size: u64, msg_size: usize) -> bool {
+        self.limits.max_file_bytes > 0 && file_size > 0 && file_size + msg_size as u64 > self.limits.max_file_bytes
+    }
+
+    fn message_is_too_big(&self, msg_size: usize) -> bool {
+        self.limits.max_message_bytes > 0 && msg_size > self.limits.max_message_bytes
+    }
+
+    /// Write constraints as one message, or as smaller messages if it is above the size limit.
+    fn write_constraints_chunk(&mut self, constraints: &[BilinearConstraint]) -> Result<()> {
+        let mut msg = vec![];
+        ConstraintSystem { constraints: constraints.to_vec() }.write_into(&mut msg)?;
+        if self.message_is_too_big(msg.len()) {
+            if constraints.len() <= 1 {
+                return Err(Error::InvalidArgument(format!("A single constraint takes {} bytes, more than the limit of {} bytes per message.",
+                                                msg.len(), self.limits.max_message_bytes)));
+            }
+            let middle = constraints.len() / 2;
+            self.write_constraints_chunk(&constraints[..middle])?;
+            return self.write_constraints_chunk(&constraints[middle..]);
+        }
+        self.write_constraints_message(&msg)
+    }
+
+    /// Write the assignments of a range of variables as one message, or as smaller messages if it is above the size limit.
+    fn write_witness_chunk(&mut self, witness: &Witness, range: Range<usize>) -> Result<()> {
+        let mut msg = vec![];
+        witness_part(witness, range.clone()).write_into(&mut msg)?;
+        if self.message_is_too_big(msg.len()) {
+            if range.len() <= 1 {
+                return Err(Error::InvalidArgument(format!("A single assignment takes {} bytes, more than the limit of {} bytes per message.",
+                                                msg.len(), self.limits.max_message_bytes)));
+            }
+            let middle = range.start + range.len() / 2;
+            self.write_witness_chunk(witness, range.start..middle)?;
+            return self.write_witness_chunk(witness, middle..range.end);
+        }
+        self.write_witness_message(&msg)
+    }
 }
 
 impl Sink for WorkspaceSink {
@@ -44,22 +162,68 @@ impl Sink for WorkspaceSink {
     }
 
     fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
-        let mut constraints_file = File::create(self.workspace.join(format!("constraints_{}.zkif", &self.cs_file_counter)))?;
-        self.cs_file_counter += 1;
-        cs.write_into(&mut constraints_file)
+        self.start_constraints();
+        let sizes = cs.constraints.iter().map(|c|
+            estimated_size(&c.linear_combination_a) + estimated_size(&c.linear_combination_b) + estimated_size(&c.linear_combination_c));
+        for range in chunk_ranges(sizes, &self.limits) {
+            self.write_constraints_chunk(&cs.constraints[range])?;
+        }
+        Ok(())
     }
 
     fn push_witness(&mut self, witness: Witness) -> Result<()> {
-        let file = match self.witness_file {
-            None => {
-                self.witness_file = Some(File::create(
-                    self.workspace.join("witness.zkif"))?);
-                self.witness_file.as_mut().unwrap()
-            }
-            Some(ref mut file) => file,
-        };
+        let vars = &witness.assigned_variables;
+        let stride = vars.value_size();
+        let sizes = vars.variable_ids.iter().map(|_| 8 + stride);
+        for range in chunk_ranges(sizes, &self.limits) {
+            self.write_witness_chunk(&witness, range)?;
+        }
+        Ok(())
+    }
+}
+
+/// Group consecutive items into chunks within the limits, given the estimated size of each item.
+/// There is always at least one chunk, possibly empty.
+fn chunk_ranges(sizes: impl Iterator<Item=usize>, limits: &SplitLimits) -> Vec<Range<usize>> {
+    // Room for the tables and vectors around the items.
+    const MESSAGE_OVERHEAD: usize = 64;
+
+    let mut ranges = vec![];
+    let (mut start, mut end, mut bytes) = (0, 0, MESSAGE_OVERHEAD);
+    for size in sizes {
+        let full_count = limits.max_items > 0 && end - start >= limits.max_items;
+        let full_bytes = limits.max_message_bytes > 0 && end > start && bytes + size > limits.max_message_bytes;
+        if full_count || full_bytes {
+            ranges.push(start..end);
+            start = end;
+            bytes = MESSAGE_OVERHEAD;
+        }
+        end += 1;
+        bytes += size;
+    }
+    ranges.push(start..end);
+    ranges
+}
+
+/// An estimate of the encoded size of a linear combination.
+fn estimated_size(vars: &Variables) -> usize {
+    // Variable IDs, coefficients, and room for the tables, vectors, and padding.
+    vars.variable_ids.len() * 8 + vars.values.as_ref().map_or(0, |v| v.len()) + 64
+}
 
-        witness.write_into(file)
+/// A part of a witness. Names of variables go with their variable, and other info goes with the first part.
+fn witness_part(witness: &Witness, range: Range<usize>) -> Witness {
+    let vars = &witness.assigned_variables;
+    let ids = &vars.variable_ids[range.clone()];
+    let stride = vars.value_size();
+    let values = vars.values.as_ref().map(|values|
+        values[range.start * stride..range.end * stride].to_vec());
+    let info = vars.info.as_ref().map(|info| info.iter().filter(|kv|
+        if kv.key == NAME_KEY { ids.contains(&(kv.number as u64)) } else { range.start == 0 }
+    ).cloned().collect());
+
+    Witness {
+        assigned_variables: Variables { variable_ids: ids.to_vec(), values, info },
     }
 }
 
@@ -90,4 +254,53 @@ fn test_workspace_cleanup() {
     // clean workspace, and check there is no more file in it.
     clean_workspace(&workspace).unwrap();
     assert_eq!(read_dir(&workspace).unwrap().count(), 0);
-}
\ No newline at end of file
+}
+#[test]
+fn test_workspace_limits() -> Result<()> {
+    use std::fs::{remove_dir_all, metadata};
+    use crate::{Workspace, Messages};
+    use crate::producers::examples::*;
+
+    let workspace = PathBuf::from("local/test_workspace_limits");
+    let _ = remove_dir_all(&workspace);
+    let limits = SplitLimits { max_items: 2, max_message_bytes: 400, max_file_bytes: 1000 };
+    let mut sink = WorkspaceSink::with_limits(&workspace, limits)?;
+
+    let mut constraints = example_constraints();
+    for _ in 0..4 { constraints.constraints.extend(example_constraints().constraints); }
+    let mut witness = Witness {
+        assigned_variables: Variables {
+            variable_ids: (10..15).collect(),
+            values: Some(serialize_small(&[1_u32, 2, 3, 4, 5])),
+            info: None,
+        },
+    };
+    witness.assigned_variables.set_name(14, "last");
+    sink.push_header(example_circuit_header())?;
+    sink.push_constraints(constraints.clone())?;
+    sink.push_witness(witness.clone())?;
+    drop(sink);
+
+    // Every message and file is within the limits.
+    let ws = Workspace::from_dir(&workspace)?;
+    let messages = Messages::from(&ws);
+    assert!(messages.constraint_systems.len() >= 8);
+    for cs in &messages.constraint_systems {
+        let mut msg = vec![];
+        cs.write_into(&mut msg)?;
+        assert!(cs.constraints.len() <= 2 && msg.len() <= 400);
+    }
+    for file in read_dir(&workspace)? {
+        assert!(metadata(file?.path())?.len() <= 1000);
+    }
+
+    // The content is the same.
+    let all_constraints: Vec<_> = messages.constraint_systems.iter().flat_map(|cs| cs.constraints.clone()).collect();
+    assert_eq!(all_constraints, constraints.constraints);
+    assert_eq!(messages.witnesses.len(), 3);
+    assert_eq!(messages.witnesses[2].assigned_variables.get_names().get(&14).unwrap(), "last");
+    let all_values: Vec<u8> = messages.witnesses.iter().flat_map(|w| w.assigned_variables.values.clone().unwrap()).collect();
+    assert_eq!(all_values, witness.assigned_variables.values.unwrap());
+
+    Ok(())
+}
@@ -4,9 +4,9 @@ use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
-use crate::Result;
+use crate::consumers::verifier::verify_message;
+use crate::{Result, Error};
 use std::convert::TryFrom;
-use std::error::Error;
 
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
@@ -27,13 +27,14 @@ impl<'a> From<fb::Command<'a>> for Command {
 }
 
 impl<'a> TryFrom<&'a [u8]> for Command {
-    type Error = Box<dyn Error>;
+    type Error = crate::Error;
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
+        verify_message(buffer)?;
         Ok(Self::from(
             fb::get_size_prefixed_root_as_root(&buffer)
                 .message_as_command()
-                .ok_or("Not a Command message.")?))
+                .ok_or_else(|| Error::InvalidMessage("Not a Command message.".into()))?))
     }
 }
 
@@ -1,10 +1,11 @@
 use std::io::Write;
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use serde::{Deserialize, Serialize};
-use crate::{Result, Variables};
+use crate::{Result, Error, Variables, Field};
+use crate::consumers::fingerprint::Fingerprint;
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::verify_message;
 use std::convert::TryFrom;
-use std::error::Error;
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ConstraintSystem {
@@ -25,7 +26,11 @@ impl<'a> From<fb::ConstraintSystem<'a>> for ConstraintSystem {
             constraints: vec![],
         };
 
-        let fb_constraints = fb_cs.constraints().unwrap();
+        // A missing vector of constraints is empty.
+        let fb_constraints = match fb_cs.constraints() {
+            Some(fb_constraints) => fb_constraints,
+            None => return cs,
+        };
         for i in 0..fb_constraints.len() {
             let fb_constraint = fb_constraints.get(i);
             cs.constraints.push(BilinearConstraint {
@@ -40,13 +45,14 @@ impl<'a> From<fb::ConstraintSystem<'a>> for ConstraintSystem {
 }
 
 impl<'a> TryFrom<&'a [u8]> for ConstraintSystem {
-    type Error = Box<dyn Error>;
+    type Error = crate::Error;
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
+        verify_message(buffer)?;
         Ok(Self::from(
             fb::get_size_prefixed_root_as_root(&buffer)
                 .message_as_constraint_system()
-                .ok_or("Not a ConstraintSystem message.")?))
+                .ok_or_else(|| Error::InvalidMessage("Not a ConstraintSystem message.".into()))?))
     }
 }
 
@@ -74,14 +80,17 @@ impl From<&[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))]> fo
             let lca = Variables {
                 variable_ids: lca.0.clone(),
                 values: Some(lca.1.clone()),
+                info: None,
             };
             let lcb = Variables {
                 variable_ids: lcb.0.clone(),
                 values: Some(lcb.1.clone()),
+                info: None,
             };
             let lcc = Variables {
                 variable_ids: lcc.0.clone(),
                 values: Some(lcc.1.clone()),
+                info: None,
             };
             constraints.constraints.push(BilinearConstraint {
                 linear_combination_a: lca,
@@ -94,6 +103,15 @@ impl From<&[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))]> fo
 }
 
 impl BilinearConstraint {
+    /// Normalize the three linear combinations, see `Variables::normalize`.
+    pub fn normalize(&self, field: &Field) -> BilinearConstraint {
+        BilinearConstraint {
+            linear_combination_a: self.linear_combination_a.normalize(field),
+            linear_combination_b: self.linear_combination_b.normalize(field),
+            linear_combination_c: self.linear_combination_c.normalize(field),
+        }
+    }
+
     /// Add this structure into a Flatbuffers message builder.
     pub fn build<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
         &'args self,
@@ -113,6 +131,20 @@ impl BilinearConstraint {
 }
 
 impl ConstraintSystem {
+    /// A stable hash of the constraints in hexadecimal, see `Fingerprint`.
+    pub fn fingerprint(&self) -> String {
+        let mut fingerprint = Fingerprint::default();
+        fingerprint.ingest_constraint_system(self);
+        fingerprint.finish()
+    }
+
+    /// Normalize all linear combinations, see `Variables::normalize`.
+    pub fn normalize(&self, field: &Field) -> ConstraintSystem {
+        ConstraintSystem {
+            constraints: self.constraints.iter().map(|constraint| constraint.normalize(field)).collect(),
+        }
+    }
+
     /// Add this structure into a Flatbuffers message builder.
     pub fn build<'bldr: 'args, 'args: 'mut_bldr, 'mut_bldr>(
         &'args self,
@@ -4,11 +4,11 @@ use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::verify_message;
 use super::variables::Variables;
 use super::keyvalue::KeyValue;
-use crate::Result;
+use crate::{Result, Error};
 use std::convert::TryFrom;
-use std::error::Error;
 use std::collections::HashSet;
 
 
@@ -36,13 +36,14 @@ impl<'a> From<fb::CircuitHeader<'a>> for CircuitHeader {
 }
 
 impl<'a> TryFrom<&'a [u8]> for CircuitHeader {
-    type Error = Box<dyn Error>;
+    type Error = crate::Error;
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
+        verify_message(buffer)?;
         Ok(Self::from(
             fb::get_size_prefixed_root_as_root(&buffer)
                 .message_as_circuit_header()
-                .ok_or("Not a CircuitHeader message.")?))
+                .ok_or_else(|| Error::InvalidMessage("Not a CircuitHeader message.".into()))?))
     }
 }
 
@@ -64,7 +65,7 @@ impl CircuitHeader {
 
     pub fn with_instance_values(mut self, vars: Variables) -> Result<Self> {
         if self.instance_variables.variable_ids != vars.variable_ids {
-            return Err(format!("The provided instance variables do not match.\nGot     : {:?}\nExpected:{:?}", vars.variable_ids, self.instance_variables.variable_ids).into());
+            return Err(Error::InvalidArgument(format!("The provided instance variables do not match.\nGot     : {:?}\nExpected:{:?}", vars.variable_ids, self.instance_variables.variable_ids)));
         }
         self.instance_variables = vars;
         Ok(self)
@@ -78,6 +79,7 @@ impl CircuitHeader {
             instance_variables: Variables {
                 variable_ids: (first_input_id..first_local_id).collect(),
                 values: None,
+                info: None,
             },
             free_variable_id: first_local_id,
             field_maximum: None,
@@ -94,6 +96,7 @@ impl CircuitHeader {
             instance_variables: Variables {
                 variable_ids: (first_output_id..first_local_id).collect(),
                 values: None,
+                info: None,
             },
             free_variable_id: first_local_id + num_locals,
             field_maximum: None,
@@ -152,6 +155,7 @@ fn test_circuit_header() {
         instance_variables: Variables {
             variable_ids: (1..3).collect(),
             values: Some(vec![6, 7]),
+            info: None,
         },
         free_variable_id: 3,
         field_maximum: Some(vec![8]),
@@ -1,6 +1,7 @@
 use serde::{Serialize, Deserialize};
 use crate::zkinterface_generated::zkinterface as fb;
-use crate::{CircuitHeader, ConstraintSystem, Witness, Command};
+use crate::consumers::verifier::verify_message;
+use crate::{CircuitHeader, ConstraintSystem, Witness, Command, Error};
 
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum Message {
@@ -8,11 +9,14 @@ pub enum Message {
     ConstraintSystem(ConstraintSystem),
     Witness(Witness),
     Command(Command),
-    Err(String),
+    Err(Error),
 }
 
 impl<'a> From<&'a [u8]> for Message {
     fn from(buffer: &'a [u8]) -> Self {
+        if let Err(err) = verify_message(buffer) {
+            return Message::Err(err);
+        }
         let msg = fb::get_size_prefixed_root_as_root(&buffer);
 
         match msg.message_type() {
@@ -33,7 +37,7 @@ impl<'a> From<&'a [u8]> for Message {
                 Message::Command(Command::from(fb_command))
             }
             fb::Message::NONE => {
-                Message::Err("Invalid message type".into())
+                Message::Err(Error::InvalidMessage("Invalid message type".into()))
             }
         }
     }
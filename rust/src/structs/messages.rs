@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
-use crate::Workspace;
+use std::io::Write;
+use crate::{Workspace, Sink, Result};
 use crate::consumers::reader::Reader;
 use crate::zkinterface_generated::zkinterface as fb;
 use super::header::CircuitHeader;
@@ -61,4 +62,86 @@ impl From<&Workspace> for Messages {
         }
         messages
     }
-}
\ No newline at end of file
+}
+
+impl Messages {
+    /// Write all messages into a sink: headers, then witnesses, then constraints,
+    /// in the order in which `Workspace` reads them.
+    pub fn push_into(&self, sink: &mut impl Sink) -> Result<()> {
+        for header in &self.circuit_headers {
+            sink.push_header(header.clone())?;
+        }
+        for witness in &self.witnesses {
+            sink.push_witness(witness.clone())?;
+        }
+        for cs in &self.constraint_systems {
+            sink.push_constraints(cs.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Write all messages as Flatbuffers messages into the provided buffer:
+    /// headers, then witnesses, then constraints, so that a stream can be consumed in one pass.
+    pub fn write_into(&self, writer: &mut impl Write) -> Result<()> {
+        for header in &self.circuit_headers {
+            header.write_into(writer)?;
+        }
+        for witness in &self.witnesses {
+            witness.write_into(writer)?;
+        }
+        for cs in &self.constraint_systems {
+            cs.write_into(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for Messages {
+    /// Collect messages in memory.
+    fn push_header(&mut self, header: CircuitHeader) -> Result<()> {
+        self.circuit_headers.push(header);
+        Ok(())
+    }
+
+    fn push_constraints(&mut self, cs: ConstraintSystem) -> Result<()> {
+        self.constraint_systems.push(cs);
+        Ok(())
+    }
+
+    fn push_witness(&mut self, witness: Witness) -> Result<()> {
+        self.witnesses.push(witness);
+        Ok(())
+    }
+}
+
+
+#[test]
+fn test_messages_stream() -> Result<()> {
+    use crate::consumers::workspace::iterate_stream;
+    use crate::consumers::validator::Validator;
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::*;
+
+    // Round-trip through JSON, then to a stream, as `zkif to-json` and `zkif from-json` to stdout.
+    let messages = Messages {
+        circuit_headers: vec![example_circuit_header()],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness()],
+    };
+    let json = serde_json::to_string(&messages)?;
+    let parsed: Messages = serde_json::from_str(&json)?;
+    let mut stream = vec![];
+    parsed.write_into(&mut stream)?;
+
+    // The stream is consumed in one pass, like stdin.
+    let mut validator = Validator::new_as_prover();
+    let mut simulator = Simulator::default();
+    for buffer in iterate_stream(&stream[..]) {
+        let msg = Message::from(&buffer[..]);
+        validator.ingest_message(&msg);
+        simulator.ingest_message(&msg);
+    }
+    assert_eq!(validator.get_violations(), Vec::<String>::new());
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+    Ok(())
+}
@@ -1,14 +1,21 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
 use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use crate::zkinterface_generated::zkinterface as fb;
 use crate::consumers::reader::{Variable, get_value_size};
+use super::keyvalue::KeyValue;
+use crate::{Field, FieldElement};
+
+/// The key of `info` entries that give a human-readable name to a variable.
+/// The name is in `text` and the variable ID is in `number`.
+pub const NAME_KEY: &str = "name";
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Variables {
     pub variable_ids: Vec<u64>,
     pub values: Option<Vec<u8>>,
-    // pub info: Option<Vec<(String, &'a [u8])>>,
+    pub info: Option<Vec<KeyValue>>,
 }
 
 impl<'a> From<fb::Variables<'a>> for Variables {
@@ -23,6 +30,7 @@ impl<'a> From<fb::Variables<'a>> for Variables {
                 Some(bytes) => Some(Vec::from(bytes)),
                 None => None,
             },
+            info: KeyValue::from_vector(fb_variables.info()),
         }
     }
 }
@@ -46,6 +54,64 @@ impl Variables {
             ).collect()
     }
 
+    /// Collect the names of variables found in `info`, by variable ID.
+    /// # Example
+    /// ```
+    /// let mut vars = zkinterface::Variables::default();
+    /// vars.set_name(4, "xx");
+    /// assert_eq!(vars.get_names().get(&4).unwrap(), "xx");
+    /// ```
+    pub fn get_names(&self) -> HashMap<u64, String> {
+        names_in_info(self.info.as_deref().unwrap_or_default())
+    }
+
+    /// Give a human-readable name to a variable in `info`.
+    pub fn set_name(&mut self, id: u64, name: impl ToString) {
+        self.info.get_or_insert_with(Vec::new).push(KeyValue {
+            key: NAME_KEY.to_string(),
+            text: Some(name.to_string()),
+            data: None,
+            number: id as i64,
+        });
+    }
+
+    /// Normalize a linear combination: sort terms by variable ID, merge repeated IDs, drop zero
+    /// coefficients, and encode the coefficients with the smallest width that fits them all.
+    /// # Example
+    /// ```
+    /// use zkinterface::{Field, Variables};
+    ///
+    /// let field = Field::from_maximum(&[100]).unwrap();
+    /// // 2*v5 + 1*v4 + 99*v5 + 0*v6 = v4 (mod 101)
+    /// let lc = Variables {
+    ///     variable_ids: vec![5, 4, 5, 6],
+    ///     values: Some(vec![2, 0, 1, 0, 99, 0, 0, 0]),
+    ///     info: None,
+    /// };
+    /// let normalized = lc.normalize(&field);
+    /// assert_eq!(normalized.variable_ids, vec![4]);
+    /// assert_eq!(normalized.values, Some(vec![1]));
+    /// ```
+    pub fn normalize(&self, field: &Field) -> Variables {
+        let mut terms = BTreeMap::<u64, FieldElement>::new();
+        for term in self.get_variables() {
+            let coeff = field.decode(term.value);
+            let sum = match terms.get(&term.id) {
+                Some(existing) => field.add(existing, &coeff),
+                None => coeff,
+            };
+            terms.insert(term.id, sum);
+        }
+        terms.retain(|_, coeff| !field.is_zero(coeff));
+
+        let width = terms.values().map(|coeff| field.encoded_size(coeff)).max().unwrap_or(0).max(1);
+        Variables {
+            variable_ids: terms.keys().cloned().collect(),
+            values: Some(terms.values().flat_map(|coeff| field.encode_with_width(coeff, width)).collect()),
+            info: self.info.clone(),
+        }
+    }
+
     pub fn value_size(&self) -> usize {
         match self.values {
             Some(ref values) => get_value_size(&self.variable_ids, values),
@@ -64,10 +130,24 @@ impl Variables {
         let values = self.values.as_ref().map(|values|
             builder.create_vector(values));
 
+        let info = self.info.as_ref().map(|info|
+            KeyValue::build_vector(info, builder));
+
         fb::Variables::create(builder, &fb::VariablesArgs {
             variable_ids,
             values,
-            info: None,
+            info,
         })
     }
 }
+
+/// Collect the names of variables found in `info` entries with the key `NAME_KEY`.
+pub fn names_in_info(info: &[KeyValue]) -> HashMap<u64, String> {
+    let mut names = HashMap::new();
+    for kv in info {
+        if let (NAME_KEY, Some(name)) = (kv.key.as_str(), &kv.text) {
+            names.insert(kv.number as u64, name.clone());
+        }
+    }
+    names
+}
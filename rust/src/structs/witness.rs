@@ -2,10 +2,10 @@ use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use std::io::Write;
 use serde::{Deserialize, Serialize};
 use crate::zkinterface_generated::zkinterface as fb;
+use crate::consumers::verifier::verify_message;
 use super::variables::Variables;
-use crate::Result;
+use crate::{Result, Error};
 use std::convert::TryFrom;
-use std::error::Error;
 
 #[derive(Clone, Default, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct Witness {
@@ -22,13 +22,14 @@ impl<'a> From<fb::Witness<'a>> for Witness {
 }
 
 impl<'a> TryFrom<&'a [u8]> for Witness {
-    type Error = Box<dyn Error>;
+    type Error = crate::Error;
 
     fn try_from(buffer: &'a [u8]) -> Result<Self> {
+        verify_message(buffer)?;
         Ok(Self::from(
             fb::get_size_prefixed_root_as_root(&buffer)
                 .message_as_witness()
-                .ok_or("Not a Witness message.")?))
+                .ok_or_else(|| Error::InvalidMessage("Not a Witness message.".into()))?))
     }
 }
 
@@ -0,0 +1,322 @@
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, BilinearConstraint, Variables, Messages, Field, StatementBuilder, Sink};
+
+use std::collections::HashMap;
+
+type Var = u64;
+
+/// A variable of one of the linked statements: the index of the statement, and the variable ID in it.
+pub type PartVar = (usize, Var);
+
+/// Linker composes several statements into one.
+///
+/// The variables of each statement are moved into a fresh ID space, allocated in order with a `StatementBuilder`.
+/// Instance variables of different statements can be connected, either with an equality constraint
+/// `a * 1 = b`, or by substitution where both become the same variable.
+/// The combined header lists the instance variables of all statements, connected ones only once
+/// with substitution.
+///
+/// # Example
+/// ```
+/// use zkinterface::transformations::linker::Linker;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::Messages;
+///
+/// let example = Messages {
+///     circuit_headers: vec![example_circuit_header()],
+///     constraint_systems: vec![example_constraints()],
+///     witnesses: vec![example_witness()],
+/// };
+///
+/// // Two copies of the example with the same input x (variable 1).
+/// let mut linker = Linker::new_substitution();
+/// let first = linker.add_statement(&example).unwrap();
+/// let second = linker.add_statement(&example).unwrap();
+/// linker.connect((first, 1), (second, 1)).unwrap();
+///
+/// let mut linked = Messages::default();
+/// linker.emit(&mut linked).unwrap();
+/// assert_eq!(linked.circuit_headers[0].instance_variables.variable_ids, vec![1, 2, 3, 6, 7]);
+/// assert_eq!(linked.circuit_headers[0].free_variable_id, 10);
+/// ```
+#[derive(Clone, Default)]
+pub struct Linker {
+    substitution: bool,
+    field: Option<Field>,
+    parts: Vec<Messages>,
+    connections: Vec<(PartVar, PartVar)>,
+}
+
+impl Linker {
+    /// Connect variables with equality constraints.
+    pub fn new_equality() -> Linker {
+        Linker::default()
+    }
+
+    /// Connect variables by substitution.
+    pub fn new_substitution() -> Linker {
+        Linker { substitution: true, ..Self::default() }
+    }
+
+    /// Add a statement with exactly one header, and return its index.
+    pub fn add_statement(&mut self, statement: &Messages) -> Result<usize> {
+        if statement.circuit_headers.len() != 1 {
+            return Err(Error::MissingHeader);
+        }
+        let header = &statement.circuit_headers[0];
+        let field = Field::from_header(header)?;
+        match &self.field {
+            Some(first) if first.modulus() != field.modulus() => {
+                return Err(Error::Field("All linked statements must use the same field.".into()));
+            }
+            _ => self.field = Some(field),
+        }
+
+        // All variables must be within free_variable_id, which is the size of the ID space to allocate.
+        let in_bound = |vars: &Variables| vars.variable_ids.iter().all(|&id| id < header.free_variable_id);
+        if !in_bound(&header.instance_variables)
+            || !statement.witnesses.iter().all(|w| in_bound(&w.assigned_variables))
+            || !statement.constraint_systems.iter().flat_map(|cs| &cs.constraints).all(|c|
+            in_bound(&c.linear_combination_a) && in_bound(&c.linear_combination_b) && in_bound(&c.linear_combination_c)) {
+            return Err(Error::InvalidMessage(format!(
+                "Statement {} uses variables beyond its free_variable_id {}.", self.parts.len(), header.free_variable_id)));
+        }
+
+        self.parts.push(statement.clone());
+        Ok(self.parts.len() - 1)
+    }
+
+    /// Connect an instance variable of a statement to an instance variable of another statement.
+    pub fn connect(&mut self, a: PartVar, b: PartVar) -> Result<()> {
+        for &(part, id) in &[a, b] {
+            let header = self.parts.get(part)
+                .ok_or_else(|| Error::InvalidArgument(format!("No statement {} to connect.", part)))?
+                .circuit_headers[0].clone();
+            if !header.instance_variables.variable_ids.contains(&id) {
+                return Err(Error::InvalidArgument(format!("variable_{} is not an instance variable of statement {}.", id, part)));
+            }
+        }
+        self.connections.push((a, b));
+        Ok(())
+    }
+
+    /// Link the statements, and write the combined header, constraints, and witnesses (if any) into a sink.
+    pub fn emit(&self, sink: &mut impl Sink) -> Result<()> {
+        let field = self.field.as_ref().ok_or(Error::MissingHeader)?;
+
+        // With substitution, each variable is replaced by the first one it is connected to.
+        let mut representative = HashMap::<PartVar, PartVar>::new();
+        if self.substitution {
+            for &(a, b) in &self.connections {
+                let (ra, rb) = (find(&representative, a), find(&representative, b));
+                if ra != rb {
+                    representative.insert(ra.max(rb), ra.min(rb));
+                }
+            }
+        }
+
+        // Allocate new IDs, in order of statements and variables.
+        let mut builder = StatementBuilder::new(Messages::default());
+        let mut maps: Vec<HashMap<Var, Var>> = vec![];
+        for (part, statement) in self.parts.iter().enumerate() {
+            let mut map = HashMap::new();
+            map.insert(0, 0);
+            for id in 1..statement.circuit_headers[0].free_variable_id {
+                let rep = find(&representative, (part, id));
+                let new_id = if rep == (part, id) {
+                    builder.allocate_var()
+                } else if rep.0 == part {
+                    // Connected to a previous variable of the same statement.
+                    map[&rep.1]
+                } else {
+                    maps[rep.0][&rep.1]
+                };
+                map.insert(id, new_id);
+            }
+            maps.push(map);
+        }
+
+        // The combined instance variables, with values padded to a common size.
+        let headers: Vec<&CircuitHeader> = self.parts.iter().map(|p| &p.circuit_headers[0]).collect();
+        let with_values = headers.iter().filter(|h| has_values(&h.instance_variables)).count();
+        if with_values != 0 && with_values != headers.len() {
+            return Err(Error::InvalidArgument("Either all or none of the linked statements must have instance values.".into()));
+        }
+        let value_size = headers.iter().map(|h| h.instance_variables.value_size()).max().unwrap_or(0);
+
+        let mut instance_ids = vec![];
+        let mut instance_values = HashMap::<Var, Vec<u8>>::new();
+        for (part, header) in headers.iter().enumerate() {
+            for var in header.instance_variables.get_variables() {
+                let new_id = maps[part][&var.id];
+                let mut value = var.value.to_vec();
+                value.resize(value_size, 0);
+                match instance_values.get(&new_id) {
+                    None => {
+                        instance_ids.push(new_id);
+                        instance_values.insert(new_id, value);
+                    }
+                    Some(existing) if *existing != value => {
+                        return Err(Error::Unsatisfied(format!("The connected instance variable_{} of statement {} has a different value.", var.id, part)));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        let mut header = CircuitHeader {
+            instance_variables: Variables {
+                variable_ids: instance_ids.clone(),
+                values: if with_values > 0 {
+                    Some(instance_ids.iter().flat_map(|id| instance_values[id].clone()).collect())
+                } else { None },
+                info: None,
+            },
+            free_variable_id: builder.header.free_variable_id,
+            field_maximum: Some(field.encoded_maximum()),
+            configuration: None,
+        };
+        for (part, header_part) in headers.iter().enumerate() {
+            for (id, name) in header_part.instance_variables.get_names() {
+                header.instance_variables.set_name(maps[part][&id], name);
+            }
+        }
+        sink.push_header(header)?;
+
+        for (part, statement) in self.parts.iter().enumerate() {
+            for cs in &statement.constraint_systems {
+                sink.push_constraints(ConstraintSystem {
+                    constraints: cs.constraints.iter().map(|c| BilinearConstraint {
+                        linear_combination_a: remap(&c.linear_combination_a, &maps[part]),
+                        linear_combination_b: remap(&c.linear_combination_b, &maps[part]),
+                        linear_combination_c: remap(&c.linear_combination_c, &maps[part]),
+                    }).collect(),
+                })?;
+            }
+        }
+
+        if !self.substitution && !self.connections.is_empty() {
+            let one = Variables { variable_ids: vec![0], values: Some(vec![1]), info: None };
+            let var = |(part, id): PartVar| Variables { variable_ids: vec![maps[part][&id]], values: Some(vec![1]), info: None };
+            sink.push_constraints(ConstraintSystem {
+                constraints: self.connections.iter().map(|&(a, b)| BilinearConstraint {
+                    linear_combination_a: var(a),
+                    linear_combination_b: one.clone(),
+                    linear_combination_c: var(b),
+                }).collect(),
+            })?;
+        }
+
+        for (part, statement) in self.parts.iter().enumerate() {
+            for witness in &statement.witnesses {
+                sink.push_witness(Witness {
+                    assigned_variables: remap(&witness.assigned_variables, &maps[part]),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn find(representative: &HashMap<PartVar, PartVar>, mut var: PartVar) -> PartVar {
+    while let Some(&rep) = representative.get(&var) {
+        var = rep;
+    }
+    var
+}
+
+fn has_values(vars: &Variables) -> bool {
+    vars.values.as_ref().is_some_and(|values| !values.is_empty())
+}
+
+/// Rename the variables, and their names in `info`.
+fn remap(vars: &Variables, map: &HashMap<Var, Var>) -> Variables {
+    let mut remapped = Variables {
+        variable_ids: vars.variable_ids.iter().map(|id| map[id]).collect(),
+        values: vars.values.clone(),
+        info: None,
+    };
+    for (id, name) in vars.get_names() {
+        if let Some(&new_id) = map.get(&id) {
+            remapped.set_name(new_id, name);
+        }
+    }
+    remapped
+}
+
+
+#[test]
+fn test_linker() -> Result<()> {
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::*;
+
+    // The example computes z = x^2 + y^2. Link two copies where z of the first is x of the second.
+    let header_inputs = |values: &[u32]| CircuitHeader {
+        instance_variables: Variables { variable_ids: vec![1, 2, 3], values: Some(serialize_small(values)), info: None },
+        ..example_circuit_header()
+    };
+    let first = Messages {
+        circuit_headers: vec![header_inputs(&[3, 4, 25])],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness_inputs(3, 4)],
+    };
+    let second = Messages {
+        circuit_headers: vec![header_inputs(&[25, 1, 626])],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness_inputs(25, 1)],
+    };
+
+    for &substitution in &[false, true] {
+        let mut linker = if substitution { Linker::new_substitution() } else { Linker::new_equality() };
+        let a = linker.add_statement(&first)?;
+        let b = linker.add_statement(&second)?;
+        linker.connect((a, 3), (b, 1))?;
+        assert!(linker.connect((a, 4), (b, 1)).is_err());
+
+        let mut linked = Messages::default();
+        linker.emit(&mut linked)?;
+
+        let header = &linked.circuit_headers[0];
+        if substitution {
+            assert_eq!(header.instance_variables.variable_ids, vec![1, 2, 3, 6, 7]);
+            assert_eq!(header.free_variable_id, 10);
+        } else {
+            assert_eq!(header.instance_variables.variable_ids, vec![1, 2, 3, 6, 7, 8]);
+            assert_eq!(header.free_variable_id, 11);
+            assert_eq!(linked.constraint_systems.len(), 3);
+        }
+
+        let mut simulator = Simulator::default();
+        linked.circuit_headers.iter().try_for_each(|h| simulator.ingest_header(h))?;
+        linked.witnesses.iter().try_for_each(|w| simulator.ingest_witness(w))?;
+        linked.constraint_systems.iter().try_for_each(|cs| simulator.ingest_constraint_system(cs))?;
+    }
+
+    // Different values cannot be substituted.
+    let mut linker = Linker::new_substitution();
+    linker.add_statement(&first)?;
+    linker.add_statement(&second)?;
+    linker.connect((0, 1), (1, 1))?;
+    assert!(linker.emit(&mut Messages::default()).is_err());
+
+    // Two variables of the same statement, where x = y.
+    let same = Messages {
+        circuit_headers: vec![header_inputs(&[3, 3, 18])],
+        constraint_systems: vec![example_constraints()],
+        witnesses: vec![example_witness_inputs(3, 3)],
+    };
+    let mut linker = Linker::new_substitution();
+    let a = linker.add_statement(&same)?;
+    linker.connect((a, 2), (a, 1))?;
+
+    let mut linked = Messages::default();
+    linker.emit(&mut linked)?;
+    assert_eq!(linked.circuit_headers[0].instance_variables.variable_ids, vec![1, 2]);
+
+    let mut simulator = Simulator::default();
+    linked.circuit_headers.iter().try_for_each(|h| simulator.ingest_header(h))?;
+    linked.witnesses.iter().try_for_each(|w| simulator.ingest_witness(w))?;
+    linked.constraint_systems.iter().try_for_each(|cs| simulator.ingest_constraint_system(cs))?;
+
+    Ok(())
+}
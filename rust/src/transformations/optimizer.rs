@@ -0,0 +1,334 @@
+use crate::{Result, Error, CircuitHeader, Witness, ConstraintSystem, BilinearConstraint, Variables, Message, Field, FieldElement, Sink};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+type Var = u64;
+
+/// A linear combination, by variable ID. Zero coefficients are omitted.
+type Lc = BTreeMap<Var, FieldElement>;
+
+/// Optimizer removes linear constraints from a statement.
+///
+/// A constraint is linear when A or B is a multiple of the constant one (variable 0).
+/// Such a constraint defines one of its witness variables in terms of the others.
+/// That variable is substituted in all other constraints, and the constraint is removed.
+/// The remaining witness variables are then renumbered to be contiguous,
+/// and `free_variable_id` and the witness are updated to match.
+///
+/// # Example
+/// ```
+/// use zkinterface::transformations::optimizer::Optimizer;
+/// use zkinterface::producers::examples::*;
+/// use zkinterface::Messages;
+///
+/// let mut optimizer = Optimizer::default();
+/// optimizer.ingest_header(&example_circuit_header()).unwrap();
+/// optimizer.ingest_witness(&example_witness()).unwrap();
+/// optimizer.ingest_constraint_system(&example_constraints()).unwrap();
+///
+/// let mut optimized = Messages::default();
+/// optimizer.emit(&mut optimized).unwrap();
+///
+/// // The linear constraint 1 * (xx + yy) = z is removed, and yy is replaced by z - xx.
+/// assert_eq!(optimized.constraint_systems[0].constraints.len(), 2);
+/// assert_eq!(optimized.circuit_headers[0].free_variable_id, 5);
+/// ```
+#[derive(Clone, Default)]
+pub struct Optimizer {
+    header: Option<CircuitHeader>,
+    field: Option<Field>,
+    witnesses: Vec<Witness>,
+    constraints: Vec<BilinearConstraint>,
+}
+
+impl Optimizer {
+    pub fn ingest_message(&mut self, msg: &Message) -> Result<()> {
+        match msg {
+            Message::Header(h) => self.ingest_header(h)?,
+            Message::ConstraintSystem(cs) => self.ingest_constraint_system(cs)?,
+            Message::Witness(w) => self.ingest_witness(w)?,
+            Message::Command(_) => {}
+            Message::Err(err) => return Err(err.clone()),
+        }
+        Ok(())
+    }
+
+    pub fn ingest_header(&mut self, header: &CircuitHeader) -> Result<()> {
+        if self.header.is_some() {
+            return Err(Error::MultipleHeaders);
+        }
+        self.field = Some(Field::from_header(header)?);
+        self.header = Some(header.clone());
+        Ok(())
+    }
+
+    pub fn ingest_witness(&mut self, witness: &Witness) -> Result<()> {
+        self.ensure_header()?;
+        self.witnesses.push(witness.clone());
+        Ok(())
+    }
+
+    pub fn ingest_constraint_system(&mut self, system: &ConstraintSystem) -> Result<()> {
+        self.ensure_header()?;
+        self.constraints.extend(system.constraints.iter().cloned());
+        Ok(())
+    }
+
+    /// Optimize the statement and write the header, constraints, and witness (if any) into a sink.
+    pub fn emit(&self, sink: &mut impl Sink) -> Result<()> {
+        let field = self.field.as_ref().ok_or(Error::MissingHeader)?;
+        let header = self.header.as_ref().unwrap();
+        let instance_ids: HashSet<Var> = header.instance_variables.variable_ids.iter().cloned().collect();
+        let can_eliminate = |id: Var| id != 0 && !instance_ids.contains(&id);
+
+        // Eliminate a variable from each linear constraint, in order.
+        let mut subst = Substitutions::default();
+        let mut kept = vec![];
+
+        for constraint in &self.constraints {
+            let linear_form = match linear_form(field, constraint) {
+                Some(linear_form) => subst.apply(field, &linear_form),
+                None => {
+                    kept.push(constraint);
+                    continue;
+                }
+            };
+
+            // Prefer the latest variable, which is usually the one that the constraint defines.
+            match linear_form.keys().rev().find(|id| can_eliminate(**id)) {
+                Some(&pivot) => {
+                    // pivot = -(1 / coeff) * (the other terms)
+                    let inverse = field.inverse(&linear_form[&pivot]).ok_or_else(|| Error::Field(format!(
+                        "The coefficient of variable_{} has no inverse, the field order is not prime.", pivot)))?;
+                    let factor = field.neg(&inverse);
+                    let definition = linear_form.iter()
+                        .filter(|(id, _)| **id != pivot)
+                        .map(|(id, coeff)| (*id, field.mul(&factor, coeff)))
+                        .collect();
+                    subst.eliminate(field, pivot, definition);
+                }
+                None => {
+                    // A trivial constraint 0 = 0 can be dropped, otherwise it is kept.
+                    if !linear_form.is_empty() {
+                        kept.push(constraint);
+                    }
+                }
+            }
+        }
+
+        // Renumber the remaining witness variables contiguously, after the instance variables.
+        let mut used_ids: HashSet<Var> = header.list_witness_ids().into_iter().collect();
+        for constraint in &self.constraints {
+            for lc in &[&constraint.linear_combination_a, &constraint.linear_combination_b, &constraint.linear_combination_c] {
+                used_ids.extend(lc.variable_ids.iter().cloned());
+            }
+        }
+        let mut witness_ids: Vec<Var> = used_ids.into_iter()
+            .filter(|id| can_eliminate(*id) && !subst.defs.contains_key(id))
+            .collect();
+        witness_ids.sort_unstable();
+
+        let mut renumbering: HashMap<Var, Var> = HashMap::new();
+        let mut next_id = 1;
+        for id in witness_ids {
+            while instance_ids.contains(&next_id) { next_id += 1; }
+            renumbering.insert(id, next_id);
+            next_id += 1;
+        }
+        let free_variable_id = instance_ids.iter().map(|id| id + 1).chain(Some(next_id)).max().unwrap();
+        let renumber = |id: &Var| *renumbering.get(id).unwrap_or(id);
+
+        // Write the optimized statement.
+        sink.push_header(CircuitHeader { free_variable_id, ..header.clone() })?;
+
+        let encode_lc = |lc: &Variables| -> Variables {
+            let lc = subst.apply(field, &decode_lc(field, lc));
+            let coeffs: Vec<FieldElement> = lc.values().cloned().collect();
+            Variables {
+                variable_ids: lc.keys().map(renumber).collect(),
+                values: Some(field.encode_all(&coeffs)),
+                info: None,
+            }
+        };
+        let constraints = kept.iter().map(|constraint| BilinearConstraint {
+            linear_combination_a: encode_lc(&constraint.linear_combination_a),
+            linear_combination_b: encode_lc(&constraint.linear_combination_b),
+            linear_combination_c: encode_lc(&constraint.linear_combination_c),
+        }).collect();
+        sink.push_constraints(ConstraintSystem { constraints })?;
+
+        for witness in &self.witnesses {
+            let assigned = &witness.assigned_variables;
+            let mut variables = Variables::default();
+            let mut values = vec![];
+            for var in assigned.get_variables() {
+                if subst.defs.contains_key(&var.id) { continue; }
+                variables.variable_ids.push(renumber(&var.id));
+                values.extend_from_slice(var.value);
+            }
+            variables.values = Some(values);
+            for (id, name) in assigned.get_names() {
+                if !subst.defs.contains_key(&id) {
+                    variables.set_name(renumber(&id), name);
+                }
+            }
+            sink.push_witness(Witness { assigned_variables: variables })?;
+        }
+
+        Ok(())
+    }
+
+    fn ensure_header(&self) -> Result<()> {
+        match self.header {
+            Some(_) => Ok(()),
+            None => Err(Error::MissingHeader),
+        }
+    }
+}
+
+/// Definitions of eliminated variables, always in terms of variables that are not eliminated.
+#[derive(Default)]
+struct Substitutions {
+    defs: HashMap<Var, Lc>,
+    /// The eliminated variables whose definition may refer to a variable.
+    users: HashMap<Var, Vec<Var>>,
+}
+
+impl Substitutions {
+    fn eliminate(&mut self, field: &Field, var: Var, definition: Lc) {
+        // Update the definitions that refer to this variable.
+        for user in self.users.remove(&var).unwrap_or_default() {
+            let user_def = self.defs.get_mut(&user).unwrap();
+            if let Some(coeff) = user_def.remove(&var) {
+                for (id, c) in &definition {
+                    add_term(field, user_def, *id, &field.mul(&coeff, c));
+                    self.users.entry(*id).or_default().push(user);
+                }
+            }
+        }
+        for id in definition.keys() {
+            self.users.entry(*id).or_default().push(var);
+        }
+        self.defs.insert(var, definition);
+    }
+
+    fn apply(&self, field: &Field, lc: &Lc) -> Lc {
+        let mut result = Lc::new();
+        for (id, coeff) in lc {
+            match self.defs.get(id) {
+                Some(definition) => for (def_id, c) in definition {
+                    add_term(field, &mut result, *def_id, &field.mul(coeff, c));
+                },
+                None => add_term(field, &mut result, *id, coeff),
+            }
+        }
+        result
+    }
+}
+
+/// If the constraint is linear, return the linear combination that it sets to zero.
+fn linear_form(field: &Field, constraint: &BilinearConstraint) -> Option<Lc> {
+    let a = decode_lc(field, &constraint.linear_combination_a);
+    let b = decode_lc(field, &constraint.linear_combination_b);
+    let c = decode_lc(field, &constraint.linear_combination_c);
+
+    // k * L = C becomes k * L - C = 0.
+    let (k, l) = match (as_constant(&a), as_constant(&b)) {
+        (Some(k), _) => (k, b),
+        (_, Some(k)) => (k, a),
+        _ => return None,
+    };
+    let mut form = Lc::new();
+    for (id, coeff) in &l {
+        add_term(field, &mut form, *id, &field.mul(k, coeff));
+    }
+    for (id, coeff) in &c {
+        add_term(field, &mut form, *id, &field.neg(coeff));
+    }
+    Some(form)
+}
+
+/// The coefficient of a linear combination made only of the constant one, if any.
+fn as_constant(lc: &Lc) -> Option<&FieldElement> {
+    match (lc.len(), lc.get(&0)) {
+        (1, Some(k)) => Some(k),
+        _ => None,
+    }
+}
+
+fn decode_lc(field: &Field, lc: &Variables) -> Lc {
+    let mut result = Lc::new();
+    for term in lc.get_variables() {
+        add_term(field, &mut result, term.id, &field.decode(term.value));
+    }
+    result
+}
+
+fn add_term(field: &Field, lc: &mut Lc, id: Var, coeff: &FieldElement) {
+    let sum = match lc.get(&id) {
+        Some(existing) => field.add(existing, coeff),
+        None => coeff.clone(),
+    };
+    if field.is_zero(&sum) {
+        lc.remove(&id);
+    } else {
+        lc.insert(id, sum);
+    }
+}
+
+
+#[test]
+fn test_optimizer() -> Result<()> {
+    use crate::Messages;
+    use crate::consumers::simulator::Simulator;
+    use crate::producers::examples::*;
+
+    // A chain of linear constraints: w = xx + yy, then zz = 1 * w.
+    let mut cs = example_constraints();
+    cs.constraints[2].linear_combination_c.variable_ids = vec![6];
+    cs.constraints.push(BilinearConstraint {
+        linear_combination_a: Variables { variable_ids: vec![0], values: Some(vec![1]), info: None },
+        linear_combination_b: Variables { variable_ids: vec![6], values: Some(vec![1]), info: None },
+        linear_combination_c: Variables { variable_ids: vec![3], values: Some(vec![1]), info: None },
+    });
+    let header = CircuitHeader { free_variable_id: 7, ..example_circuit_header() };
+    let mut witness = example_witness();
+    witness.assigned_variables.variable_ids.push(6);
+    witness.assigned_variables.values.as_mut().unwrap().extend_from_slice(&[25, 0, 0, 0]);
+    witness.assigned_variables.set_name(4, "xx");
+
+    let mut optimizer = Optimizer::default();
+    optimizer.ingest_header(&header)?;
+    optimizer.ingest_witness(&witness)?;
+    optimizer.ingest_constraint_system(&cs)?;
+    let mut optimized = Messages::default();
+    optimizer.emit(&mut optimized)?;
+
+    // Both linear constraints are gone, and only xx remains as a witness variable.
+    assert_eq!(optimized.circuit_headers[0].free_variable_id, 5);
+    assert_eq!(optimized.constraint_systems[0].constraints.len(), 2);
+    let assigned = &optimized.witnesses[0].assigned_variables;
+    assert_eq!(assigned.variable_ids, vec![4]);
+    assert_eq!(assigned.get_names().get(&4).unwrap(), "xx");
+
+    // The optimized statement is still true.
+    let mut simulator = Simulator::default();
+    simulator.ingest_header(&optimized.circuit_headers[0])?;
+    simulator.ingest_witness(&optimized.witnesses[0])?;
+    simulator.ingest_constraint_system(&optimized.constraint_systems[0])?;
+    assert_eq!(simulator.get_violations(), Vec::<String>::new());
+
+    // In the ring of integers modulo 9, 3 * xx = 0 cannot be solved for xx.
+    let constraints: &[((Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>), (Vec<u64>, Vec<u8>))] = &[
+        ((vec![0], vec![1]), (vec![4], vec![3]), (vec![], vec![])),
+    ];
+    let mut optimizer = Optimizer::default();
+    optimizer.ingest_header(&CircuitHeader { field_maximum: Some(vec![8]), ..example_circuit_header() })?;
+    optimizer.ingest_constraint_system(&ConstraintSystem::from(constraints))?;
+    match optimizer.emit(&mut Messages::default()) {
+        Err(Error::Field(_)) => {}
+        other => panic!("Expected a field error, got {:?}", other),
+    }
+
+    Ok(())
+}